@@ -1,15 +1,63 @@
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 pub mod utils;
 
-use sabidb::rdbc::{driver_adapter::DriverAdapter, embedded::embedded_driver::EmbeddedDriver};
+use sabidb::{
+    buffer::replacement_policy::ReplacementPolicyKind,
+    file::durability_mode::DurabilityMode,
+    rdbc::embedded::embedded_driver::EmbeddedDriver,
+    server::{
+        db_options::{QueryPlannerKind, UpdatePlannerKind},
+        log_level::LogLevel,
+        simple_db::SimpleDB,
+    },
+    tx::concurrency::deadlock_policy::DeadlockPolicy,
+};
 
 use clap::Parser;
 
+// Flags override the options in the database directory's sabidb.conf.
 #[derive(Debug, Parser)]
 struct Args {
     #[arg(help = "dbname", short)]
     dbname: Option<String>,
+    #[arg(long, help = "block size in bytes; must match the existing database")]
+    block_size: Option<i32>,
+    #[arg(long, help = "number of buffers in the buffer pool")]
+    buffers: Option<i32>,
+    #[arg(long, help = "buffer replacement policy: lru, clock or lru-k")]
+    replacement_policy: Option<ReplacementPolicyKind>,
+    #[arg(long, help = "blocks to read ahead during sequential scans; 0 disables")]
+    read_ahead: Option<i32>,
+    #[arg(long, help = "query planner: basic or heuristic")]
+    query_planner: Option<QueryPlannerKind>,
+    #[arg(long, help = "update planner: basic or index")]
+    update_planner: Option<UpdatePlannerKind>,
+    #[arg(long, help = "milliseconds to wait for a lock")]
+    lock_timeout: Option<i64>,
+    #[arg(long, help = "deadlock policy: detect, wait-die or wound-wait")]
+    deadlock_policy: Option<DeadlockPolicy>,
+    #[arg(long, help = "block and record locks in one table before the whole table is locked; 0 disables")]
+    lock_escalation_blocks: Option<i32>,
+    #[arg(long, help = "milliseconds to wait for a free buffer")]
+    pin_timeout: Option<i64>,
+    #[arg(long, help = "durability: full, group or none")]
+    durability: Option<DurabilityMode>,
+    #[arg(long, help = "log level: quiet, info or debug")]
+    log_level: Option<LogLevel>,
+    #[arg(long, help = "milliseconds between background flushes of dirty buffers; 0 disables")]
+    writer_interval: Option<i64>,
+    #[arg(long, help = "milliseconds between background checkpoints; 0 disables")]
+    checkpoint_interval: Option<i64>,
+    #[arg(long, help = "microseconds a commit waits for others to share its log flush")]
+    group_commit_window: Option<i64>,
+    #[arg(long, help = "blocks per log segment")]
+    log_segment_blocks: Option<i32>,
+    #[arg(long, help = "directory to move old log segments to instead of deleting them")]
+    log_archive_dir: Option<PathBuf>,
 }
 
 fn main() {
@@ -23,7 +71,73 @@ fn main() {
         }
     );
     let dbpath = Path::new(&dbpath);
-    let mut drvr = EmbeddedDriver::connect(dbpath);
+
+    let mut builder = match SimpleDB::builder(dbpath).config_file() {
+        Ok(builder) => builder,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1)
+        }
+    };
+    if let Some(blocksize) = args.block_size {
+        builder = builder.block_size(blocksize);
+    }
+    if let Some(buffers) = args.buffers {
+        builder = builder.buffer_size(buffers);
+    }
+    if let Some(kind) = args.replacement_policy {
+        builder = builder.replacement_policy(kind);
+    }
+    if let Some(blocks) = args.read_ahead {
+        builder = builder.read_ahead_blocks(blocks);
+    }
+    if let Some(kind) = args.query_planner {
+        builder = builder.query_planner(kind);
+    }
+    if let Some(kind) = args.update_planner {
+        builder = builder.update_planner(kind);
+    }
+    if let Some(ms) = args.lock_timeout {
+        builder = builder.lock_timeout_ms(ms);
+    }
+    if let Some(policy) = args.deadlock_policy {
+        builder = builder.deadlock_policy(policy);
+    }
+    if let Some(blocks) = args.lock_escalation_blocks {
+        builder = builder.lock_escalation_blocks(blocks);
+    }
+    if let Some(ms) = args.pin_timeout {
+        builder = builder.pin_timeout_ms(ms);
+    }
+    if let Some(durability) = args.durability {
+        builder = builder.durability(durability);
+    }
+    if let Some(level) = args.log_level {
+        builder = builder.log_level(level);
+    }
+    if let Some(ms) = args.writer_interval {
+        builder = builder.writer_interval_ms(ms);
+    }
+    if let Some(ms) = args.checkpoint_interval {
+        builder = builder.checkpoint_interval_ms(ms);
+    }
+    if let Some(us) = args.group_commit_window {
+        builder = builder.group_commit_window_us(us);
+    }
+    if let Some(blocks) = args.log_segment_blocks {
+        builder = builder.log_segment_blocks(blocks);
+    }
+    if let Some(dir) = args.log_archive_dir {
+        builder = builder.log_archive_dir(&dir);
+    }
+
+    let mut drvr = match EmbeddedDriver::connect_with(builder) {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("failed to open {}: {}", dbpath.display(), e);
+            exit(1)
+        }
+    };
 
     while let Ok(qry) = utils::read_query() {
         utils::exec(&mut drvr, &qry);
@@ -12,8 +12,11 @@ pub struct Buffer {
     contents: Page,
     blk: Option<BlockId>,
     pins: i32,
-    txnum: i32,
-    lsn: i32,
+    // the transactions with changes in the page that are not on disk yet; with
+    // record locks more than one can change a page at a time
+    txnums: Vec<i64>,
+    // the page LSN: the log record of the latest logged change in the page
+    lsn: i64,
 }
 
 impl Buffer {
@@ -27,7 +30,7 @@ impl Buffer {
             contents: page,
             blk: None,
             pins: 0,
-            txnum: -1,
+            txnums: Vec::new(),
             lsn: -1,
         })
     }
@@ -40,8 +43,10 @@ impl Buffer {
         self.blk.clone()
     }
 
-    pub fn set_modified(&mut self, txnum: i32, lsn: i32) {
-        self.txnum = txnum;
+    pub fn set_modified(&mut self, txnum: i64, lsn: i64) {
+        if !self.txnums.contains(&txnum) {
+            self.txnums.push(txnum);
+        }
         if lsn >= 0 {
             self.lsn = lsn;
         }
@@ -51,28 +56,70 @@ impl Buffer {
         self.pins > 0
     }
 
-    pub fn modifying_tx(&self) -> i32 {
-        self.txnum
+    pub fn is_modified(&self) -> bool {
+        !self.txnums.is_empty()
+    }
+
+    pub fn is_modified_by(&self, txnum: i64) -> bool {
+        self.txnums.contains(&txnum)
+    }
+
+    pub fn lsn(&self) -> i64 {
+        self.lsn
     }
 
     pub(crate) fn assign_to_block(&mut self, b: &BlockId) -> Result<(), String> {
         self.flush()?;
         self.blk = Some(b.clone());
-        self.fm.read(b, &mut self.contents)?;
+        match self.fm.read_with_lsn(b, &mut self.contents) {
+            Ok(lsn) => self.lsn = lsn,
+            Err(e) => {
+                self.blk = None;
+                return Err(e.into());
+            }
+        }
+        self.pins = 0;
+        Ok(())
+    }
+
+    // Like assign_to_block, for a page that has already been read, e.g. by read-ahead.
+    // The old page must have been flushed.
+    pub(crate) fn assign_contents(
+        &mut self,
+        b: &BlockId,
+        bytes: &[u8],
+        lsn: i64,
+    ) -> Result<(), String> {
+        self.contents
+            .contents()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .copy_from_slice(bytes);
+        self.blk = Some(b.clone());
+        self.lsn = lsn;
         self.pins = 0;
         Ok(())
     }
 
     pub(crate) fn flush(&mut self) -> Result<(), String> {
-        if self.txnum >= 0 {
+        if self.is_modified() {
             let mut lm = self.lm.lock().map_err(|_| "failed to get lock")?;
             lm.flush(self.lsn)?;
-            self.fm.write(&self.blk.clone().unwrap(), &self.contents)
-                .map_err(|_| "failed to write")?
+            self.fm.write_with_lsn(&self.blk.clone().unwrap(), &self.contents, self.lsn)
+                .map_err(|_| "failed to write")?;
+            self.txnums.clear();
         }
         Ok(())
     }
 
+    // Forgets the block without writing it back, used once the block has been truncated away.
+    pub(crate) fn discard(&mut self) {
+        self.txnums.clear();
+        if !self.is_pinned() {
+            self.blk = None;
+        }
+    }
+
     pub(crate) fn pin(&mut self) {
         self.pins += 1;
     }
@@ -95,7 +142,6 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 3);
         let bm = db.buffer_manager();
-        let mut bm = bm.lock().unwrap();
 
         let buff1 = bm
             .pin(&BlockId::new("testfile".to_string(), 1))
@@ -132,7 +178,7 @@ mod tests {
         let blk = BlockId::new("testfile".to_string(), 2);
         let pos1 = 88;
 
-        let b1: Arc<Mutex<Buffer>> = bm.lock().unwrap().pin(&blk).unwrap().unwrap();
+        let b1: Arc<Mutex<Buffer>> = bm.pin(&blk).unwrap().unwrap();
         b1.lock()
             .unwrap()
             .contents()
@@ -142,9 +188,9 @@ mod tests {
         let pos2 = pos1 + size;
         b1.lock().unwrap().contents().set_int(pos2, 345).unwrap();
         b1.lock().unwrap().set_modified(1, 0);
-        bm.lock().unwrap().unpin(b1).unwrap();
+        bm.unpin(b1).unwrap();
 
-        let b2 = bm.lock().unwrap().pin(&blk).unwrap().unwrap();
+        let b2 = bm.pin(&blk).unwrap().unwrap();
         println!(
             "offset {} contents {}",
             pos2,
@@ -155,7 +201,7 @@ mod tests {
             pos1,
             b2.lock().unwrap().contents().get_string(pos1).unwrap()
         );
-        bm.lock().unwrap().unpin(b2.clone()).unwrap();
+        bm.unpin(b2.clone()).unwrap();
 
         assert_eq!(345, b2.lock().unwrap().contents().get_int(pos2).unwrap());
         assert_eq!(
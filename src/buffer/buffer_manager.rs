@@ -1,20 +1,23 @@
-use core::time;
 use std::{
+    collections::{HashMap, VecDeque},
     fmt,
-    sync::{Arc, Mutex},
-    thread,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 
-use chrono::Utc;
-
 use crate::{
     file::{block_id::BlockId, file_manager::FileManager},
     log::log_manager::LogManager,
 };
 
-use super::buffer::Buffer;
+use super::{
+    buffer::Buffer,
+    latch_table::{LatchTable, PageLatch},
+    replacement_policy::{AccessHint, ReplacementPolicy, ReplacementPolicyKind},
+};
 
-const MAX_TIME: i64 = 10000;
+pub const DEFAULT_PIN_TIMEOUT_MS: i64 = 10000;
+pub const DEFAULT_READ_AHEAD_BLOCKS: i32 = 4;
 
 #[derive(Debug, Clone, PartialEq)]
 
@@ -27,10 +30,38 @@ impl fmt::Display for BufferAbortException {
 
 impl std::error::Error for BufferAbortException {}
 
-#[derive(Debug, Clone)]
+// Which frame holds which block, and how often each frame is pinned. Guarded by
+// a single latch that is only held for bookkeeping; reading and writing pages
+// happens under the latch of the frame involved.
+//
+// Latch order: the pool state before any frame, never the other way round.
+#[derive(Debug)]
+struct PoolState {
+    page_table: HashMap<BlockId, usize>,
+    pins: Vec<i32>,
+    num_available: i32,
+    // tickets of the pins waiting for a free frame, served front to back
+    waiters: VecDeque<u64>,
+    next_ticket: u64,
+    // frames filled by read-ahead whose block has not been pinned yet; they are
+    // only evicted when nothing else is free
+    prefetched: Vec<bool>,
+    // the last block each file was sequentially pinned at
+    scan_positions: HashMap<String, i32>,
+}
+
+#[derive(Debug)]
 pub struct BufferManager {
+    fm: Arc<FileManager>,
     bufferpool: Vec<Arc<Mutex<Buffer>>>,
-    num_available: i32,
+    state: Mutex<PoolState>,
+    unpinned: Condvar,
+    max_wait: i64,
+    policy: Arc<Mutex<dyn ReplacementPolicy>>,
+    read_ahead: i32,
+    latches: Arc<LatchTable>,
+    // where the last search of each free space map for a block with room stopped
+    free_space_hints: Mutex<HashMap<String, i32>>,
 }
 
 impl BufferManager {
@@ -38,105 +69,480 @@ impl BufferManager {
         fm: Arc<FileManager>,
         lm: Arc<Mutex<LogManager>>,
         numbuffer: i32,
+    ) -> Result<Self, String> {
+        Self::new_with_timeout(fm, lm, numbuffer, DEFAULT_PIN_TIMEOUT_MS)
+    }
+
+    pub fn new_with_timeout(
+        fm: Arc<FileManager>,
+        lm: Arc<Mutex<LogManager>>,
+        numbuffer: i32,
+        max_wait: i64,
+    ) -> Result<Self, String> {
+        Self::new_with_policy(fm, lm, numbuffer, max_wait, ReplacementPolicyKind::default())
+    }
+
+    pub fn new_with_policy(
+        fm: Arc<FileManager>,
+        lm: Arc<Mutex<LogManager>>,
+        numbuffer: i32,
+        max_wait: i64,
+        policy: ReplacementPolicyKind,
     ) -> Result<Self, String> {
         let mut bufferpool = Vec::<Arc<Mutex<Buffer>>>::new();
         for _ in 0..numbuffer {
             bufferpool.push(Arc::new(Mutex::new(Buffer::new(fm.clone(), lm.clone())?)));
         }
         Ok(Self {
+            fm,
             bufferpool,
-            num_available: numbuffer,
+            state: Mutex::new(PoolState {
+                page_table: HashMap::new(),
+                pins: vec![0; numbuffer as usize],
+                num_available: numbuffer,
+                waiters: VecDeque::new(),
+                next_ticket: 0,
+                prefetched: vec![false; numbuffer as usize],
+                scan_positions: HashMap::new(),
+            }),
+            unpinned: Condvar::new(),
+            max_wait,
+            policy: policy.create(numbuffer as usize),
+            read_ahead: DEFAULT_READ_AHEAD_BLOCKS.min(numbuffer / 2),
+            latches: Arc::new(LatchTable::new()),
+            free_space_hints: Mutex::new(HashMap::new()),
         })
     }
 
+    // How many blocks to read ahead once a file is pinned sequentially; 0 disables
+    // it. At most half the pool is used for blocks nobody has asked for yet.
+    pub fn with_read_ahead(mut self, blocks: i32) -> Self {
+        self.read_ahead = blocks.clamp(0, self.bufferpool.len() as i32 / 2);
+        self
+    }
+
+    // Latches a page for as long as the guard lives, whichever transactions
+    // have the block pinned.
+    pub fn latch(&self, blk: &BlockId, exclusive: bool) -> Result<PageLatch, String> {
+        self.latches.latch(blk, exclusive)
+    }
+
+    pub fn latched_pages(&self) -> usize {
+        self.latches.size()
+    }
+
+    pub fn free_space_hint(&self, filename: &str) -> i32 {
+        self.free_space_hints
+            .lock()
+            .ok()
+            .and_then(|hints| hints.get(filename).copied())
+            .unwrap_or(0)
+    }
+
+    pub fn set_free_space_hint(&self, filename: &str, blknum: i32) -> Result<(), String> {
+        self.free_space_hints
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .insert(filename.to_string(), blknum);
+        Ok(())
+    }
+
     pub fn available(&self) -> i32 {
-        self.num_available
+        self.state.lock().map(|st| st.num_available).unwrap_or(0)
     }
 
-    pub fn flush_all(&mut self, txnum: i32) -> Result<(), String> {
-        for buff in &mut self.bufferpool {
+    pub fn flush_all(&self, txnum: i64) -> Result<(), String> {
+        for buff in &self.bufferpool {
             let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
-            if locked_buff.modifying_tx() == txnum {
+            if locked_buff.is_modified_by(txnum) {
                 locked_buff.flush()?
             }
         }
-        Ok(())
+        // callers write a commit, rollback or checkpoint record next, which must not
+        // become durable before the pages it vouches for
+        self.fm.sync_written_files()
+    }
+
+    // Writes every dirty buffer, waiting for the busy ones.
+    pub fn flush_all_dirty(&self) -> Result<(), String> {
+        for buff in &self.bufferpool {
+            let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
+            if locked_buff.is_modified() {
+                locked_buff.flush()?
+            }
+        }
+        self.fm.sync_written_files()
+    }
+
+    // Writes every dirty buffer that is not busy, e.g. from a background thread
+    // so that commits and evictions find less to write. Returns how many were written.
+    pub fn flush_dirty(&self) -> Result<i32, String> {
+        let mut flushed = 0;
+        for buff in &self.bufferpool {
+            if let Ok(mut locked_buff) = buff.try_lock() {
+                if locked_buff.is_modified() {
+                    locked_buff.flush()?;
+                    flushed += 1;
+                }
+            }
+        }
+        if flushed > 0 {
+            self.fm.sync_written_files()?;
+        }
+        Ok(flushed)
     }
 
-    pub fn unpin(&mut self, buff: Arc<Mutex<Buffer>>) -> Result<(), String> {
-        let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
-        locked_buff.unpin();
-        if !locked_buff.is_pinned() {
-            self.num_available += 1;
+    pub fn discard_blocks(&self, filename: &str, from_blknum: i32) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let discarded: Vec<(BlockId, usize)> = st
+            .page_table
+            .iter()
+            .filter(|(blk, _)| blk.file_name() == filename && blk.number() >= from_blknum)
+            .map(|(blk, frame)| (blk.clone(), *frame))
+            .collect();
+        for (blk, frame) in discarded {
+            let mut locked_buff = self.bufferpool[frame]
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            locked_buff.discard();
+            if locked_buff.block().is_none() {
+                st.page_table.remove(&blk);
+                st.prefetched[frame] = false;
+            }
         }
         Ok(())
     }
 
+    pub fn unpin(&self, buff: Arc<Mutex<Buffer>>) -> Result<(), String> {
+        let blk = {
+            let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
+            locked_buff.unpin();
+            locked_buff.block()
+        };
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let frame = blk
+            .and_then(|blk| st.page_table.get(&blk).copied())
+            .filter(|&frame| Arc::ptr_eq(&self.bufferpool[frame], &buff))
+            .ok_or("unpinned buffer is not in the pool")?;
+        self.release(&mut st, frame);
+        Ok(())
+    }
+
     pub fn pin(
-        &mut self,
+        &self,
         blk: &BlockId,
     ) -> Result<Option<Arc<Mutex<Buffer>>>, BufferAbortException> {
-        let timestamp = Utc::now().timestamp_millis();
-        let mut buff = self.try_to_pin(&blk).map_err(|_| BufferAbortException)?;
-        while buff.is_none() && !Self::waiting_too_long(timestamp) {
-            thread::sleep(time::Duration::from_millis(MAX_TIME.try_into().unwrap()));
-            buff = self.try_to_pin(&blk).map_err(|_| BufferAbortException)?;
-        }
-        if buff.is_none() {
-            return Err(From::from(BufferAbortException));
-        }
-        Ok(buff)
-    }
-
-    fn waiting_too_long(starttime: i64) -> bool {
-        Utc::now().timestamp_millis() - starttime > MAX_TIME
-    }
-
-    fn try_to_pin(&mut self, blk: &BlockId) -> Result<Option<Arc<Mutex<Buffer>>>, String> {
-        let buff: Option<Arc<Mutex<Buffer>>> = {
-            if let Some(buffer) = self.find_existing_buffer(blk)? {
-                Some(buffer)
-            } else {
-                if let Some(new_buff) = self.choose_unpinned_buffer()? {
-                    new_buff
-                        .lock()
-                        .map_err(|_| "failed to get lock")?
-                        .assign_to_block(blk)?;
-                    Some(new_buff)
-                } else {
-                    None
+        self.pin_with_hint(blk, AccessHint::Normal)
+    }
+
+    // Waits for a frame to be unpinned if the pool is full. Waiters are parked
+    // on a condition variable and served in arrival order.
+    pub fn pin_with_hint(
+        &self,
+        blk: &BlockId,
+        hint: AccessHint,
+    ) -> Result<Option<Arc<Mutex<Buffer>>>, BufferAbortException> {
+        let deadline = Instant::now() + Duration::from_millis(self.max_wait.max(0) as u64);
+        let mut ticket = None;
+        loop {
+            let res = self.try_to_pin(blk, hint, ticket);
+            if !matches!(res, Ok(None)) {
+                if let Some(ticket) = ticket {
+                    self.leave_queue(ticket).map_err(|_| BufferAbortException)?;
+                }
+                if hint == AccessHint::Sequential && res.is_ok() && self.read_ahead > 0 {
+                    // read-ahead is only an optimization; a block it failed to read
+                    // reports its error when it is pinned
+                    let _ = self.continue_scan(blk);
                 }
+                return res.map_err(|_| BufferAbortException);
             }
-        };
-        if let Some(ref b) = buff {
-            let mut locked_buff = b.lock().map_err(|_| "failed to get lock")?;
-            if !locked_buff.is_pinned() {
-                self.num_available -= 1;
+
+            let mut st = self.state.lock().map_err(|_| BufferAbortException)?;
+            let t = *ticket.get_or_insert_with(|| {
+                let t = st.next_ticket;
+                st.next_ticket += 1;
+                st.waiters.push_back(t);
+                t
+            });
+            let now = Instant::now();
+            if now >= deadline {
+                drop(st);
+                self.leave_queue(t).map_err(|_| BufferAbortException)?;
+                return Err(BufferAbortException);
+            }
+            if st.num_available == 0 || st.waiters.front() != Some(&t) {
+                let _ = self
+                    .unpinned
+                    .wait_timeout(st, deadline - now)
+                    .map_err(|_| BufferAbortException)?;
             }
-            locked_buff.pin();
         }
+    }
 
-        Ok(buff)
+    fn leave_queue(&self, ticket: u64) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.waiters.retain(|&t| t != ticket);
+        // the next waiter may be able to go now
+        self.unpinned.notify_all();
+        Ok(())
     }
 
-    fn find_existing_buffer(&self, blk: &BlockId) -> Result<Option<Arc<Mutex<Buffer>>>, String> {
-        for buff in &self.bufferpool {
-            if let Some(b) = buff.lock().map_err(|_| "failed to get lock")?.block() {
-                if b.eq(&blk) {
-                    return Ok(Some(buff.clone()));
+    // `ticket` identifies a queued waiter; a frame is only taken for someone
+    // else if nobody is waiting.
+    fn try_to_pin(
+        &self,
+        blk: &BlockId,
+        hint: AccessHint,
+        ticket: Option<u64>,
+    ) -> Result<Option<Arc<Mutex<Buffer>>>, String> {
+        loop {
+            let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+            if let Some(&frame) = st.page_table.get(blk) {
+                Self::reserve(&mut st, frame);
+                // the first pin of a read-ahead block counts as loading it
+                let loaded = std::mem::replace(&mut st.prefetched[frame], false);
+                self.policy
+                    .lock()
+                    .map_err(|_| "failed to get lock")?
+                    .record_access(frame, hint, loaded);
+                drop(st);
+
+                // waits here while another thread is still reading the block in
+                let buff = self.bufferpool[frame].clone();
+                let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
+                if locked_buff.block().as_ref() == Some(blk) {
+                    locked_buff.pin();
+                    drop(locked_buff);
+                    return Ok(Some(buff));
                 }
+                // that read failed; look the block up again
+                drop(locked_buff);
+                let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+                self.release(&mut st, frame);
+                continue;
+            }
+
+            if st.waiters.front().is_some_and(|&t| Some(t) != ticket) {
+                return Ok(None);
+            }
+            let frame = match self.choose_unpinned_buffer(&st)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            let buff = self.bufferpool[frame].clone();
+            let mut locked_buff = buff.lock().map_err(|_| "failed to get lock")?;
+            if locked_buff.is_modified() {
+                self.write_back(st, frame, locked_buff)?;
+                continue;
             }
+            Self::reserve(&mut st, frame);
+            if let Some(old) = locked_buff.block() {
+                st.page_table.remove(&old);
+            }
+            st.page_table.insert(blk.clone(), frame);
+            st.prefetched[frame] = false;
+            self.policy
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .record_access(frame, hint, true);
+            drop(st);
+
+            // other threads can find the frame now but block on its latch until
+            // the page has been read
+            if let Err(e) = locked_buff.assign_to_block(blk) {
+                let kept = locked_buff.block();
+                drop(locked_buff);
+                let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+                if st.page_table.get(blk) == Some(&frame) {
+                    st.page_table.remove(blk);
+                }
+                // a failed write-back leaves the old page in the frame
+                if let Some(old) = kept {
+                    st.page_table.insert(old, frame);
+                }
+                self.release(&mut st, frame);
+                return Err(e);
+            }
+            locked_buff.pin();
+            drop(locked_buff);
+            return Ok(Some(buff));
         }
-        Ok(None)
     }
 
-    fn choose_unpinned_buffer(&self) -> Result<Option<Arc<Mutex<Buffer>>>, String> {
-        for buff in &self.bufferpool {
-            if !buff.lock().map_err(|_| "failed to get lock")?.is_pinned() {
-                return Ok(Some(buff.clone()));
+    // Reads the blocks following `blk` if it continues a sequential scan of its
+    // file and they are not in the pool yet.
+    fn continue_scan(&self, blk: &BlockId) -> Result<i32, String> {
+        {
+            let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+            if st.scan_positions.len() > self.bufferpool.len() {
+                st.scan_positions.clear();
+            }
+            let prev = st.scan_positions.insert(blk.file_name(), blk.number());
+            if prev != Some(blk.number() - 1) {
+                return Ok(0);
             }
         }
-        Ok(None)
+        let next = BlockId::new(blk.file_name(), blk.number() + 1);
+        self.read_ahead(&next, self.read_ahead)
+    }
+
+    // Reads up to `count` blocks starting at `first` into free frames with a
+    // single read, without pinning them. Never reads more than the configured
+    // read-ahead, and stops at the end of the file, at a block that is already
+    // in the pool, or when no frame is free. Returns how many blocks were read.
+    pub fn read_ahead(&self, first: &BlockId, count: i32) -> Result<i32, String> {
+        let count = count
+            .min(self.read_ahead)
+            .min(self.fm.len(&first.file_name())? - first.number());
+        if count <= 0 {
+            return Ok(0);
+        }
+
+        let mut frames = vec![];
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let mut taken: Vec<bool> = (0..self.bufferpool.len())
+            .map(|i| st.pins[i] > 0 || st.prefetched[i])
+            .collect();
+        let mut i = 0;
+        while i < count && st.waiters.is_empty() {
+            let blk = BlockId::new(first.file_name(), first.number() + i);
+            if st.page_table.contains_key(&blk) {
+                break;
+            }
+            let victim = self
+                .policy
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .choose_victim(&taken);
+            let Some(frame) = victim else {
+                break;
+            };
+            let locked_buff = self.bufferpool[frame]
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            if locked_buff.is_modified() {
+                // the pool latch is let go for the write, which must not
+                // happen while frames taken for the read are latched
+                if !frames.is_empty() {
+                    break;
+                }
+                self.write_back(st, frame, locked_buff)?;
+                st = self.state.lock().map_err(|_| "failed to get lock")?;
+                taken = (0..self.bufferpool.len())
+                    .map(|i| st.pins[i] > 0 || st.prefetched[i])
+                    .collect();
+                continue;
+            }
+            i += 1;
+            taken[frame] = true;
+            Self::reserve(&mut st, frame);
+            if let Some(old) = locked_buff.block() {
+                st.page_table.remove(&old);
+            }
+            st.page_table.insert(blk.clone(), frame);
+            st.prefetched[frame] = true;
+            frames.push((frame, blk, locked_buff));
+        }
+        drop(st);
+        if frames.is_empty() {
+            return Ok(0);
+        }
+
+        // as in try_to_pin, other threads block on the frame latches until the
+        // pages are in
+        let res = frames
+            .iter_mut()
+            .try_for_each(|(_, _, locked_buff)| locked_buff.flush())
+            .and_then(|_| {
+                self.fm
+                    .read_blocks(first, frames.len())
+                    .map_err(String::from)
+            })
+            .and_then(|pages| {
+                frames
+                    .iter_mut()
+                    .zip(pages)
+                    .try_for_each(|((_, blk, locked_buff), (page, lsn))| {
+                        locked_buff.assign_contents(blk, &page, lsn)
+                    })
+            });
+
+        let kept: Vec<(usize, BlockId, Option<BlockId>)> = frames
+            .into_iter()
+            .map(|(frame, blk, locked_buff)| (frame, blk, locked_buff.block()))
+            .collect();
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        for (frame, blk, current) in kept.iter() {
+            if res.is_err() && current.as_ref() != Some(blk) {
+                if st.page_table.get(blk) == Some(frame) {
+                    st.page_table.remove(blk);
+                }
+                if let Some(old) = current {
+                    st.page_table.insert(old.clone(), *frame);
+                }
+                st.prefetched[*frame] = false;
+            }
+            self.release(&mut st, *frame);
+        }
+        res.map(|_| kept.len() as i32)
+    }
+
+    // Writes back the dirty page of a frame about to be reused. Its block stays
+    // mapped until the page is on disk, so that a pin of the block in the
+    // meantime waits for the frame instead of reading the stale copy; the
+    // frame is reserved so that nobody else takes it meanwhile.
+    fn write_back(
+        &self,
+        mut st: MutexGuard<'_, PoolState>,
+        frame: usize,
+        mut locked_buff: MutexGuard<'_, Buffer>,
+    ) -> Result<(), String> {
+        Self::reserve(&mut st, frame);
+        drop(st);
+        let res = locked_buff.flush();
+        drop(locked_buff);
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        self.release(&mut st, frame);
+        res
+    }
+
+    fn reserve(st: &mut PoolState, frame: usize) {
+        if st.pins[frame] == 0 {
+            st.num_available -= 1;
+        }
+        st.pins[frame] += 1;
+    }
+
+    fn release(&self, st: &mut PoolState, frame: usize) {
+        st.pins[frame] -= 1;
+        if st.pins[frame] == 0 {
+            st.num_available += 1;
+            self.unpinned.notify_all();
+        }
+    }
+
+    #[cfg(test)]
+    fn find_existing_buffer(&self, blk: &BlockId) -> Result<Option<usize>, String> {
+        Ok(self
+            .state
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .page_table
+            .get(blk)
+            .copied())
+    }
+
+    fn choose_unpinned_buffer(&self, st: &PoolState) -> Result<Option<usize>, String> {
+        let mut policy = self.policy.lock().map_err(|_| "failed to get lock")?;
+        let taken: Vec<bool> = (0..self.bufferpool.len())
+            .map(|i| st.pins[i] > 0 || st.prefetched[i])
+            .collect();
+        if let Some(frame) = policy.choose_victim(&taken) {
+            return Ok(Some(frame));
+        }
+        let pinned: Vec<bool> = st.pins.iter().map(|&pins| pins > 0).collect();
+        Ok(policy.choose_victim(&pinned))
     }
 }
 
@@ -145,7 +551,242 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
-    use crate::server::simple_db::SimpleDB;
+    use crate::{file::page::Page, server::simple_db::SimpleDB};
+
+    #[test]
+    fn test_sequential_scan_keeps_hot_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 3);
+        let bm = db.buffer_manager();
+        let hot = BlockId::new("testfile".to_string(), 0);
+
+        for _ in 0..2 {
+            let buff = bm.pin(&hot).unwrap().unwrap();
+            bm.unpin(buff).unwrap();
+        }
+        for blknum in 1..20 {
+            let blk = BlockId::new("testfile".to_string(), blknum);
+            let buff = bm.pin_with_hint(&blk, AccessHint::Sequential).unwrap().unwrap();
+            bm.unpin(buff).unwrap();
+        }
+        assert!(bm.find_existing_buffer(&hot).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_sequential_pins_read_ahead() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let fm = db.file_manager();
+        let mut page = Page::new_from_blocksize(400);
+        for blknum in 0..6 {
+            page.set_int(80, blknum * 10).unwrap();
+            fm.write(&BlockId::new("testfile".to_string(), blknum), &page).unwrap();
+        }
+        let bm = BufferManager::new(fm, db.log_mgr(), 8).unwrap();
+
+        for blknum in 0..2 {
+            let blk = BlockId::new("testfile".to_string(), blknum);
+            let buff = bm.pin_with_hint(&blk, AccessHint::Sequential).unwrap().unwrap();
+            bm.unpin(buff).unwrap();
+        }
+        // the second pin showed a scan, so blocks 2 to 5 were read in one go
+        for blknum in 2..6 {
+            let blk = BlockId::new("testfile".to_string(), blknum);
+            assert!(bm.find_existing_buffer(&blk).unwrap().is_some());
+        }
+        assert!(bm.find_existing_buffer(&BlockId::new("testfile".to_string(), 6)).unwrap().is_none());
+        assert_eq!(bm.available(), 8);
+
+        let blk = BlockId::new("testfile".to_string(), 4);
+        let buff = bm.pin_with_hint(&blk, AccessHint::Sequential).unwrap().unwrap();
+        assert_eq!(buff.lock().unwrap().contents().get_int(80).unwrap(), 40);
+        bm.unpin(buff).unwrap();
+
+        // read-ahead stops at blocks already in the pool
+        assert_eq!(bm.read_ahead(&BlockId::new("testfile".to_string(), 3), 2).unwrap(), 0);
+
+        // and reads no more than it is configured to, whoever asks
+        let fm = db.file_manager();
+        for blknum in 6..12 {
+            fm.write(&BlockId::new("testfile".to_string(), blknum), &page).unwrap();
+        }
+        assert_eq!(bm.read_ahead(&BlockId::new("testfile".to_string(), 6), 6).unwrap(), 4);
+        let bm = BufferManager::new(fm, db.log_mgr(), 8).unwrap().with_read_ahead(0);
+        assert_eq!(bm.read_ahead(&BlockId::new("testfile".to_string(), 6), 6).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pin_waits_for_evicted_page_write() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let lm = db.log_mgr();
+        let bm = Arc::new(
+            BufferManager::new_with_policy(db.file_manager(), lm.clone(), 3, 5_000, ReplacementPolicyKind::Lru)
+                .unwrap(),
+        );
+        let pin = |blknum| bm.pin(&BlockId::new("testfile".to_string(), blknum)).unwrap().unwrap();
+        let buff = pin(1);
+        buff.lock().unwrap().contents().set_int(80, 11).unwrap();
+        buff.lock().unwrap().set_modified(1, 0);
+        bm.unpin(buff).unwrap();
+        bm.unpin(pin(2)).unwrap();
+        let _kept = pin(9);
+
+        // block 1 is the victim, and its write waits for the log
+        let guard = lm.lock().unwrap();
+        let evicter = {
+            let bm = bm.clone();
+            std::thread::spawn(move || {
+                let buff = bm.pin(&BlockId::new("testfile".to_string(), 3)).unwrap().unwrap();
+                bm.unpin(buff).unwrap();
+            })
+        };
+        while bm.available() > 1 {
+            std::thread::yield_now();
+        }
+        let (sent, got) = std::sync::mpsc::channel();
+        let reader = {
+            let bm = bm.clone();
+            std::thread::spawn(move || {
+                let buff = bm.pin(&BlockId::new("testfile".to_string(), 1)).unwrap().unwrap();
+                let n = buff.lock().unwrap().contents().get_int(80).unwrap();
+                bm.unpin(buff).unwrap();
+                sent.send(n).unwrap();
+            })
+        };
+        // the reader waits for the page rather than reading the old copy from disk
+        assert!(got.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(guard);
+        assert_eq!(got.recv_timeout(Duration::from_secs(5)).unwrap(), 11);
+        evicter.join().unwrap();
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_ahead_writes_back_victims_before_unmapping() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let fm = db.file_manager();
+        fm.write(&BlockId::new("testfile".to_string(), 20), &Page::new_from_blocksize(400))
+            .unwrap();
+        let lm = db.log_mgr();
+        let bm = Arc::new(
+            BufferManager::new_with_policy(fm, lm.clone(), 4, 5_000, ReplacementPolicyKind::Lru).unwrap(),
+        );
+        let pin = |blknum| bm.pin(&BlockId::new("testfile".to_string(), blknum)).unwrap().unwrap();
+        let buff = pin(1);
+        buff.lock().unwrap().contents().set_int(80, 11).unwrap();
+        buff.lock().unwrap().set_modified(1, 0);
+        bm.unpin(buff).unwrap();
+        bm.unpin(pin(2)).unwrap();
+        let _kept = (pin(8), pin(9));
+
+        // block 1 is the victim, and its write waits for the log
+        let guard = lm.lock().unwrap();
+        let reader_ahead = {
+            let bm = bm.clone();
+            std::thread::spawn(move || bm.read_ahead(&BlockId::new("testfile".to_string(), 20), 1).unwrap())
+        };
+        while bm.available() > 1 {
+            std::thread::yield_now();
+        }
+        let (sent, got) = std::sync::mpsc::channel();
+        let reader = {
+            let bm = bm.clone();
+            std::thread::spawn(move || {
+                let buff = bm.pin(&BlockId::new("testfile".to_string(), 1)).unwrap().unwrap();
+                let n = buff.lock().unwrap().contents().get_int(80).unwrap();
+                bm.unpin(buff).unwrap();
+                sent.send(n).unwrap();
+            })
+        };
+        assert!(got.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(guard);
+        assert_eq!(got.recv_timeout(Duration::from_secs(5)).unwrap(), 11);
+        reader_ahead.join().unwrap();
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_flush_all_writes_pages_changed_by_others_since() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let bm = db.buffer_manager();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        let buff = bm.pin(&blk).unwrap().unwrap();
+        buff.lock().unwrap().contents().set_int(80, 11).unwrap();
+        buff.lock().unwrap().set_modified(1, 0);
+        buff.lock().unwrap().set_modified(2, 0);
+        bm.unpin(buff).unwrap();
+
+        // transaction 2 changed the page last, but 1's change is in it too
+        bm.flush_all(1).unwrap();
+        let mut page = Page::new_from_blocksize(400);
+        db.file_manager().read(&blk, &mut page).unwrap();
+        assert_eq!(page.get_int(80).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_concurrent_pins() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let bm = db.buffer_manager();
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let bm = bm.clone();
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        // every thread shares block 0 and cycles through its own blocks
+                        for blknum in [0, 1 + t * 10 + i % 10] {
+                            let blk = BlockId::new("testfile".to_string(), blknum);
+                            let buff = bm.pin(&blk).unwrap().unwrap();
+                            assert_eq!(buff.lock().unwrap().block(), Some(blk));
+                            bm.unpin(buff).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(bm.available(), 8);
+    }
+
+    #[test]
+    fn test_unpin_wakes_waiting_pin() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let bm = Arc::new(
+            BufferManager::new_with_timeout(db.file_manager(), db.log_mgr(), 1, 5_000).unwrap(),
+        );
+        let buff = bm
+            .pin(&BlockId::new("testfile".to_string(), 0))
+            .unwrap()
+            .unwrap();
+
+        let waiter = {
+            let bm = bm.clone();
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                let buff = bm.pin(&BlockId::new("testfile".to_string(), 1)).unwrap();
+                (start.elapsed(), buff.is_some())
+            })
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        bm.unpin(buff).unwrap();
+        let (waited, pinned) = waiter.join().unwrap();
+        assert!(pinned);
+        assert!(waited < Duration::from_secs(1));
+
+        let bm = BufferManager::new_with_timeout(db.file_manager(), db.log_mgr(), 1, 50).unwrap();
+        bm.pin(&BlockId::new("testfile".to_string(), 0)).unwrap();
+        assert_eq!(
+            bm.pin(&BlockId::new("testfile".to_string(), 1)).unwrap_err(),
+            BufferAbortException
+        );
+    }
 
     #[test]
     fn test_main() {
@@ -156,53 +797,26 @@ mod tests {
         let bm = db.buffer_manager();
 
         let mut buffs = vec![Ok(None); 6];
-        buffs[0] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 0)).unwrap());
-        buffs[1] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 1)).unwrap());
-        buffs[2] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 2)).unwrap());
+        buffs[0] = bm.pin(Some(&BlockId::new("testfile".to_string(), 0)).unwrap());
+        buffs[1] = bm.pin(Some(&BlockId::new("testfile".to_string(), 1)).unwrap());
+        buffs[2] = bm.pin(Some(&BlockId::new("testfile".to_string(), 2)).unwrap());
 
-        bm.lock()
-            .unwrap()
-            .unpin(buffs[1].clone().unwrap().unwrap())
-            .unwrap();
+        bm.unpin(buffs[1].clone().unwrap().unwrap()).unwrap();
         buffs[1] = Ok(None);
 
-        buffs[3] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 0)).unwrap());
-        buffs[4] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 1)).unwrap());
-        println!("Available buffers: {}", bm.lock().unwrap().available());
+        buffs[3] = bm.pin(Some(&BlockId::new("testfile".to_string(), 0)).unwrap());
+        buffs[4] = bm.pin(Some(&BlockId::new("testfile".to_string(), 1)).unwrap());
+        println!("Available buffers: {}", bm.available());
 
         println!("Attempting to pin block 3...");
-        let res = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 3)).unwrap());
+        let res = bm.pin(Some(&BlockId::new("testfile".to_string(), 3)).unwrap());
 
         assert_eq!(res.unwrap_err(), BufferAbortException);
 
-        bm.lock()
-            .unwrap()
-            .unpin(buffs[2].clone().unwrap().unwrap())
-            .unwrap();
+        bm.unpin(buffs[2].clone().unwrap().unwrap()).unwrap();
         buffs[2] = Ok(None);
 
-        buffs[5] = bm
-            .lock()
-            .unwrap()
-            .pin(Some(&BlockId::new("testfile".to_string(), 3)).unwrap());
+        buffs[5] = bm.pin(Some(&BlockId::new("testfile".to_string(), 3)).unwrap());
 
         println!("Final Buffer Allocation:");
 
@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::file::block_id::BlockId;
+
+// Short-term latches on pages, for structures such as B-trees that change
+// shared pages on behalf of many transactions at once. Unlike locks they are
+// not tied to a transaction, are never part of a deadlock check and do not
+// time out: their users take them in a fixed order, from the root of a tree
+// down, and hold them only while working on the page.
+#[derive(Debug, Default)]
+pub struct LatchTable {
+    // the readers and whether a writer holds each latched page
+    state: Mutex<HashMap<BlockId, (usize, bool)>>,
+    released: Condvar,
+}
+
+impl LatchTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Waits until the page can be latched in the mode asked for. The latch is
+    // released when the returned guard is dropped.
+    pub fn latch(self: &Arc<Self>, blk: &BlockId, exclusive: bool) -> Result<PageLatch, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        loop {
+            let (readers, writer) = st.entry(blk.clone()).or_default();
+            if !*writer && (!exclusive || *readers == 0) {
+                match exclusive {
+                    true => *writer = true,
+                    false => *readers += 1,
+                }
+                break;
+            }
+            st = self
+                .released
+                .wait(st)
+                .map_err(|_| "failed to get lock")?;
+        }
+        Ok(PageLatch {
+            table: self.clone(),
+            blk: blk.clone(),
+            exclusive,
+        })
+    }
+
+    fn unlatch(&self, blk: &BlockId, exclusive: bool) {
+        let Ok(mut st) = self.state.lock() else {
+            return;
+        };
+        if let Some((readers, writer)) = st.get_mut(blk) {
+            match exclusive {
+                true => *writer = false,
+                false => *readers -= 1,
+            }
+            if *readers == 0 && !*writer {
+                st.remove(blk);
+            }
+        }
+        self.released.notify_all();
+    }
+
+    // The number of pages latched.
+    pub fn size(&self) -> usize {
+        self.state.lock().map(|st| st.len()).unwrap_or(0)
+    }
+}
+
+#[derive(Debug)]
+pub struct PageLatch {
+    table: Arc<LatchTable>,
+    blk: BlockId,
+    exclusive: bool,
+}
+
+impl PageLatch {
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+}
+
+impl Drop for PageLatch {
+    fn drop(&mut self) {
+        self.table.unlatch(&self.blk, self.exclusive);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use crate::file::block_id::BlockId;
+
+    use super::LatchTable;
+
+    #[test]
+    fn test_exclusive_latch_waits_for_readers() {
+        let latches = Arc::new(LatchTable::new());
+        let blk = BlockId::new("testfile".to_string(), 0);
+        let r1 = latches.latch(&blk, false).unwrap();
+        let r2 = latches.latch(&blk, false).unwrap();
+
+        let (sent, got) = mpsc::channel();
+        let writer = {
+            let latches = latches.clone();
+            let blk = blk.clone();
+            thread::spawn(move || {
+                let latch = latches.latch(&blk, true).unwrap();
+                sent.send(()).unwrap();
+                drop(latch);
+            })
+        };
+        drop(r1);
+        assert!(got.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(r2);
+        got.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+        assert_eq!(latches.size(), 0);
+    }
+}
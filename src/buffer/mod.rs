@@ -1,2 +1,4 @@
 pub mod buffer;
 pub mod buffer_manager;
+pub mod latch_table;
+pub mod replacement_policy;
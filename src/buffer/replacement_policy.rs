@@ -0,0 +1,212 @@
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+// How a block is being accessed. A block that a sequential scan reads into the
+// pool is unlikely to be needed again soon, so it becomes the first eviction
+// candidate; only a later hit makes it count as recently used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessHint {
+    #[default]
+    Normal,
+    Sequential,
+}
+
+// Decides which unpinned buffer frame to reuse. Frames are identified by their
+// index in the buffer pool.
+pub trait ReplacementPolicy: Debug + Send {
+    // Called on every pin; `loaded` is true when the frame has just been assigned
+    // a new block.
+    fn record_access(&mut self, frame: usize, hint: AccessHint, loaded: bool);
+    // Returns an unpinned frame to evict, or None if every frame is pinned.
+    fn choose_victim(&mut self, pinned: &[bool]) -> Option<usize>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplacementPolicyKind {
+    #[default]
+    Lru,
+    Clock,
+    LruK,
+}
+
+impl ReplacementPolicyKind {
+    pub fn create(&self, numbuffs: usize) -> Arc<Mutex<dyn ReplacementPolicy>> {
+        match self {
+            ReplacementPolicyKind::Lru => Arc::new(Mutex::new(LruPolicy::new(numbuffs))),
+            ReplacementPolicyKind::Clock => Arc::new(Mutex::new(ClockPolicy::new(numbuffs))),
+            ReplacementPolicyKind::LruK => Arc::new(Mutex::new(LruKPolicy::new(numbuffs, 2))),
+        }
+    }
+}
+
+impl FromStr for ReplacementPolicyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(ReplacementPolicyKind::Lru),
+            "clock" => Ok(ReplacementPolicyKind::Clock),
+            "lru-k" | "lruk" => Ok(ReplacementPolicyKind::LruK),
+            _ => Err(format!("unknown replacement policy {}", s)),
+        }
+    }
+}
+
+// Evicts the frame whose last access is the oldest.
+#[derive(Debug)]
+pub struct LruPolicy {
+    last_access: Vec<u64>,
+    clock: u64,
+}
+
+impl LruPolicy {
+    pub fn new(numbuffs: usize) -> Self {
+        LruPolicy {
+            last_access: vec![0; numbuffs],
+            clock: 0,
+        }
+    }
+}
+
+impl ReplacementPolicy for LruPolicy {
+    fn record_access(&mut self, frame: usize, hint: AccessHint, loaded: bool) {
+        if hint == AccessHint::Sequential && loaded {
+            self.last_access[frame] = 0;
+        } else {
+            self.clock += 1;
+            self.last_access[frame] = self.clock;
+        }
+    }
+
+    fn choose_victim(&mut self, pinned: &[bool]) -> Option<usize> {
+        (0..pinned.len())
+            .filter(|&i| !pinned[i])
+            .min_by_key(|&i| self.last_access[i])
+    }
+}
+
+// Sweeps the pool, giving each recently referenced frame a second chance.
+#[derive(Debug)]
+pub struct ClockPolicy {
+    referenced: Vec<bool>,
+    hand: usize,
+}
+
+impl ClockPolicy {
+    pub fn new(numbuffs: usize) -> Self {
+        ClockPolicy {
+            referenced: vec![false; numbuffs],
+            hand: 0,
+        }
+    }
+}
+
+impl ReplacementPolicy for ClockPolicy {
+    fn record_access(&mut self, frame: usize, hint: AccessHint, loaded: bool) {
+        self.referenced[frame] = !(hint == AccessHint::Sequential && loaded);
+    }
+
+    fn choose_victim(&mut self, pinned: &[bool]) -> Option<usize> {
+        let n = pinned.len();
+        // two sweeps clear every reference bit, so an unpinned frame is found if one exists
+        for _ in 0..2 * n {
+            let frame = self.hand;
+            self.hand = (self.hand + 1) % n;
+            if pinned[frame] {
+                continue;
+            }
+            if self.referenced[frame] {
+                self.referenced[frame] = false;
+            } else {
+                return Some(frame);
+            }
+        }
+        None
+    }
+}
+
+// Evicts the frame whose k-th most recent access is the oldest. Frames with
+// fewer than k accesses go first, oldest last access first, so a block touched
+// once cannot push out one that is used repeatedly.
+#[derive(Debug)]
+pub struct LruKPolicy {
+    k: usize,
+    history: Vec<VecDeque<u64>>,
+    clock: u64,
+}
+
+impl LruKPolicy {
+    pub fn new(numbuffs: usize, k: usize) -> Self {
+        LruKPolicy {
+            k,
+            history: vec![VecDeque::new(); numbuffs],
+            clock: 0,
+        }
+    }
+
+    // (has k accesses, k-th most recent access or else the last one)
+    fn eviction_key(&self, frame: usize) -> (bool, u64) {
+        let h = &self.history[frame];
+        if h.len() < self.k {
+            (false, h.back().copied().unwrap_or(0))
+        } else {
+            (true, h[0])
+        }
+    }
+}
+
+impl ReplacementPolicy for LruKPolicy {
+    fn record_access(&mut self, frame: usize, hint: AccessHint, loaded: bool) {
+        if loaded {
+            self.history[frame].clear();
+            if hint == AccessHint::Sequential {
+                return;
+            }
+        }
+        self.clock += 1;
+        let h = &mut self.history[frame];
+        h.push_back(self.clock);
+        if h.len() > self.k {
+            h.pop_front();
+        }
+    }
+
+    fn choose_victim(&mut self, pinned: &[bool]) -> Option<usize> {
+        (0..pinned.len())
+            .filter(|&i| !pinned[i])
+            .min_by_key(|&i| self.eviction_key(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessHint, ReplacementPolicyKind};
+
+    #[test]
+    fn test_replacement_policies() {
+        let unpinned = [false; 3];
+        for kind in [
+            ReplacementPolicyKind::Lru,
+            ReplacementPolicyKind::Clock,
+            ReplacementPolicyKind::LruK,
+        ] {
+            let policy = kind.create(3);
+            let mut policy = policy.lock().unwrap();
+            for frame in 0..3 {
+                policy.record_access(frame, AccessHint::Normal, true);
+            }
+            // frame 0 is hot, frame 2 was loaded by a scan
+            policy.record_access(0, AccessHint::Normal, false);
+            policy.record_access(2, AccessHint::Sequential, true);
+            assert_eq!(policy.choose_victim(&unpinned), Some(2), "{:?}", kind);
+            if kind != ReplacementPolicyKind::Clock {
+                assert_eq!(policy.choose_victim(&[false, false, true]), Some(1), "{:?}", kind);
+            }
+            assert_eq!(policy.choose_victim(&[true; 3]), None, "{:?}", kind);
+        }
+    }
+}
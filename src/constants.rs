@@ -1,2 +1,5 @@
 pub const INTEGER_BYTES: i32 = 4;
+pub const LONG_BYTES: i32 = 8;
 pub const LOG_FILE: &str = "simpledb.log";
+pub const SUPERBLOCK_FILE: &str = "superblock";
+pub const CONFIG_FILE: &str = "sabidb.conf";
@@ -0,0 +1,67 @@
+use crate::constants::INTEGER_BYTES;
+
+// Every block on disk is followed by a CRC32 of its contents.
+pub const CHECKSUM_BYTES: i32 = INTEGER_BYTES;
+
+const CRC32_TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for b in bytes {
+        crc = CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xFFFFFFFF
+}
+
+// Appends the checksum trailer to a block's contents.
+pub fn seal(contents: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(contents.len() + CHECKSUM_BYTES as usize);
+    ret.extend_from_slice(contents);
+    ret.extend_from_slice(&crc32(contents).to_be_bytes());
+    ret
+}
+
+// A block that was never written (or was appended and left empty) is all zeros,
+// trailer included, and is accepted as is.
+pub fn verify(block: &[u8]) -> bool {
+    if block.len() < CHECKSUM_BYTES as usize {
+        return false;
+    }
+    if block.iter().all(|b| *b == 0) {
+        return true;
+    }
+    let (contents, trailer) = block.split_at(block.len() - CHECKSUM_BYTES as usize);
+    trailer == crc32(contents).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, seal, verify};
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+
+        let mut block = seal(b"hello sabidb");
+        assert!(verify(&block));
+        block[3] ^= 0x01;
+        assert!(!verify(&block));
+        assert!(verify(&vec![0u8; 404]));
+    }
+}
@@ -0,0 +1,28 @@
+use std::str::FromStr;
+
+// Controls when written files are forced to stable storage. Unless it is None, data files
+// are synced before a commit, rollback or checkpoint record is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurabilityMode {
+    // Every log page write is synced.
+    #[default]
+    Full,
+    // Log writes are synced only when a commit or a data page write depends on them,
+    // so a single sync covers every record written before it.
+    Group,
+    // Nothing is synced; meant for tests and throwaway databases.
+    None,
+}
+
+impl FromStr for DurabilityMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "full" => Ok(DurabilityMode::Full),
+            "group" => Ok(DurabilityMode::Group),
+            "none" => Ok(DurabilityMode::None),
+            _ => Err(format!("unknown durability mode {}", s)),
+        }
+    }
+}
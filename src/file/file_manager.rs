@@ -1,37 +1,93 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs::{create_dir, remove_file, File, OpenOptions};
+use std::fmt;
+use std::fs::{copy, create_dir, create_dir_all, remove_file, rename, File, OpenOptions};
 use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+use crate::constants::{CONFIG_FILE, LONG_BYTES};
 use crate::file::block_id::BlockId;
+use crate::file::checksum::{self, CHECKSUM_BYTES};
+use crate::file::durability_mode::DurabilityMode;
 use crate::file::page::Page;
+use crate::server::log_level::LogLevel;
+
+// Every block on disk is followed by its page LSN and then its checksum.
+pub const PAGE_LSN_BYTES: i32 = LONG_BYTES;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageReadException {
+    // The block's checksum does not match its contents, e.g. after a torn write.
+    Corrupt(BlockId),
+    Io(String),
+}
+
+impl fmt::Display for PageReadException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageReadException::Corrupt(blk) => write!(f, "checksum mismatch in block {}", blk),
+            PageReadException::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for PageReadException {}
+
+impl From<PageReadException> for String {
+    fn from(e: PageReadException) -> Self {
+        e.to_string()
+    }
+}
+
+// Temporary tables live in files like these and are removed on startup.
+pub fn is_temp_file(filename: &str) -> bool {
+    filename.starts_with("temp")
+}
 
 #[derive(Debug)]
 pub struct FileManager {
     blocksize: i32,
     db_directory: PathBuf,
     open_files: Mutex<HashMap<String, Arc<File>>>,
+    unsynced_files: Mutex<HashSet<String>>,
+    durability: DurabilityMode,
+    log_level: LogLevel,
     is_new: bool,
 }
 
 impl FileManager {
     pub fn new_from_blocksize(db_directory: &Path, blocksize: i32) -> Self {
+        Self::new_with_durability(db_directory, blocksize, DurabilityMode::default())
+    }
+
+    pub fn new_with_durability(
+        db_directory: &Path,
+        blocksize: i32,
+        durability: DurabilityMode,
+    ) -> Self {
+        // a directory holding nothing but its configuration is still a new database
         let is_new = !db_directory.exists()
             || !db_directory.read_dir().unwrap().into_iter().any(|entry| {
                 entry.is_ok_and(|e| {
                     let name = e.file_name();
-                    name != OsStr::new(".") && name != OsStr::new("..")
+                    name != OsStr::new(".")
+                        && name != OsStr::new("..")
+                        && name != OsStr::new(CONFIG_FILE)
                 })
             });
         if is_new {
             let _ = create_dir(db_directory);
+            if durability != DurabilityMode::None {
+                if let Some(parent) = db_directory.parent() {
+                    let _ = sync_dir(parent);
+                }
+            }
         }
 
         for file in db_directory.read_dir().unwrap().into_iter() {
             let filepath = file.unwrap().file_name();
-            if filepath.to_string_lossy().starts_with("temp") {
+            if is_temp_file(&filepath.to_string_lossy()) {
                 let _ = remove_file(db_directory.join(filepath));
             }
         }
@@ -40,64 +96,251 @@ impl FileManager {
             blocksize,
             db_directory: db_directory.to_path_buf(),
             open_files: Mutex::new(HashMap::new()),
+            unsynced_files: Mutex::new(HashSet::new()),
+            durability,
+            log_level: LogLevel::default(),
             is_new,
         }
     }
 
-    pub fn read(&self, blk: &BlockId, p: &mut Page) -> Result<(), String> {
-        let blocksize = self.blocksize;
+    pub fn with_log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level
+    }
+
+    pub fn read(&self, blk: &BlockId, p: &mut Page) -> Result<(), PageReadException> {
+        self.read_with_lsn(blk, p).map(|_| ())
+    }
+
+    // Returns the page LSN stored with the block, 0 if it was never written.
+    pub fn read_with_lsn(&self, blk: &BlockId, p: &mut Page) -> Result<i64, PageReadException> {
         let binding = p.contents();
-        let mut contents = binding.lock().map_err(|_| "failed to get lock")?;
+        let mut contents = binding
+            .lock()
+            .map_err(|_| PageReadException::Io("failed to get lock".to_string()))?;
+
+        let f = self
+            .get_file(&blk.file_name())
+            .map_err(PageReadException::Io)?;
+        let mut block = vec![0u8; contents.len() + (PAGE_LSN_BYTES + CHECKSUM_BYTES) as usize];
+        Self::read_fully(&f, blk, self.block_offset(blk), &mut block)?;
+        let (page, lsn) = self.unseal(blk, &block)?;
+        contents.copy_from_slice(page);
+        Ok(lsn)
+    }
 
-        let f = self.get_file(&blk.file_name())?;
-        let offset = (blk.number() * blocksize) as u64;
+    // Reads `count` consecutive blocks starting at `first` with a single read and
+    // returns the contents and page LSN of each.
+    pub fn read_blocks(
+        &self,
+        first: &BlockId,
+        count: usize,
+    ) -> Result<Vec<(Vec<u8>, i64)>, PageReadException> {
+        let f = self
+            .get_file(&first.file_name())
+            .map_err(PageReadException::Io)?;
+        let physical = self.physical_block_size() as usize;
+        let mut run = vec![0u8; physical * count];
+        Self::read_fully(&f, first, self.block_offset(first), &mut run)?;
+        run.chunks(physical)
+            .enumerate()
+            .map(|(i, block)| {
+                let blk = BlockId::new(first.file_name(), first.number() + i as i32);
+                let (page, lsn) = self.unseal(&blk, block)?;
+                Ok((page.to_vec(), lsn))
+            })
+            .collect()
+    }
 
-        // Zero-fill the buffer first to handle potential short reads beyond EOF
-        contents.fill(0);
+    // Splits a block read from disk into its contents and page LSN.
+    fn unseal<'a>(
+        &self,
+        blk: &BlockId,
+        block: &'a [u8],
+    ) -> Result<(&'a [u8], i64), PageReadException> {
+        if !checksum::verify(block) {
+            return Err(PageReadException::Corrupt(blk.clone()));
+        }
+        let (page, trailer) =
+            block.split_at(block.len() - (PAGE_LSN_BYTES + CHECKSUM_BYTES) as usize);
+        let mut lsn = [0u8; PAGE_LSN_BYTES as usize];
+        lsn.copy_from_slice(&trailer[..PAGE_LSN_BYTES as usize]);
+        Ok((page, i64::from_be_bytes(lsn)))
+    }
 
-        match f.read_at(contents.as_mut_slice(), offset) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!(
-                "failed to read block {} at offset {}: {}",
-                blk, offset, e
-            )),
+    // Fills `buf` from `offset`; whatever lies beyond EOF is left zero-filled so
+    // that it reads as an empty block.
+    fn read_fully(
+        f: &File,
+        blk: &BlockId,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<(), PageReadException> {
+        let mut nread = 0;
+        while nread < buf.len() {
+            match f.read_at(&mut buf[nread..], offset + nread as u64) {
+                Ok(0) => break,
+                Ok(n) => nread += n,
+                Err(e) => {
+                    return Err(PageReadException::Io(format!(
+                        "failed to read block {} at offset {}: {}",
+                        blk, offset, e
+                    )))
+                }
+            }
         }
+        Ok(())
     }
 
+    // For blocks whose changes are not logged, such as the log's own blocks.
     pub fn write(&self, blk: &BlockId, p: &Page) -> Result<(), String> {
-        let blocksize = self.blocksize;
+        self.write_with_lsn(blk, p, 0)
+    }
+
+    // `lsn` is the log record of the latest change in the page; recovery only
+    // redoes records that are newer.
+    pub fn write_with_lsn(&self, blk: &BlockId, p: &Page, lsn: i64) -> Result<(), String> {
         let binding = p.contents();
-        let contents = binding.lock().map_err(|_| "failed to get lock")?;
+        let mut block = binding.lock().map_err(|_| "failed to get lock")?.clone();
+        block.extend_from_slice(&lsn.to_be_bytes());
 
         let f = self.get_file(&blk.file_name())?;
-        f.write_all_at(contents.as_slice(), (blk.number() * blocksize) as u64)
+        f.write_all_at(checksum::seal(&block).as_slice(), self.block_offset(blk))
             .map_err(|e| format!("failed to write content: {}", e))?;
+        self.mark_unsynced(&blk.file_name())?;
         Ok(())
     }
 
     pub fn append(&self, filename: &String) -> Result<BlockId, String> {
-        let blocksize = self.blocksize;
         let newblknum = self.len(filename)?;
         let blk = BlockId::new(filename.clone(), newblknum);
-        let b = vec![0u8; blocksize as usize];
+        let b = vec![0u8; self.physical_block_size() as usize];
 
         let f = self.get_file(&blk.file_name())?;
-        f.write_all_at(b.as_slice(), (blk.number() * blocksize) as u64)
+        f.write_all_at(b.as_slice(), self.block_offset(&blk))
             .map_err(|e| format!("failed to append content: {}", e))?;
+        self.mark_unsynced(filename)?;
 
         Ok(blk)
     }
 
-    pub fn len(&self, filename: &String) -> Result<i32, String> {
-        let blocksize = self.blocksize;
+    pub fn truncate(&self, filename: &String, numblocks: i32) -> Result<(), String> {
+        let f = self.get_file(filename)?;
+        f.set_len(numblocks as u64 * self.physical_block_size() as u64)
+            .map_err(|e| format!("failed to truncate file {}: {}", filename, e))?;
+        self.mark_unsynced(filename)?;
+        self.sync(filename)
+    }
+
+    // Forces the file's written contents to stable storage unless durability is off.
+    pub fn sync(&self, filename: &String) -> Result<(), String> {
+        if self.durability == DurabilityMode::None {
+            return Ok(());
+        }
+        // taken out first, so that a write made during the sync marks the file again
+        self.unsynced_files
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .remove(filename);
+        let res = self.get_file(filename).and_then(|f| {
+            f.sync_data()
+                .map_err(|e| format!("failed to sync file {}: {}", filename, e))
+        });
+        if res.is_err() {
+            self.unsynced_files
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .insert(filename.clone());
+        }
+        res
+    }
+
+    pub fn sync_written_files(&self) -> Result<(), String> {
+        let filenames: Vec<String> = self
+            .unsynced_files
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .iter()
+            .cloned()
+            .collect();
+        for filename in filenames.iter() {
+            self.sync(filename)?;
+        }
+        Ok(())
+    }
+
+    pub fn has_unsynced_writes(&self) -> Result<bool, String> {
+        Ok(!self
+            .unsynced_files
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .is_empty())
+    }
 
+    pub fn len(&self, filename: &String) -> Result<i32, String> {
         let f = self.get_file(filename)?;
         let len = f
             .metadata()
             .map_err(|_| "failed to access file's metadata")?
-            .len() as i32
-            / blocksize;
-        return Ok(len);
+            .len()
+            / self.physical_block_size() as u64;
+        return Ok(len as i32);
+    }
+
+    pub fn file_names(&self) -> Result<Vec<String>, String> {
+        let entries = self
+            .db_directory
+            .read_dir()
+            .map_err(|e| format!("failed to list {}: {}", self.db_directory.display(), e))?;
+        Ok(entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect())
+    }
+
+    // Closes the file and deletes it, or moves it to `archive_dir` if one is given.
+    pub fn discard_file(&self, filename: &String, archive_dir: Option<&Path>) -> Result<(), String> {
+        self.open_files
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .remove(filename);
+        self.unsynced_files
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .remove(filename);
+        let path = self.db_directory.join(filename);
+        match archive_dir {
+            Some(dir) => {
+                create_dir_all(dir)
+                    .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+                let target = dir.join(filename);
+                // the archive may be on another file system
+                if rename(&path, &target).is_err() {
+                    copy(&path, &target)
+                        .map_err(|e| format!("failed to archive {}: {}", filename, e))?;
+                    if self.durability != DurabilityMode::None {
+                        File::open(&target)
+                            .and_then(|f| f.sync_all())
+                            .map_err(|e| format!("failed to sync {}: {}", target.display(), e))?;
+                    }
+                    remove_file(&path)
+                        .map_err(|e| format!("failed to remove {}: {}", filename, e))?;
+                }
+                if self.durability != DurabilityMode::None {
+                    sync_dir(dir)?;
+                }
+            }
+            None => remove_file(&path)
+                .map_err(|e| format!("failed to remove {}: {}", filename, e))?,
+        }
+        if self.durability != DurabilityMode::None {
+            sync_dir(&self.db_directory)?;
+        }
+        Ok(())
     }
 
     pub fn is_new(&self) -> bool {
@@ -108,25 +351,60 @@ impl FileManager {
         self.blocksize
     }
 
+    pub fn durability(&self) -> DurabilityMode {
+        self.durability
+    }
+
+    // the size a block occupies on disk, including its page LSN and checksum trailer
+    pub fn physical_block_size(&self) -> i32 {
+        self.blocksize + PAGE_LSN_BYTES + CHECKSUM_BYTES
+    }
+
+    fn block_offset(&self, blk: &BlockId) -> u64 {
+        blk.number() as u64 * self.physical_block_size() as u64
+    }
+
+    fn mark_unsynced(&self, filename: &str) -> Result<(), String> {
+        if self.durability != DurabilityMode::None {
+            self.unsynced_files
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .insert(filename.to_string());
+        }
+        Ok(())
+    }
+
     fn get_file(&self, file_name: &str) -> Result<Arc<File>, String> {
         let mut open_files = self.open_files.lock().map_err(|_| "failed to get lock")?;
         if let Some(f) = open_files.get(file_name) {
             return Ok(f.clone());
         }
 
+        let path = self.db_directory.join(file_name);
+        let created = !path.exists();
         let f = Arc::new(
             OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
-                .open(self.db_directory.join(file_name))
+                .open(path)
                 .map_err(|e| format!("failed to open file {}: {}", file_name, e))?,
         );
+        // the new directory entry has to be durable too, not just the file contents
+        if created && self.durability != DurabilityMode::None {
+            sync_dir(&self.db_directory)?;
+        }
         open_files.insert(file_name.to_string(), f.clone());
         Ok(f)
     }
 }
 
+pub(crate) fn sync_dir(dir: &Path) -> Result<(), String> {
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .map_err(|e| format!("failed to sync directory {}: {}", dir.display(), e))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -167,6 +445,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_detects_corrupt_block() {
+        let filename = "temptest.db".to_string();
+        let blk = BlockId::new(filename.clone(), 1);
+        let (fm, dir) = setup();
+        let mut page = Page::new_from_blocksize(100);
+        page.set_int(0, 7).unwrap();
+        fm.write(&blk, &page).unwrap();
+
+        // a block that was appended but never written reads as zeros
+        fm.read(&BlockId::new(filename.clone(), 0), &mut page).unwrap();
+        assert_eq!(page.get_int(0).unwrap(), 0);
+
+        let mut bytes = std::fs::read(dir.path().join(&filename)).unwrap();
+        bytes[fm.physical_block_size() as usize + 2] ^= 0xff;
+        std::fs::write(dir.path().join(&filename), bytes).unwrap();
+        assert_eq!(
+            fm.read(&blk, &mut page).unwrap_err(),
+            PageReadException::Corrupt(blk.clone())
+        );
+    }
+
     #[test]
     fn test_append() {
         let filename = "temptest.db".to_string();
@@ -179,6 +479,34 @@ mod tests {
         assert_eq!(fm.len(&filename).unwrap(), 2);
     }
 
+    #[test]
+    fn test_truncate() {
+        let filename = "temptest.db".to_string();
+        let (fm, _dir) = setup();
+
+        for _ in 0..3 {
+            fm.append(&filename).unwrap();
+        }
+        fm.truncate(&filename, 1).unwrap();
+        assert_eq!(fm.len(&filename).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_sync_written_files() {
+        let filename = "temptest.db".to_string();
+        let (fm, _dir) = setup();
+
+        fm.append(&filename).unwrap();
+        assert!(fm.has_unsynced_writes().unwrap());
+        fm.sync_written_files().unwrap();
+        assert!(!fm.has_unsynced_writes().unwrap());
+
+        let temp_dir = TempDir::new().unwrap();
+        let fm = FileManager::new_with_durability(temp_dir.path(), 100, DurabilityMode::None);
+        fm.append(&filename).unwrap();
+        assert!(!fm.has_unsynced_writes().unwrap());
+    }
+
     #[test]
     fn test_len() {
         let (fm, _dir) = setup();
@@ -199,4 +527,22 @@ mod tests {
 
         assert!(dir.path().join(&filename).exists());
     }
+
+    #[test]
+    fn test_discard_file() {
+        let (fm, dir) = setup();
+        let first = "first.log".to_string();
+        let second = "second.log".to_string();
+        fm.append(&first).unwrap();
+        fm.append(&second).unwrap();
+        assert!(fm.file_names().unwrap().contains(&first));
+
+        fm.discard_file(&first, None).unwrap();
+        assert!(!dir.path().join(&first).exists());
+
+        let archive = dir.path().join("archive");
+        fm.discard_file(&second, Some(&archive)).unwrap();
+        assert!(!dir.path().join(&second).exists());
+        assert_eq!(std::fs::metadata(archive.join(&second)).unwrap().len(), fm.physical_block_size() as u64);
+    }
 }
@@ -1,3 +1,6 @@
 pub mod block_id;
+pub mod checksum;
+pub mod durability_mode;
 pub mod file_manager;
 pub mod page;
+pub mod superblock;
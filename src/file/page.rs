@@ -1,4 +1,4 @@
-use crate::constants::INTEGER_BYTES;
+use crate::constants::{INTEGER_BYTES, LONG_BYTES};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
@@ -42,11 +42,40 @@ impl Page {
         Ok(())
     }
 
+    pub fn get_long(&self, offset: usize) -> Result<i64, String> {
+        let bb = self.bb.lock().map_err(|_| "failed to get lock")?;
+        let arr: [u8; 8] = offset
+            .checked_add(LONG_BYTES as usize)
+            .and_then(|end| bb.get(offset..end))
+            .ok_or_else(|| {
+                format!(
+                    "Page::get_long boundary error: offset={}, buffer_len={}",
+                    offset,
+                    bb.len()
+                )
+            })?
+            .try_into()
+            .map_err(|_| "failed to convert slice")?;
+        Ok(i64::from_be_bytes(arr))
+    }
+
+    pub fn set_long(&mut self, offset: usize, n: i64) -> Result<(), String> {
+        self.bb.lock().map_err(|_| "failed to get lock")?[offset..offset + LONG_BYTES as usize]
+            .copy_from_slice(&n.to_be_bytes());
+        Ok(())
+    }
+
     pub fn get_bytes(&self, offset: usize) -> Result<Vec<u8>, String> {
-        let length = self.get_int(offset).unwrap() as usize;
-        return Ok(self.bb.lock().map_err(|_| "failed to get lock")?
-            [offset + INTEGER_BYTES as usize..offset + INTEGER_BYTES as usize + length]
-            .to_vec());
+        let length = self.get_int(offset)?;
+        let start = offset + INTEGER_BYTES as usize;
+        let bb = self.bb.lock().map_err(|_| "failed to get lock")?;
+        if length < 0 || start + length as usize > bb.len() {
+            return Err(format!(
+                "Page::get_bytes boundary error: offset={}, length={}, buffer_len={}",
+                offset, length, bb.len()
+            ));
+        }
+        Ok(bb[start..start + length as usize].to_vec())
     }
 
     pub fn set_bytes(&mut self, offset: usize, b: &Vec<u8>) -> Result<(), String> {
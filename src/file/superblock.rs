@@ -0,0 +1,584 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{read_dir, remove_file, rename, File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+
+use crate::{
+    constants::{CONFIG_FILE, INTEGER_BYTES, SUPERBLOCK_FILE},
+    file::{
+        checksum,
+        file_manager::{sync_dir, PAGE_LSN_BYTES},
+        page::Page,
+    },
+};
+
+pub const MAGIC: i32 = 0x53414249; // "SABI"
+pub const FORMAT_VERSION: i32 = 1;
+// Directories written before the superblock existed are treated as this version.
+const LEGACY_VERSION: i32 = 0;
+
+// Log record types of the legacy log format.
+const LEGACY_CHECKPOINT: i32 = 0;
+const LEGACY_COMMIT: i32 = 2;
+const LEGACY_ROLLBACK: i32 = 3;
+const LEGACY_SETINT: i32 = 4;
+const LEGACY_SETSTRING: i32 = 5;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = MAGIC_OFFSET + INTEGER_BYTES as usize;
+const BLOCKSIZE_OFFSET: usize = VERSION_OFFSET + INTEGER_BYTES as usize;
+const CREATED_AT_OFFSET: usize = BLOCKSIZE_OFFSET + INTEGER_BYTES as usize;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SuperblockException {
+    pub message: String,
+}
+
+impl SuperblockException {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SuperblockException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid database: {}", self.message)
+    }
+}
+
+impl std::error::Error for SuperblockException {}
+
+#[derive(Debug, Clone)]
+pub struct Superblock {
+    format_version: i32,
+    block_size: i32,
+    created_at: String,
+    log_file: String,
+}
+
+impl Superblock {
+    // Creates the superblock of a new database, or reads, validates and migrates
+    // the superblock of an existing one.
+    pub fn open_or_create(
+        db_directory: &Path,
+        is_new: bool,
+        blocksize: i32,
+        logfile: &str,
+    ) -> Result<Self, SuperblockException> {
+        let mut sb = match Self::read(db_directory)? {
+            Some(sb) => sb,
+            None if is_new => {
+                let sb = Superblock {
+                    format_version: FORMAT_VERSION,
+                    block_size: blocksize,
+                    created_at: Utc::now().to_rfc3339(),
+                    log_file: logfile.to_string(),
+                };
+                sb.write(db_directory)?;
+                return Ok(sb);
+            }
+            None => Superblock {
+                format_version: LEGACY_VERSION,
+                block_size: blocksize,
+                created_at: Utc::now().to_rfc3339(),
+                log_file: logfile.to_string(),
+            },
+        };
+
+        if sb.format_version > FORMAT_VERSION {
+            return Err(SuperblockException::new(&format!(
+                "format version {} is newer than the supported version {}",
+                sb.format_version, FORMAT_VERSION
+            )));
+        }
+        if sb.block_size != blocksize {
+            return Err(SuperblockException::new(&format!(
+                "the database was created with block size {} but is opened with block size {}",
+                sb.block_size, blocksize
+            )));
+        }
+        if sb.log_file != logfile {
+            return Err(SuperblockException::new(&format!(
+                "the database uses log file {} but is opened with log file {}",
+                sb.log_file, logfile
+            )));
+        }
+        if sb.format_version < FORMAT_VERSION {
+            sb.migrate(db_directory)?;
+        }
+        Ok(sb)
+    }
+
+    pub fn format_version(&self) -> i32 {
+        self.format_version
+    }
+
+    pub fn block_size(&self) -> i32 {
+        self.block_size
+    }
+
+    pub fn created_at(&self) -> String {
+        self.created_at.clone()
+    }
+
+    pub fn log_file(&self) -> String {
+        self.log_file.clone()
+    }
+
+    // Upgrades a legacy directory to the current format in one step. The
+    // superblock is written only once every file is converted, so an
+    // interrupted migration starts again from the legacy format and resumes
+    // with the files its journal does not list yet.
+    fn migrate(&mut self, db_directory: &Path) -> Result<(), SuperblockException> {
+        if self.format_version != LEGACY_VERSION {
+            return Err(SuperblockException::new(&format!(
+                "no migration from format version {}",
+                self.format_version
+            )));
+        }
+        self.migrate_from_legacy(db_directory)?;
+        self.write(db_directory)?;
+        let journal = Self::journal_path(db_directory, LEGACY_VERSION);
+        if journal.exists() {
+            remove_file(journal)
+                .map_err(|e| SuperblockException::new(&format!("migration failed: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    // A legacy directory has no record of its block size, so the best we can do is
+    // to check that every file is a whole number of blocks of the requested size.
+    // Its log cannot be redone since it has no after-images, but it never needs
+    // to be: legacy builds forced pages to disk at commit, so once the changes of
+    // unfinished transactions are undone the log is dropped and a new one starts.
+    // Every block then gets a page LSN older than any record of the new log and a
+    // checksum trailer. Every step can be repeated if interrupted.
+    fn migrate_from_legacy(&mut self, db_directory: &Path) -> Result<(), SuperblockException> {
+        let io_err = |e: std::io::Error| SuperblockException::new(&format!("migration failed: {}", e));
+        let blocksize = self.block_size as usize;
+
+        let logpath = db_directory.join(&self.log_file);
+        if logpath.exists() {
+            self.undo_unfinished(db_directory, &logpath)?;
+            remove_file(&logpath).map_err(io_err)?;
+        }
+
+        self.rewrite_files(db_directory, |name, bytes| {
+            if bytes.len() % blocksize != 0 {
+                return Err(SuperblockException::new(&format!(
+                    "file {} is not a multiple of block size {}",
+                    name, blocksize
+                )));
+            }
+            let mut ret = Vec::new();
+            for block in bytes.chunks(blocksize) {
+                let mut page = block.to_vec();
+                page.extend_from_slice(&[0u8; PAGE_LSN_BYTES as usize]);
+                ret.extend_from_slice(&checksum::seal(&page));
+            }
+            Ok(ret)
+        })?;
+        self.format_version = FORMAT_VERSION;
+        Ok(())
+    }
+
+    // Replaces every block file with its converted contents. Whether a file has
+    // been converted is never guessed from what it holds: a journal for the
+    // version being migrated lists each file whose new contents are complete,
+    // and a file listed there is at most left to be renamed into place.
+    fn rewrite_files<F>(&self, db_directory: &Path, convert: F) -> Result<(), SuperblockException>
+    where
+        F: Fn(&str, &[u8]) -> Result<Vec<u8>, SuperblockException>,
+    {
+        let io_err = |e: std::io::Error| SuperblockException::new(&format!("migration failed: {}", e));
+        let journal_path = Self::journal_path(db_directory, self.format_version);
+        let mut done = HashSet::new();
+        if journal_path.exists() {
+            let mut contents = String::new();
+            File::open(&journal_path)
+                .and_then(|mut f| f.read_to_string(&mut contents))
+                .map_err(io_err)?;
+            done.extend(contents.lines().map(str::to_string));
+        }
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&journal_path)
+            .map_err(io_err)?;
+
+        let mut names = Vec::new();
+        for entry in read_dir(db_directory).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if !path.is_file()
+                || name.starts_with(SUPERBLOCK_FILE)
+                || name == CONFIG_FILE
+                || name.ends_with(".new")
+            {
+                continue;
+            }
+            names.push(name.to_string());
+        }
+        for name in names {
+            let path = db_directory.join(&name);
+            let tmppath = db_directory.join(format!("{}.new", name));
+            if done.contains(&name) {
+                if tmppath.exists() {
+                    rename(&tmppath, &path).map_err(io_err)?;
+                }
+                continue;
+            }
+            let mut bytes = Vec::new();
+            File::open(&path)
+                .and_then(|mut f| f.read_to_end(&mut bytes))
+                .map_err(io_err)?;
+            let converted = convert(&name, &bytes)?;
+            let mut f = File::create(&tmppath).map_err(io_err)?;
+            f.write_all(&converted).map_err(io_err)?;
+            f.sync_all().map_err(io_err)?;
+            writeln!(journal, "{}", name).map_err(io_err)?;
+            journal.sync_all().map_err(io_err)?;
+            rename(&tmppath, &path).map_err(io_err)?;
+        }
+        sync_dir(db_directory).map_err(|e| SuperblockException::new(&e))?;
+        Ok(())
+    }
+
+    fn journal_path(db_directory: &Path, version: i32) -> PathBuf {
+        db_directory.join(format!("{}.migrating.{}", SUPERBLOCK_FILE, version))
+    }
+
+    // Restores the before-images of the transactions in a legacy log that
+    // neither committed nor rolled back, newest first and back to the last
+    // checkpoint, directly in the data files.
+    fn undo_unfinished(&self, db_directory: &Path, logpath: &Path) -> Result<(), SuperblockException> {
+        let to_err = |e: String| SuperblockException::new(&format!("migration failed: {}", e));
+        let blocksize = self.block_size as usize;
+        let bytes = std::fs::read(logpath).map_err(|e| to_err(e.to_string()))?;
+        if bytes.len() % blocksize != 0 {
+            return Err(SuperblockException::new(&format!(
+                "log file {} is not a multiple of block size {}",
+                self.log_file, self.block_size
+            )));
+        }
+
+        let mut finished = HashSet::new();
+        for block in bytes.chunks(blocksize).rev() {
+            let p = Page::new_from_bytes(block.to_vec());
+            let boundary = p.get_int(0).map_err(to_err)?;
+            // nothing before a block that was never written can be located
+            if boundary < INTEGER_BYTES || boundary as usize > blocksize {
+                return Ok(());
+            }
+            let mut pos = boundary as usize;
+            while pos < blocksize {
+                let Ok(rec) = p.get_bytes(pos) else {
+                    return Ok(());
+                };
+                pos += INTEGER_BYTES as usize + rec.len();
+                let rec = Page::new_from_bytes(rec);
+                let tpos = INTEGER_BYTES as usize;
+                match rec.get_int(0).map_err(to_err)? {
+                    LEGACY_CHECKPOINT => return Ok(()),
+                    LEGACY_COMMIT | LEGACY_ROLLBACK => {
+                        finished.insert(rec.get_int(tpos).map_err(to_err)?);
+                    }
+                    op @ (LEGACY_SETINT | LEGACY_SETSTRING) => {
+                        if finished.contains(&rec.get_int(tpos).map_err(to_err)?) {
+                            continue;
+                        }
+                        let fpos = tpos + INTEGER_BYTES as usize;
+                        let filename = rec.get_string(fpos).map_err(to_err)?;
+                        let bpos = fpos + Page::max_length(filename.len());
+                        let blknum = rec.get_int(bpos).map_err(to_err)?;
+                        let opos = bpos + INTEGER_BYTES as usize;
+                        let offset = rec.get_int(opos).map_err(to_err)? as usize;
+                        let vpos = opos + INTEGER_BYTES as usize;
+                        self.patch_block(db_directory, &filename, blknum, |page| {
+                            if op == LEGACY_SETINT {
+                                page.set_int(offset, rec.get_int(vpos)?)
+                            } else {
+                                page.set_string(offset, &rec.get_string(vpos)?)
+                            }
+                        })
+                        .map_err(to_err)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Changes one block of a legacy data file in place. Blocks that were never
+    // written have nothing to undo.
+    fn patch_block(
+        &self,
+        db_directory: &Path,
+        filename: &str,
+        blknum: i32,
+        change: impl FnOnce(&mut Page) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let path = db_directory.join(filename);
+        if !path.exists() {
+            return Ok(());
+        }
+        let blocksize = self.block_size as usize;
+        let offset = blknum as u64 * blocksize as u64;
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| e.to_string())?;
+        if f.metadata().map_err(|e| e.to_string())?.len() < offset + blocksize as u64 {
+            return Ok(());
+        }
+        let mut block = vec![0u8; blocksize];
+        f.read_exact_at(&mut block, offset).map_err(|e| e.to_string())?;
+        let mut page = Page::new_from_bytes(block);
+        change(&mut page)?;
+        let contents = page.contents().lock().map_err(|_| "failed to get lock")?.clone();
+        f.write_all_at(&contents, offset)
+            .and_then(|_| f.sync_data())
+            .map_err(|e| e.to_string())
+    }
+
+    fn read(db_directory: &Path) -> Result<Option<Self>, SuperblockException> {
+        let path = db_directory.join(SUPERBLOCK_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut bytes = Vec::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(|e| SuperblockException::new(&format!("failed to read superblock: {}", e)))?;
+        let p = Page::new_from_bytes(bytes);
+        let truncated = |_| SuperblockException::new("superblock is truncated");
+
+        if p.get_int(MAGIC_OFFSET).map_err(truncated)? != MAGIC {
+            return Err(SuperblockException::new(
+                "bad magic number, not a sabidb database",
+            ));
+        }
+        let format_version = p.get_int(VERSION_OFFSET).map_err(truncated)?;
+        let block_size = p.get_int(BLOCKSIZE_OFFSET).map_err(truncated)?;
+        let created_at = Self::read_string(&p, CREATED_AT_OFFSET)?;
+        let log_file = Self::read_string(&p, CREATED_AT_OFFSET + Page::max_length(created_at.len()))?;
+        Ok(Some(Superblock {
+            format_version,
+            block_size,
+            created_at,
+            log_file,
+        }))
+    }
+
+    fn read_string(p: &Page, offset: usize) -> Result<String, SuperblockException> {
+        let truncated = SuperblockException::new("superblock is truncated");
+        let len = p.get_int(offset).map_err(|_| truncated.clone())?;
+        let available = p.contents().lock().map_err(|_| truncated.clone())?.len();
+        if len < 0 || offset + Page::max_length(len as usize) > available {
+            return Err(truncated);
+        }
+        p.get_string(offset).map_err(|e| SuperblockException::new(&e))
+    }
+
+    // Written to a side file and renamed into place so a crash never leaves a
+    // half-written superblock behind.
+    fn write(&self, db_directory: &Path) -> Result<(), SuperblockException> {
+        let size = CREATED_AT_OFFSET
+            + Page::max_length(self.created_at.len())
+            + Page::max_length(self.log_file.len());
+        let mut p = Page::new_from_blocksize(size);
+        let to_err = |e: String| SuperblockException::new(&e);
+        p.set_int(MAGIC_OFFSET, MAGIC).map_err(to_err)?;
+        p.set_int(VERSION_OFFSET, self.format_version).map_err(to_err)?;
+        p.set_int(BLOCKSIZE_OFFSET, self.block_size).map_err(to_err)?;
+        p.set_string(CREATED_AT_OFFSET, &self.created_at).map_err(to_err)?;
+        p.set_string(
+            CREATED_AT_OFFSET + Page::max_length(self.created_at.len()),
+            &self.log_file,
+        )
+        .map_err(to_err)?;
+
+        let tmppath = db_directory.join(format!("{}.new", SUPERBLOCK_FILE));
+        let io_err =
+            |e: std::io::Error| SuperblockException::new(&format!("failed to write superblock: {}", e));
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmppath)
+            .map_err(io_err)?;
+        f.write_all(&p.contents().lock().map_err(|_| SuperblockException::new("failed to get lock"))?)
+            .map_err(io_err)?;
+        f.sync_all().map_err(io_err)?;
+        rename(&tmppath, db_directory.join(SUPERBLOCK_FILE)).map_err(io_err)?;
+        sync_dir(db_directory).map_err(|e| SuperblockException::new(&e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{write, File};
+
+    use tempfile::TempDir;
+
+    use super::{Superblock, FORMAT_VERSION, LEGACY_VERSION};
+    use crate::file::checksum;
+    use crate::{
+        constants::SUPERBLOCK_FILE,
+        file::{block_id::BlockId, file_manager::FileManager, page::Page},
+    };
+
+    #[test]
+    fn test_superblock_create_and_validate() {
+        let temp_dir = TempDir::new().unwrap();
+        let sb = Superblock::open_or_create(temp_dir.path(), true, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.format_version(), FORMAT_VERSION);
+
+        let sb = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.block_size(), 400);
+        assert_eq!(sb.log_file(), "simpledb.log");
+
+        let err = Superblock::open_or_create(temp_dir.path(), false, 800, "simpledb.log").unwrap_err();
+        assert!(err.message.contains("block size 400"));
+
+        write(temp_dir.path().join(SUPERBLOCK_FILE), b"garbage!").unwrap();
+        let err = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap_err();
+        assert!(err.message.contains("bad magic number"));
+    }
+
+    #[test]
+    fn test_superblock_migrates_legacy_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = File::create(temp_dir.path().join("simpledb.log")).unwrap();
+        log.set_len(800).unwrap();
+
+        let err = Superblock::open_or_create(temp_dir.path(), false, 300, "simpledb.log").unwrap_err();
+        assert!(err.message.contains("not a multiple of block size"));
+        assert!(!temp_dir.path().join(SUPERBLOCK_FILE).exists());
+
+        let sb = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.format_version(), FORMAT_VERSION);
+        assert!(temp_dir.path().join(SUPERBLOCK_FILE).exists());
+        assert!(!temp_dir.path().join("simpledb.log").exists());
+    }
+
+    #[test]
+    fn test_superblock_adds_trailers_to_legacy_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut block = vec![0u8; 400];
+        block[0..4].copy_from_slice(&42i32.to_be_bytes());
+        write(temp_dir.path().join("T.tbl"), block).unwrap();
+
+        let sb = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.format_version(), FORMAT_VERSION);
+
+        let fm = FileManager::new_from_blocksize(temp_dir.path(), 400);
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 1);
+        let mut p = Page::new_from_blocksize(400);
+        let lsn = fm.read_with_lsn(&BlockId::new("T.tbl".to_string(), 0), &mut p).unwrap();
+        assert_eq!((p.get_int(0).unwrap(), lsn), (42, 0));
+    }
+
+    #[test]
+    fn test_superblock_adds_trailers_to_empty_legacy_files() {
+        let temp_dir = TempDir::new().unwrap();
+        // 103 empty blocks, which would also pass for 100 converted ones
+        write(temp_dir.path().join("T.tbl"), vec![0u8; 103 * 400]).unwrap();
+
+        Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        let fm = FileManager::new_from_blocksize(temp_dir.path(), 400);
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 103);
+    }
+
+    #[test]
+    fn test_superblock_resumes_interrupted_migration() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut block = vec![0u8; 400];
+        block[0..4].copy_from_slice(&42i32.to_be_bytes());
+        write(temp_dir.path().join("T.tbl"), &block).unwrap();
+
+        // the new contents of T.tbl were journaled but not yet renamed into place
+        let mut converted = block.clone();
+        converted.extend_from_slice(&0i64.to_be_bytes());
+        write(temp_dir.path().join("T.tbl.new"), checksum::seal(&converted)).unwrap();
+        let journal = temp_dir
+            .path()
+            .join(format!("{}.migrating.{}", SUPERBLOCK_FILE, LEGACY_VERSION));
+        write(&journal, "T.tbl\n").unwrap();
+
+        let sb = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.format_version(), FORMAT_VERSION);
+        assert!(!journal.exists());
+        assert!(!temp_dir.path().join("T.tbl.new").exists());
+
+        let fm = FileManager::new_from_blocksize(temp_dir.path(), 400);
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 1);
+        let mut p = Page::new_from_blocksize(400);
+        fm.read(&BlockId::new("T.tbl".to_string(), 0), &mut p).unwrap();
+        assert_eq!(p.get_int(0).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_superblock_undoes_unfinished_legacy_transactions() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // T.tbl holds 42 at offset 0 and 8 at offset 4; the first value came from
+        // a transaction that committed, the second from one that never finished
+        let mut block = Page::new_from_blocksize(400);
+        block.set_int(0, 42).unwrap();
+        block.set_int(4, 8).unwrap();
+        let bytes = block.contents().lock().unwrap().clone();
+        write(temp_dir.path().join("T.tbl"), bytes).unwrap();
+
+        let setint = |txnum: i32, offset: i32, oldval: i32| {
+            let mut rec = Page::new_from_blocksize(4 * 5 + Page::max_length(5));
+            rec.set_int(0, 4).unwrap();
+            rec.set_int(4, txnum).unwrap();
+            rec.set_string(8, &"T.tbl".to_string()).unwrap();
+            rec.set_int(17, 0).unwrap();
+            rec.set_int(21, offset).unwrap();
+            rec.set_int(25, oldval).unwrap();
+            let bytes = rec.contents().lock().unwrap().clone();
+            bytes
+        };
+        let commit = |txnum: i32| {
+            let mut rec = Page::new_from_blocksize(8);
+            rec.set_int(0, 2).unwrap();
+            rec.set_int(4, txnum).unwrap();
+            let bytes = rec.contents().lock().unwrap().clone();
+            bytes
+        };
+        // records are packed from the end of the block, oldest last
+        let mut log = Page::new_from_blocksize(400);
+        let mut boundary = 400;
+        for rec in [setint(1, 0, 7), commit(1), setint(2, 4, 3)] {
+            boundary -= 4 + rec.len();
+            log.set_bytes(boundary, &rec).unwrap();
+        }
+        log.set_int(0, boundary as i32).unwrap();
+        let bytes = log.contents().lock().unwrap().clone();
+        write(temp_dir.path().join("simpledb.log"), bytes).unwrap();
+
+        let sb = Superblock::open_or_create(temp_dir.path(), false, 400, "simpledb.log").unwrap();
+        assert_eq!(sb.format_version(), FORMAT_VERSION);
+        assert!(!temp_dir.path().join("simpledb.log").exists());
+
+        let fm = FileManager::new_from_blocksize(temp_dir.path(), 400);
+        let mut p = Page::new_from_blocksize(400);
+        let lsn = fm.read_with_lsn(&BlockId::new("T.tbl".to_string(), 0), &mut p).unwrap();
+        assert_eq!(p.get_int(0).unwrap(), 42);
+        assert_eq!(p.get_int(4).unwrap(), 3);
+        assert_eq!(lsn, 0);
+    }
+}
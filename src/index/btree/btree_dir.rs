@@ -8,8 +8,6 @@ use crate::{
 use super::{btree_page::BTPage, dir_entry::DirEntry};
 
 pub struct BTreeDir {
-    tx: Arc<Mutex<Transaction>>,
-    layout: Layout,
     contents: BTPage,
     filename: String,
 }
@@ -19,31 +17,26 @@ impl BTreeDir {
         tx: Arc<Mutex<Transaction>>,
         blk: BlockId,
         layout: Layout,
+        exclusive: bool,
     ) -> Result<Self, String> {
         let filename = blk.file_name();
-        let contents = BTPage::new(tx.clone(), blk, layout.clone())?;
+        let contents = BTPage::new_latched(tx, blk, layout, exclusive)?;
 
-        Ok(BTreeDir {
-            tx,
-            layout,
-            contents,
-            filename,
-        })
+        Ok(BTreeDir { contents, filename })
     }
 
     pub fn close(&mut self) -> Result<(), String> {
         self.contents.close()
     }
 
-    pub fn search(&mut self, searchkey: &Constant) -> Result<i32, String> {
-        let mut childblk = self.find_child_block(searchkey)?;
-        while self.contents.get_flag()? > 0 {
-            self.contents.close()?;
-            self.contents = BTPage::new(self.tx.clone(), childblk, self.layout.clone())?;
-            childblk = self.find_child_block(searchkey)?;
-        }
+    // 0 for the directory pages right above the leaves.
+    pub fn level(&self) -> Result<i32, String> {
+        self.contents.get_flag()
+    }
 
-        Ok(childblk.number())
+    // Whether an entry from a split below fits without splitting the page.
+    pub fn is_safe(&self) -> Result<bool, String> {
+        self.contents.has_room()
     }
 
     pub fn make_new_root(&mut self, e: DirEntry) -> Result<(), String> {
@@ -59,22 +52,7 @@ impl BTreeDir {
         Ok(())
     }
 
-    pub fn insert(&mut self, e: DirEntry) -> Result<Option<DirEntry>, String> {
-        if self.contents.get_flag()? == 0 {
-            return self.insert_entry(e);
-        }
-        let childblk = self.find_child_block(&e.data_val())?;
-        let mut child = BTreeDir::new(self.tx.clone(), childblk, self.layout.clone())?;
-        let myentry = child.insert(e)?;
-        child.close()?;
-        if let Some(entry) = myentry {
-            self.insert_entry(entry)
-        } else {
-            Ok(None)
-        }
-    }
-
-    fn insert_entry(&mut self, e: DirEntry) -> Result<Option<DirEntry>, String> {
+    pub fn insert_entry(&mut self, e: DirEntry) -> Result<Option<DirEntry>, String> {
         let newslot = 1 + self.contents.find_slot_before(&e.data_val())?;
         self.contents
             .insert_dir(newslot, e.data_val(), e.block_number())?;
@@ -88,7 +66,7 @@ impl BTreeDir {
         return Ok(Some(DirEntry::new(splitval, newblk.number())));
     }
 
-    fn find_child_block(&self, searchkey: &Constant) -> Result<BlockId, String> {
+    pub fn find_child_block(&self, searchkey: &Constant) -> Result<BlockId, String> {
         let mut slot = self.contents.find_slot_before(searchkey)?;
         if slot + 1 < self.contents.get_num_recs()?
             && self.contents.get_data_val(slot + 1)?.eq(searchkey)
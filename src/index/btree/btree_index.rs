@@ -9,23 +9,27 @@ use crate::{
     query::constant::Constant,
     record::{
         layout::Layout,
+        rid::RID,
         schema::{
             field_type::{INTEGER, VARCHAR},
             Schema,
         },
     },
-    tx::transaction::Transaction,
+    tx::{concurrency::lock_mode::LockMode, transaction::Transaction},
 };
 
-use super::{btree_dir::BTreeDir, btree_leaf::BTreeLeaf, btree_page::BTPage};
+use super::{btree_dir::BTreeDir, btree_leaf::BTreeLeaf, btree_page::BTPage, leaf_entry::LeafEntry};
 
 pub struct BTreeIndex {
     tx: Arc<Mutex<Transaction>>,
+    idxname: String,
     dir_layout: Layout,
     leaf_layout: Layout,
     leaftbl: String,
-    leaf: Option<BTreeLeaf>,
     rootblk: BlockId,
+    // the records the last search found, and how many were returned
+    rids: Vec<RID>,
+    pos: usize,
 }
 
 impl BTreeIndex {
@@ -88,84 +92,221 @@ impl BTreeIndex {
 
         Ok(BTreeIndex {
             tx,
+            idxname,
             dir_layout,
             leaf_layout,
             leaftbl,
-            leaf: None,
             rootblk: rootblk,
+            rids: Vec::new(),
+            pos: 0,
         })
     }
+
+    // Adds the entry under latches in `tx`, a system transaction. Most inserts
+    // only change a leaf, so the directory is first read under shared latches;
+    // if the leaf may split, the insert starts over from the root with
+    // exclusive latches, kept on the pages the split may reach.
+    pub(crate) fn add_entry(
+        &self,
+        tx: &Arc<Mutex<Transaction>>,
+        dataval: &Constant,
+        datarid: &RID,
+    ) -> Result<(), String> {
+        let mut leaf = self.find_leaf(tx, dataval, true)?;
+        if leaf.is_safe()? {
+            leaf.insert(datarid.clone())?;
+            return leaf.close();
+        }
+        leaf.close()?;
+
+        // the directory pages a new entry may have to go into, top first
+        let mut path: Vec<BTreeDir> = Vec::new();
+        let mut dir = BTreeDir::new(tx.clone(), self.rootblk.clone(), self.dir_layout.clone(), true)?;
+        let leafblk = loop {
+            if dir.is_safe()? {
+                release(&mut path)?;
+            }
+            let childblk = dir.find_child_block(dataval)?;
+            let level = dir.level()?;
+            path.push(dir);
+            if level == 0 {
+                break BlockId::new(self.leaftbl.clone(), childblk.number());
+            }
+            dir = BTreeDir::new(tx.clone(), childblk, self.dir_layout.clone(), true)?;
+        };
+        let mut leaf = BTreeLeaf::new(tx.clone(), leafblk, self.leaf_layout.clone(), dataval.clone(), true)?;
+        if leaf.is_safe()? {
+            release(&mut path)?;
+        }
+        let mut entry = leaf.insert(datarid.clone())?;
+        leaf.close()?;
+        while let Some(mut dir) = path.pop() {
+            if let Some(e) = entry {
+                entry = dir.insert_entry(e)?;
+            }
+            // only a split of the root can be left over
+            if path.is_empty() {
+                if let Some(e) = entry.take() {
+                    dir.make_new_root(e)?;
+                }
+            }
+            dir.close()?;
+        }
+
+        Ok(())
+    }
+
+    // Removes the entry, if it is there, under latches in `tx`, a system
+    // transaction. Pages are never merged, so only the leaves change.
+    pub(crate) fn remove_entry(
+        &self,
+        tx: &Arc<Mutex<Transaction>>,
+        dataval: &Constant,
+        datarid: &RID,
+    ) -> Result<(), String> {
+        let mut leaf = self.find_leaf(tx, dataval, true)?;
+        leaf.delete(datarid.clone())?;
+        leaf.close()
+    }
+
+    // Goes down from the root to the leaf for the key, latching each page
+    // before letting go of the one above. Only the leaf may be latched
+    // exclusively.
+    fn find_leaf(
+        &self,
+        tx: &Arc<Mutex<Transaction>>,
+        key: &Constant,
+        exclusive: bool,
+    ) -> Result<BTreeLeaf, String> {
+        let mut dir = BTreeDir::new(tx.clone(), self.rootblk.clone(), self.dir_layout.clone(), false)?;
+        while dir.level()? > 0 {
+            let child = BTreeDir::new(tx.clone(), dir.find_child_block(key)?, self.dir_layout.clone(), false)?;
+            dir.close()?;
+            dir = child;
+        }
+        let leafblk = BlockId::new(self.leaftbl.clone(), dir.find_child_block(key)?.number());
+        let leaf = BTreeLeaf::new(tx.clone(), leafblk, self.leaf_layout.clone(), key.clone(), exclusive)?;
+        dir.close()?;
+        Ok(leaf)
+    }
+
+    fn leaf_entries(&self, tx: &Arc<Mutex<Transaction>>) -> Result<Vec<(Constant, RID)>, String> {
+        let size = tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .size(self.leaftbl.clone())?;
+        let mut entries = Vec::new();
+        for blknum in 0..size {
+            let blk = BlockId::new(self.leaftbl.clone(), blknum);
+            let mut page = BTPage::new_latched(tx.clone(), blk, self.leaf_layout.clone(), false)?;
+            let read = (0..page.get_num_recs()?)
+                .map(|slot| Ok((page.get_data_val(slot)?, page.get_data_rid(slot)?)))
+                .collect::<Result<Vec<_>, String>>();
+            page.close()?;
+            entries.extend(read?);
+        }
+        Ok(entries)
+    }
+
+    fn entry(&self, dataval: &Constant, datarid: &RID) -> Result<LeafEntry, String> {
+        let fldname = "dataval".to_string();
+        Ok(LeafEntry::new(
+            self.idxname.clone(),
+            self.leaf_layout.schema().field_type(&fldname)?,
+            self.leaf_layout.schema().length(&fldname)?,
+            dataval.clone(),
+            datarid.clone(),
+        ))
+    }
+
+    // Writers of a key only wait for serializable lookups of it, not for each
+    // other; the records they index are locked on their own.
+    fn lock_key(&self, dataval: &Constant) -> Result<(), String> {
+        self.tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .lock_key(&self.leaftbl, dataval, LockMode::IntentionExclusive)
+    }
+}
+
+fn release(path: &mut Vec<BTreeDir>) -> Result<(), String> {
+    for mut dir in path.drain(..) {
+        dir.close()?;
+    }
+    Ok(())
 }
 
 impl Index for BTreeIndex {
+    // The entries are read under latches, which are let go before the first
+    // one is returned. The records they lead to may have been deleted since,
+    // or be ones the transaction does not see yet; scans through the index
+    // check each record.
     fn before_first(&mut self, searchkey: &Constant) -> Result<(), String> {
         self.close()?;
-        let mut root = BTreeDir::new(
-            self.tx.clone(),
-            self.rootblk.clone(),
-            self.dir_layout.clone(),
-        )?;
-        let blknum = root.search(searchkey)?;
-        root.close()?;
-        let leafblk = BlockId::new(self.leaftbl.clone(), blknum);
-        self.leaf = Some(BTreeLeaf::new(
-            self.tx.clone(),
-            leafblk,
-            self.leaf_layout.clone(),
-            searchkey.clone(),
-        )?);
+        let tx = self.tx.clone();
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .lock_for_search(&self.leaftbl, searchkey)?;
+        let latched = tx.lock().map_err(|_| "failed to get lock")?.set_latched(true);
+        let rids = self.find_leaf(&tx, searchkey, false).and_then(|mut leaf| {
+            let rids = leaf.data_rids()?;
+            leaf.close()?;
+            Ok(rids)
+        });
+        tx.lock().map_err(|_| "failed to get lock")?.set_latched(latched);
+        self.rids = rids?;
         Ok(())
     }
 
     fn next(&mut self) -> Result<bool, String> {
-        self.leaf.as_mut().unwrap().next()
+        if self.pos >= self.rids.len() {
+            return Ok(false);
+        }
+        self.pos += 1;
+        Ok(true)
     }
 
-    fn get_data_rid(&self) -> Result<crate::record::rid::RID, String> {
-        self.leaf.as_ref().unwrap().get_data_rid()
+    fn get_data_rid(&self) -> Result<RID, String> {
+        self.rids
+            .get(self.pos.wrapping_sub(1))
+            .cloned()
+            .ok_or("no current index entry".to_string())
     }
 
-    fn insert(
-        &mut self,
-        dataval: &Constant,
-        datarid: crate::record::rid::RID,
-    ) -> Result<(), String> {
-        self.before_first(dataval)?;
-        let e = self.leaf.as_mut().unwrap().insert(datarid)?;
-        self.leaf.as_mut().unwrap().close()?;
-        if let Some(entry) = e {
-            let mut root = BTreeDir::new(
-                self.tx.clone(),
-                self.rootblk.clone(),
-                self.dir_layout.clone(),
-            )?;
-            let e2 = root.insert(entry)?;
-            if let Some(entry2) = e2 {
-                root.make_new_root(entry2)?;
-            }
-            root.close()?;
-        }
-
-        Ok(())
+    // The entry is added by a system transaction, so others can add theirs to
+    // the same pages before this transaction ends; a rollback removes it again.
+    fn insert(&mut self, dataval: &Constant, datarid: RID) -> Result<(), String> {
+        self.lock_key(dataval)?;
+        let entry = self.entry(dataval, &datarid)?;
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        tx.log_index_insert(&entry)?;
+        tx.system_op(|sys| self.add_entry(&sys, dataval, &datarid))
     }
 
-    fn delete(
-        &mut self,
-        dataval: &Constant,
-        datarid: crate::record::rid::RID,
-    ) -> Result<(), String> {
-        self.before_first(dataval)?;
-        let leaf = self.leaf.as_mut().unwrap();
-        leaf.delete(datarid)?;
-        leaf.close()?;
+    // The entry stays until the transaction commits and no snapshot still
+    // sees the record.
+    fn delete(&mut self, dataval: &Constant, datarid: RID) -> Result<(), String> {
+        self.lock_key(dataval)?;
+        let entry = self.entry(dataval, &datarid)?;
+        self.tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .retire_index_entry(Box::new(entry))
+    }
 
-        Ok(())
+    // Every leaf block holds entries, overflow blocks too; they are read one at
+    // a time under latches.
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>, String> {
+        let tx = self.tx.clone();
+        let latched = tx.lock().map_err(|_| "failed to get lock")?.set_latched(true);
+        let entries = self.leaf_entries(&tx);
+        tx.lock().map_err(|_| "failed to get lock")?.set_latched(latched);
+        entries
     }
 
     fn close(&mut self) -> Result<(), String> {
-        if let Some(leaf) = self.leaf.as_mut() {
-            leaf.close()?;
-        }
+        self.rids.clear();
+        self.pos = 0;
         Ok(())
     }
 }
@@ -16,6 +16,7 @@ pub struct BTreeLeaf {
     contents: BTPage,
     currentslot: i32,
     filename: String,
+    exclusive: bool,
 }
 
 impl BTreeLeaf {
@@ -24,9 +25,10 @@ impl BTreeLeaf {
         blk: BlockId,
         layout: Layout,
         search_key: Constant,
+        exclusive: bool,
     ) -> Result<Self, String> {
         let filename = blk.file_name();
-        let contents = BTPage::new(tx.clone(), blk, layout.clone())?;
+        let contents = BTPage::new_latched(tx.clone(), blk, layout.clone(), exclusive)?;
         let currentslot = contents.find_slot_before(&search_key)?;
         Ok(BTreeLeaf {
             tx: tx,
@@ -35,6 +37,7 @@ impl BTreeLeaf {
             contents: contents,
             currentslot: currentslot,
             filename: filename,
+            exclusive,
         })
     }
 
@@ -61,6 +64,26 @@ impl BTreeLeaf {
         self.contents.get_data_rid(self.currentslot)
     }
 
+    // The records the search key leads to, each once.
+    pub fn data_rids(&mut self) -> Result<Vec<RID>, String> {
+        let mut rids = Vec::new();
+        while self.next()? {
+            let rid = self.get_data_rid()?;
+            if !rids.contains(&rid) {
+                rids.push(rid);
+            }
+        }
+        Ok(rids)
+    }
+
+    // Whether inserting the search key leaves the directory above as it is.
+    pub fn is_safe(&self) -> Result<bool, String> {
+        let overflow = self.contents.get_flag()? >= 0
+            && self.contents.get_data_val(0)?.partial_cmp(&self.search_key)
+                == Some(std::cmp::Ordering::Greater);
+        Ok(!overflow && self.contents.has_room()?)
+    }
+
     pub fn delete(&mut self, datarid: RID) -> Result<(), String> {
         while self.next()? {
             if self.get_data_rid()?.eq(&datarid) {
@@ -124,9 +147,10 @@ impl BTreeLeaf {
         if !self.search_key.eq(&firstkey) || flag < 0 {
             return Ok(false);
         }
-        self.contents.close()?;
         let nextblk = BlockId::new(self.filename.clone(), flag);
-        self.contents = BTPage::new(self.tx.clone(), nextblk, self.layout.clone())?;
+        let next = BTPage::new_latched(self.tx.clone(), nextblk, self.layout.clone(), self.exclusive)?;
+        self.contents.close()?;
+        self.contents = next;
         self.currentslot = 0;
 
         Ok(true)
@@ -1,6 +1,10 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
+    buffer::latch_table::PageLatch,
     constants::INTEGER_BYTES,
     file::block_id::BlockId,
     query::constant::Constant,
@@ -16,6 +20,7 @@ pub struct BTPage {
     tx: Arc<Mutex<Transaction>>,
     currentblk: Option<BlockId>,
     layout: Layout,
+    latch: RefCell<Option<PageLatch>>,
 }
 
 impl BTPage {
@@ -31,9 +36,28 @@ impl BTPage {
             tx: tx,
             currentblk: Some(currentblk),
             layout: layout,
+            latch: RefCell::new(None),
         })
     }
 
+    // Latches the page before pinning it. A shared latch goes when the page is
+    // closed; an exclusive one is handed to the transaction once the page is
+    // changed, and goes when it ends.
+    pub fn new_latched(
+        tx: Arc<Mutex<Transaction>>,
+        currentblk: BlockId,
+        layout: Layout,
+        exclusive: bool,
+    ) -> Result<Self, String> {
+        let latch = tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .latch(&currentblk, exclusive)?;
+        let page = BTPage::new(tx, currentblk, layout)?;
+        page.latch.replace(Some(latch));
+        Ok(page)
+    }
+
     pub fn find_slot_before(&self, search_key: &Constant) -> Result<i32, String> {
         let mut slot = 0;
         while slot < self.get_num_recs()?
@@ -54,6 +78,7 @@ impl BTPage {
         }
 
         self.currentblk = None;
+        self.latch.replace(None);
         Ok(())
     }
 
@@ -66,6 +91,17 @@ impl BTPage {
                 .block_size())
     }
 
+    // Whether one more record leaves the page short of full, so that inserting
+    // it does not split the page.
+    pub fn has_room(&self) -> Result<bool, String> {
+        Ok(self.slotpos(self.get_num_recs()? + 2)?
+            <= self
+                .tx
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .block_size()?)
+    }
+
     pub fn split(&self, splitpos: i32, flag: i32) -> Result<BlockId, String> {
         let newblk = self.append_new(flag)?;
         let mut newpage = BTPage::new(self.tx.clone(), newblk.clone(), self.layout.clone())?;
@@ -87,18 +123,30 @@ impl BTPage {
     }
 
     pub fn set_flag(&self, val: i32) -> Result<(), String> {
-        self.tx.lock().map_err(|_| "failed to get lock")?.set_int(
-            &self.currentblk.clone().unwrap(),
-            0,
-            val,
-            true,
-        )
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.keep_latch(&tx)?;
+        tx.set_int(&self.currentblk.clone().unwrap(), 0, val, true)
+    }
+
+    // Other transactions must not change the page before the change made to it
+    // commits.
+    fn keep_latch(&self, tx: &Transaction) -> Result<(), String> {
+        let mut latch = self.latch.borrow_mut();
+        if latch.as_ref().is_some_and(|l| l.is_exclusive()) {
+            tx.hold_latch(latch.take().unwrap())?;
+        }
+        Ok(())
     }
 
     pub fn append_new(&self, flag: i32) -> Result<BlockId, String> {
-        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
-        let blk = tx.append(self.currentblk.clone().unwrap().file_name())?;
+        let blk = self
+            .tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .append(self.currentblk.clone().unwrap().file_name())?;
+        self.tx.lock().map_err(|_| "failed to get lock")?.pin(&blk)?;
         self.format(&blk, flag)?;
+        self.tx.lock().map_err(|_| "failed to get lock")?.unpin(&blk)?;
         Ok(blk)
     }
 
@@ -229,20 +277,16 @@ impl BTPage {
 
     fn set_int(&self, slot: i32, fldname: String, val: i32) -> Result<(), String> {
         let pos = self.fldpos(slot, fldname)?;
-        self.tx.lock().map_err(|_| "failed to get lock")?.set_int(
-            &self.currentblk.clone().unwrap(),
-            pos as usize,
-            val,
-            true,
-        )
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.keep_latch(&tx)?;
+        tx.set_int(&self.currentblk.clone().unwrap(), pos as usize, val, true)
     }
 
     fn set_string(&self, slot: i32, fldname: String, val: String) -> Result<(), String> {
         let pos = self.fldpos(slot, fldname)?;
-        self.tx
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .set_string(&self.currentblk.clone().unwrap(), pos as usize, val, true)
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.keep_latch(&tx)?;
+        tx.set_string(&self.currentblk.clone().unwrap(), pos as usize, val, true)
     }
 
     fn set_val(&self, slot: i32, fldname: String, val: Constant) -> Result<(), String> {
@@ -259,12 +303,9 @@ impl BTPage {
     }
 
     fn set_num_recs(&self, n: i32) -> Result<(), String> {
-        self.tx.lock().map_err(|_| "failed to get lock")?.set_int(
-            &self.currentblk.clone().unwrap(),
-            INTEGER_BYTES as usize,
-            n,
-            true,
-        )
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.keep_latch(&tx)?;
+        tx.set_int(&self.currentblk.clone().unwrap(), INTEGER_BYTES as usize, n, true)
     }
 
     fn insert(&self, slot: i32) -> Result<(), String> {
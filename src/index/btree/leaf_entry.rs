@@ -0,0 +1,63 @@
+use crate::{
+    metadata::index_info,
+    query::constant::Constant,
+    record::rid::RID,
+    tx::{index_entry::IndexEntry, transaction::Transaction},
+};
+
+use super::btree_index::BTreeIndex;
+
+// An entry of a B-tree index, with what it takes to open the index again.
+#[derive(Debug, Clone)]
+pub struct LeafEntry {
+    idxname: String,
+    fldtype: i32,
+    fldlen: i32,
+    dataval: Constant,
+    datarid: RID,
+}
+
+impl LeafEntry {
+    pub fn new(idxname: String, fldtype: i32, fldlen: i32, dataval: Constant, datarid: RID) -> Self {
+        LeafEntry {
+            idxname,
+            fldtype,
+            fldlen,
+            dataval,
+            datarid,
+        }
+    }
+}
+
+impl IndexEntry for LeafEntry {
+    fn idx_name(&self) -> String {
+        self.idxname.clone()
+    }
+
+    fn field_type(&self) -> i32 {
+        self.fldtype
+    }
+
+    fn field_length(&self) -> i32 {
+        self.fldlen
+    }
+
+    fn data_val(&self) -> Constant {
+        self.dataval.clone()
+    }
+
+    fn data_rid(&self) -> RID {
+        self.datarid.clone()
+    }
+
+    fn remove(&self, tx: &Transaction) -> Result<(), String> {
+        let layout = index_info::index_layout(self.fldtype, self.fldlen)?;
+        tx.system_op(|sys| {
+            BTreeIndex::new(sys.clone(), self.idxname.clone(), layout)?.remove_entry(
+                &sys,
+                &self.dataval,
+                &self.datarid,
+            )
+        })
+    }
+}
@@ -3,3 +3,4 @@ pub mod btree_index;
 mod btree_leaf;
 mod btree_page;
 pub(crate) mod dir_entry;
+pub mod leaf_entry;
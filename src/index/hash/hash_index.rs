@@ -75,6 +75,21 @@ impl Index for HashIndex {
         Ok(())
     }
 
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>, String> {
+        self.close()?;
+        let mut entries = Vec::new();
+        for bucket in 0..NUM_BUCKETS {
+            let tblname = format!("{}{}", self.idxname, bucket);
+            let mut ts = TableScan::new(self.tx.clone(), tblname, self.layout.clone())?;
+            while ts.next()? {
+                let rid = RID::new(ts.get_int(&"block".to_string())?, ts.get_int(&"id".to_string())?);
+                entries.push((ts.get_val(&"dataval".to_string())?, rid));
+            }
+            ts.close()?;
+        }
+        Ok(entries)
+    }
+
     fn close(&mut self) -> Result<(), String> {
         if let Some(ref mut ts) = self.ts {
             ts.close()?;
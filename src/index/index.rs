@@ -6,20 +6,37 @@ pub(crate) trait Index {
     fn get_data_rid(&self) -> Result<RID, String>;
     fn insert(&mut self, dataval: &Constant, datarid: RID) -> Result<(), String>;
     fn delete(&mut self, dataval: &Constant, datarid: RID) -> Result<(), String>;
+    // Every entry in the index, for maintenance such as VACUUM.
+    fn entries(&mut self) -> Result<Vec<(Constant, RID)>, String>;
     fn close(&mut self) -> Result<(), String>;
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+    };
 
     use tempfile::TempDir;
 
     use crate::{
-        plan::{plan::Plan, table_plan::TablePlan},
+        plan::{plan::Plan, planner::Planner, table_plan::TablePlan},
         query::constant::Constant,
-        server::simple_db::SimpleDB,
-        testlib::helper::create_student_data,
+        record::rid::RID,
+        server::{
+            db_options::{QueryPlannerKind, UpdatePlannerKind},
+            simple_db::{SimpleDB, SimpleDBBuilder},
+        },
+        testlib::helper::{count_rows, create_student_data},
+        tx::{
+            concurrency::isolation_level::IsolationLevel, index_entry::IndexEntry,
+            transaction::Transaction,
+        },
     };
 
     #[test]
@@ -160,4 +177,256 @@ mod tests {
 
         tx.lock().unwrap().commit().unwrap();
     }
+
+    fn indexed_db(builder: SimpleDBBuilder) -> (SimpleDB, Planner) {
+        let db = builder
+            .query_planner(QueryPlannerKind::Heuristic)
+            .update_planner(UpdatePlannerKind::Index)
+            .build()
+            .unwrap();
+        let mut planner = db.planner.clone().unwrap();
+        let tx = db.new_tx();
+        if !db.file_manager().len(&"T.tbl".to_string()).is_ok_and(|n| n > 0) {
+            planner
+                .execute_update("create table T(A int, B int)", tx.clone())
+                .unwrap();
+            planner
+                .execute_update("create index a_idx on T(A)", tx.clone())
+                .unwrap();
+        }
+        tx.lock().unwrap().commit().unwrap();
+        (db, planner)
+    }
+
+    fn count(planner: &Planner, tx: &Arc<Mutex<Transaction>>, a: i32) -> i32 {
+        count_rows(planner, &format!("select B from T where A = {}", a), tx)
+    }
+
+    // the entries in the index for the key, whether their records exist or not
+    fn entries(db: &SimpleDB, tx: &Arc<Mutex<Transaction>>, a: i32) -> i32 {
+        let indexes = db
+            .metadata_manager()
+            .lock()
+            .unwrap()
+            .get_index_info("T".to_string(), tx.clone())
+            .unwrap();
+        let idx = indexes.get("A").unwrap().open().unwrap();
+        let mut idx = idx.lock().unwrap();
+        idx.before_first(&Constant::new_from_i32(a)).unwrap();
+        let mut n = 0;
+        while idx.next().unwrap() {
+            n += 1;
+        }
+        idx.close().unwrap();
+        n
+    }
+
+    #[test]
+    fn test_index_entries_do_not_wait_for_transactions() {
+        let temp_dir = TempDir::new().unwrap();
+        let (db, mut planner) = indexed_db(SimpleDB::builder(temp_dir.path()).lock_timeout_ms(200));
+        let tx = db.new_tx();
+        for i in 0..20 {
+            let cmd = format!("insert into T(A,B) values({}, {})", i, i);
+            planner.execute_update(&cmd, tx.clone()).unwrap();
+        }
+        tx.lock().unwrap().commit().unwrap();
+
+        // both go into the same leaf, neither transaction has ended
+        let tx1 = db.new_tx();
+        let tx2 = db.new_tx();
+        planner
+            .execute_update("insert into T(A,B) values(100, 1)", tx1.clone())
+            .unwrap();
+        planner
+            .execute_update("insert into T(A,B) values(101, 2)", tx2.clone())
+            .unwrap();
+        let reader = db.new_tx();
+        reader
+            .lock()
+            .unwrap()
+            .set_isolation_level(IsolationLevel::RepeatableRead)
+            .unwrap();
+        assert_eq!(count(&planner, &reader, 101), 0);
+
+        // the rollback takes out tx1's entry and leaves tx2's
+        tx1.lock().unwrap().rollback().unwrap();
+        tx2.lock().unwrap().commit().unwrap();
+        let tx = db.new_tx();
+        assert_eq!(entries(&db, &tx, 100), 0);
+        assert_eq!(count(&planner, &tx, 101), 1);
+        assert_eq!(count(&planner, &reader, 101), 0);
+
+        // the entry of a deleted record stays while a snapshot sees the record
+        planner
+            .execute_update("delete from T where A = 5", tx.clone())
+            .unwrap();
+        assert_eq!(count(&planner, &tx, 5), 0);
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(count(&planner, &reader, 5), 1);
+        reader.lock().unwrap().commit().unwrap();
+        let tx = db.new_tx();
+        assert_eq!(entries(&db, &tx, 5), 0);
+        tx.lock().unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_inserts_split_shared_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let (db, planner) = indexed_db(SimpleDB::builder(temp_dir.path()).block_size(400).buffer_size(64));
+        let threads = 4;
+        let per_thread = 150;
+        thread::scope(|s| {
+            for t in 0..threads {
+                let (db, mut planner) = (&db, planner.clone());
+                s.spawn(move || {
+                    for i in 0..per_thread {
+                        let cmd = format!("insert into T(A,B) values({}, {})", i * threads + t, t);
+                        let tx = db.new_tx();
+                        planner.execute_update(&cmd, tx.clone()).unwrap();
+                        tx.lock().unwrap().commit().unwrap();
+                    }
+                });
+            }
+        });
+
+        let tx = db.new_tx();
+        for a in 0..threads * per_thread {
+            assert_eq!(entries(&db, &tx, a), 1);
+        }
+        assert_eq!(db.buffer_manager().latched_pages(), 0);
+        tx.lock().unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_recovery_removes_entries_of_unfinished_transactions() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let (db, mut planner) = indexed_db(SimpleDB::builder(temp_dir.path()).block_size(400));
+            let tx = db.new_tx();
+            planner
+                .execute_update("insert into T(A,B) values(1, 1)", tx.clone())
+                .unwrap();
+            tx.lock().unwrap().commit().unwrap();
+
+            // splits the leaf, which another transaction then adds to
+            let tx1 = db.new_tx();
+            for i in 100..125 {
+                let cmd = format!("insert into T(A,B) values({}, 0)", i);
+                planner.execute_update(&cmd, tx1.clone()).unwrap();
+            }
+            let tx2 = db.new_tx();
+            planner
+                .execute_update("insert into T(A,B) values(2, 2)", tx2.clone())
+                .unwrap();
+            tx2.lock().unwrap().commit().unwrap();
+            db.buffer_manager().flush_all_dirty().unwrap();
+        }
+
+        let (db, planner) = indexed_db(SimpleDB::builder(temp_dir.path()).block_size(400));
+        let tx = db.new_tx();
+        for i in 100..125 {
+            assert_eq!(entries(&db, &tx, i), 0);
+        }
+        assert_eq!(count(&planner, &tx, 1), 1);
+        assert_eq!(count(&planner, &tx, 2), 1);
+        tx.lock().unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_sweeps_entries_of_deleted_records() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let (db, mut planner) = indexed_db(SimpleDB::builder(temp_dir.path()));
+            let tx = db.new_tx();
+            for i in 0..5 {
+                let cmd = format!("insert into T(A,B) values({}, {})", i, i);
+                planner.execute_update(&cmd, tx.clone()).unwrap();
+            }
+            tx.lock().unwrap().commit().unwrap();
+
+            // the reader's snapshot holds the entry back when the crash comes
+            let reader = db.new_tx();
+            reader
+                .lock()
+                .unwrap()
+                .set_isolation_level(IsolationLevel::RepeatableRead)
+                .unwrap();
+            assert_eq!(count(&planner, &reader, 3), 1);
+            let tx = db.new_tx();
+            planner
+                .execute_update("delete from T where A = 3", tx.clone())
+                .unwrap();
+            tx.lock().unwrap().commit().unwrap();
+            db.buffer_manager().flush_all_dirty().unwrap();
+        }
+
+        let (db, mut planner) = indexed_db(SimpleDB::builder(temp_dir.path()));
+        let tx = db.new_tx();
+        assert_eq!(entries(&db, &tx, 3), 1);
+        planner.execute_update("vacuum T", tx.clone()).unwrap();
+        tx.lock().unwrap().commit().unwrap();
+        let tx = db.new_tx();
+        assert_eq!(entries(&db, &tx, 3), 0);
+        for i in [0, 1, 2, 4] {
+            assert_eq!(entries(&db, &tx, i), 1);
+            assert_eq!(count(&planner, &tx, i), 1);
+        }
+        tx.lock().unwrap().commit().unwrap();
+    }
+
+    // an entry whose first removal fails
+    #[derive(Debug)]
+    struct FlakyEntry(Arc<AtomicUsize>);
+
+    impl IndexEntry for FlakyEntry {
+        fn idx_name(&self) -> String {
+            "flaky".to_string()
+        }
+
+        fn field_type(&self) -> i32 {
+            0
+        }
+
+        fn field_length(&self) -> i32 {
+            0
+        }
+
+        fn data_val(&self) -> Constant {
+            Constant::new_from_i32(0)
+        }
+
+        fn data_rid(&self) -> RID {
+            RID::new(0, 0)
+        }
+
+        fn remove(&self, _tx: &Transaction) -> Result<(), String> {
+            match self.0.fetch_add(1, Ordering::SeqCst) {
+                0 => Err("failed to remove".to_string()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_removal_does_not_fail_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new(temp_dir.path());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let tx = db.new_tx();
+        tx.lock()
+            .unwrap()
+            .retire_index_entry(Box::new(FlakyEntry(attempts.clone())))
+            .unwrap();
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // the entry is kept and the next commit removes it
+        let tx = db.new_tx();
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        let tx = db.new_tx();
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
 }
@@ -46,6 +46,7 @@ impl Plan for IndexJoinPlan {
             idx,
             self.joinfield.clone(),
             Arc::new(Mutex::new(ts.clone())),
+            self.ii.field_name(),
         )?)))
     }
 
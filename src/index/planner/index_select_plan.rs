@@ -35,6 +35,7 @@ impl Plan for IndexSelectPlan {
             ts.clone(),
             idx,
             self.val.clone(),
+            self.ii.field_name(),
         )?)))
     }
     fn blocks_accessed(&self) -> Result<i32, String> {
@@ -5,6 +5,7 @@ use crate::{
     plan::{
         plan::Plan, select_plan::SelectPlan, table_plan::TablePlan, update_planner::UpdatePlanner,
     },
+    record::table_compactor::vacuum,
 };
 
 pub struct IndexUpdatePlanner {
@@ -200,4 +201,12 @@ impl UpdatePlanner for IndexUpdatePlanner {
             )?;
         Ok(0)
     }
+    fn execute_vacuum(
+        &self,
+        data: crate::parse::vacuum_data::VacuumData,
+        tx: std::sync::Arc<std::sync::Mutex<crate::tx::transaction::Transaction>>,
+    ) -> Result<i32, String> {
+        let mdm = self.mdm.lock().map_err(|_| "failed to get lock")?;
+        vacuum(&mdm, data.table_name(), tx)
+    }
 }
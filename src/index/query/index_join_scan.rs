@@ -12,6 +12,8 @@ pub struct IndexJoinScan {
     idx: Arc<Mutex<dyn Index>>,
     joinfield: String,
     rhs: Arc<Mutex<TableScan>>,
+    // the field of the right-hand table the index is on
+    idxfield: String,
 }
 
 impl IndexJoinScan {
@@ -20,12 +22,14 @@ impl IndexJoinScan {
         idx: Arc<Mutex<dyn Index>>,
         joinfield: String,
         rhs: Arc<Mutex<TableScan>>,
+        idxfield: String,
     ) -> Result<Self, String> {
         let mut ijs = IndexJoinScan {
             lhs: lhs,
             idx: idx,
             joinfield: joinfield,
             rhs: rhs,
+            idxfield,
         };
         ijs.before_first()?;
         Ok(ijs)
@@ -55,19 +59,26 @@ impl Scan for IndexJoinScan {
         Ok(())
     }
 
+    // Like an index select, skips the entries whose record is gone, not seen
+    // yet, or no longer has the value.
     fn next(&mut self) -> Result<bool, String> {
         loop {
-            if self.idx.lock().map_err(|_| "failed to get lock")?.next()? {
-                self.rhs
+            while self.idx.lock().map_err(|_| "failed to get lock")?.next()? {
+                let rid = self
+                    .idx
                     .lock()
                     .map_err(|_| "failed to get lock")?
-                    .move_to_rid(
-                        self.idx
-                            .lock()
-                            .map_err(|_| "failed to get lock")?
-                            .get_data_rid()?,
-                    )?;
-                return Ok(true);
+                    .get_data_rid()?;
+                let mut rhs = self.rhs.lock().map_err(|_| "failed to get lock")?;
+                rhs.move_to_rid(rid)?;
+                let searchkey = self
+                    .lhs
+                    .lock()
+                    .map_err(|_| "failed to get lock")?
+                    .get_val(&self.joinfield)?;
+                if rhs.is_used()? && rhs.get_val(&self.idxfield)? == searchkey {
+                    return Ok(true);
+                }
             }
             if !self.lhs.lock().map_err(|_| "failed to get lock")?.next()? {
                 return Ok(false);
@@ -11,6 +11,7 @@ pub struct IndexSelectScan {
     ts: TableScan,
     idx: Arc<Mutex<dyn Index>>,
     val: Constant,
+    fldname: String,
 }
 
 impl IndexSelectScan {
@@ -18,8 +19,14 @@ impl IndexSelectScan {
         ts: TableScan,
         idx: Arc<Mutex<dyn Index>>,
         val: Constant,
+        fldname: String,
     ) -> Result<Self, String> {
-        let mut iss = IndexSelectScan { ts, idx, val };
+        let mut iss = IndexSelectScan {
+            ts,
+            idx,
+            val,
+            fldname,
+        };
         iss.before_first()?;
         Ok(iss)
     }
@@ -33,17 +40,21 @@ impl Scan for IndexSelectScan {
             .before_first(&self.val)
     }
 
+    // An index entry may outlive its record, or lead to one the transaction
+    // does not see yet; only records that have the value count.
     fn next(&mut self) -> Result<bool, String> {
-        let ok = self.idx.lock().map_err(|_| "failed to get lock")?.next()?;
-        if ok {
+        while self.idx.lock().map_err(|_| "failed to get lock")?.next()? {
             let rid = self
                 .idx
                 .lock()
                 .map_err(|_| "failed to get lock")?
                 .get_data_rid()?;
             self.ts.move_to_rid(rid)?;
+            if self.ts.is_used()? && self.ts.get_val(&self.fldname)? == self.val {
+                return Ok(true);
+            }
         }
-        return Ok(ok);
+        Ok(false)
     }
 
     fn get_int(&self, fldname: &String) -> Result<i32, String> {
@@ -0,0 +1,146 @@
+use std::{
+    sync::{Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::file::file_manager::FileManager;
+
+use super::log_manager::LogManager;
+
+#[derive(Debug)]
+struct GroupState {
+    // every record up to this LSN is on disk
+    durable_lsn: i64,
+    leading: bool,
+    commits: u64,
+    flushes: u64,
+}
+
+// Lets concurrent committers share log flushes. The first one to arrive leads:
+// it waits `window` for others to append their COMMIT records, writes the log
+// page once for all of them and syncs it with the log latch released, so the
+// next batch can append in the meantime. The others wait to be woken, and one
+// of them leads the next batch if their records came too late for this one.
+#[derive(Debug)]
+pub struct GroupCommit {
+    state: Mutex<GroupState>,
+    flushed: Condvar,
+    window: Duration,
+}
+
+impl GroupCommit {
+    pub fn new(window: Duration, durable_lsn: i64) -> Self {
+        GroupCommit {
+            state: Mutex::new(GroupState {
+                durable_lsn,
+                leading: false,
+                commits: 0,
+                flushes: 0,
+            }),
+            flushed: Condvar::new(),
+            window,
+        }
+    }
+
+    // Returns once the record at `lsn` is on disk.
+    pub fn commit(&self, fm: &FileManager, lm: &Mutex<LogManager>, lsn: i64) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.commits += 1;
+        loop {
+            if st.durable_lsn >= lsn {
+                return Ok(());
+            }
+            if !st.leading {
+                break;
+            }
+            st = self.flushed.wait(st).map_err(|_| "failed to get lock")?;
+        }
+        st.leading = true;
+        drop(st);
+
+        let res = self.flush_batch(fm, lm);
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.leading = false;
+        if let Ok(durable) = res {
+            st.durable_lsn = st.durable_lsn.max(durable);
+            st.flushes += 1;
+        }
+        self.flushed.notify_all();
+        res.map(|_| ())
+    }
+
+    pub fn commits(&self) -> u64 {
+        self.state.lock().map(|st| st.commits).unwrap_or(0)
+    }
+
+    // How many flushes the commits shared.
+    pub fn flushes(&self) -> u64 {
+        self.state.lock().map(|st| st.flushes).unwrap_or(0)
+    }
+
+    fn flush_batch(&self, fm: &FileManager, lm: &Mutex<LogManager>) -> Result<i64, String> {
+        if !self.window.is_zero() {
+            thread::sleep(self.window);
+        }
+        let (lsn, unsynced) = lm.lock().map_err(|_| "failed to get lock")?.write_tail()?;
+        if let Some(segment) = unsynced {
+            fm.sync(&segment)?;
+            lm.lock().map_err(|_| "failed to get lock")?.synced_through(lsn);
+        }
+        Ok(lsn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use tempfile::TempDir;
+
+    use crate::{
+        file::{durability_mode::DurabilityMode, file_manager::FileManager},
+        log::log_manager::LogManager,
+    };
+
+    #[test]
+    fn test_group_commit_shares_flushes() {
+        let temp_dir = TempDir::new().unwrap();
+        let fm = Arc::new(FileManager::new_with_durability(
+            temp_dir.path(),
+            400,
+            DurabilityMode::Group,
+        ));
+        let lm = Arc::new(Mutex::new(LogManager::new(fm.clone(), "test_log.log".to_string()).unwrap()));
+        let group = lm.lock().unwrap().group_commit();
+
+        // the leader cannot write the log page while the test holds the latch,
+        // so every committer joins the first batch
+        let mut guard = lm.lock().unwrap();
+        let lsns: Vec<i64> = (0..8).map(|i| guard.append(vec![i; 8]).unwrap()).collect();
+        let handles: Vec<_> = lsns
+            .iter()
+            .map(|&lsn| {
+                let (fm, lm, group) = (fm.clone(), lm.clone(), group.clone());
+                thread::spawn(move || {
+                    group.commit(&fm, &lm, lsn).unwrap();
+                    // durable on return
+                    assert!(lm.lock().unwrap().last_synced_lsn() >= lsn);
+                })
+            })
+            .collect();
+        while group.commits() < 8 {
+            thread::yield_now();
+        }
+        drop(guard);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(group.commits(), 8);
+        assert_eq!(group.flushes(), 1);
+    }
+}
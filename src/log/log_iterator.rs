@@ -2,63 +2,124 @@ use std::sync::Arc;
 
 use crate::{
     constants::INTEGER_BYTES,
-    file::{block_id::BlockId, file_manager::FileManager, page::Page},
+    file::{
+        block_id::BlockId,
+        file_manager::{FileManager, PageReadException},
+        page::Page,
+    },
 };
 
+use super::log_manager::{lsn_at, segment_name};
+
 pub struct LogIterator {
     fm: Arc<FileManager>,
+    logfile: String,
+    // the first block of each segment and the index of the one being read
+    segments: Vec<i64>,
+    seg: usize,
     blk: BlockId,
     p: Page,
     current_pos: usize,
     boundary: usize,
+    failed: bool,
+    lsn: i64,
 }
 
 impl Iterator for LogIterator {
-    type Item = Result<Vec<u8>, String>;
+    type Item = Result<Vec<u8>, PageReadException>;
 
+    // Yields at most one error; a damaged block ends the iteration since nothing
+    // before it can be located reliably.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
         let block_size = self.fm.block_size() as usize;
 
-        if !(self.current_pos < block_size || self.blk.number() > 0) {
+        if !(self.current_pos < block_size || self.blk.number() > 0 || self.seg > 0) {
             return None;
         }
         if self.current_pos == block_size {
-            self.blk = BlockId::new(self.blk.file_name(), self.blk.number() - 1);
-            match self.move_to_block(self.blk.clone()) {
-                Ok(()) => (),
-                Err(e) => return Some(Err(e.to_string())),
+            self.blk = if self.blk.number() > 0 {
+                BlockId::new(self.blk.file_name(), self.blk.number() - 1)
+            } else {
+                // segments are contiguous, so the previous one ends right before
+                self.seg -= 1;
+                BlockId::new(
+                    segment_name(&self.logfile, self.segments[self.seg]),
+                    (self.segments[self.seg + 1] - self.segments[self.seg] - 1) as i32,
+                )
+            };
+            if let Err(e) = self.move_to_block(self.blk.clone()) {
+                self.failed = true;
+                return Some(Err(e));
             }
         }
         match self.p.get_bytes(self.current_pos) {
             Ok(rec) => {
+                self.lsn = lsn_at(
+                    self.segments[self.seg] + self.blk.number() as i64,
+                    self.current_pos,
+                    block_size as i32,
+                );
                 self.current_pos += INTEGER_BYTES as usize + rec.len();
-                return Some(Ok(rec));
+                Some(Ok(rec))
+            }
+            Err(_) => {
+                self.failed = true;
+                Some(Err(PageReadException::Corrupt(self.blk.clone())))
             }
-            Err(_) => Some(Err("failed to get bytes".to_string())),
         }
     }
 }
 
 impl LogIterator {
-    pub fn new(fm: Arc<FileManager>, blk: BlockId) -> Result<Self, String> {
-        let mut p = Page::new_from_blocksize(fm.block_size() as usize);
-        let _ = fm.read(&blk, &mut p);
-        let boundary = p.get_int(0).unwrap() as usize;
-        Ok(Self {
+    // Starts at `blk`, a block of the last segment.
+    pub fn new(
+        fm: Arc<FileManager>,
+        logfile: String,
+        segments: Vec<i64>,
+        blk: BlockId,
+    ) -> Result<Self, PageReadException> {
+        let p = Page::new_from_blocksize(fm.block_size() as usize);
+        let seg = segments.len() - 1;
+        let mut iter = Self {
             fm,
-            blk,
-            p: p,
-            current_pos: boundary,
-            boundary,
-        })
+            logfile,
+            segments,
+            seg,
+            blk: blk.clone(),
+            p,
+            current_pos: 0,
+            boundary: 0,
+            failed: false,
+            lsn: 0,
+        };
+        iter.move_to_block(blk)?;
+        Ok(iter)
+    }
+
+    // The LSN of the record returned last.
+    pub fn lsn(&self) -> i64 {
+        self.lsn
     }
 
-    fn move_to_block(&mut self, blk: BlockId) -> Result<(), String> {
-        let _ = self
-            .fm
-            .read(&blk, &mut self.p);
-        self.boundary = self.p.get_int(0).unwrap() as usize;
+    fn move_to_block(&mut self, blk: BlockId) -> Result<(), PageReadException> {
+        self.fm.read(&blk, &mut self.p)?;
+        self.boundary = read_boundary(&self.p, self.fm.block_size())
+            .ok_or(PageReadException::Corrupt(blk))?;
         self.current_pos = self.boundary;
         Ok(())
     }
 }
+
+// Returns the offset of the first record in a log page, or None if the page
+// does not hold a plausible one.
+pub(crate) fn read_boundary(p: &Page, block_size: i32) -> Option<usize> {
+    match p.get_int(0) {
+        Ok(boundary) if (INTEGER_BYTES..=block_size).contains(&boundary) => {
+            Some(boundary as usize)
+        }
+        _ => None,
+    }
+}
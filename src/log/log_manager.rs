@@ -1,62 +1,265 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use crate::{
     constants::INTEGER_BYTES,
-    file::{block_id::BlockId, file_manager::FileManager, page::Page},
+    file::{
+        block_id::BlockId,
+        durability_mode::DurabilityMode,
+        file_manager::{FileManager, PageReadException},
+        page::Page,
+    },
+    server::log_level::LogLevel,
+};
+
+use super::{
+    group_commit::GroupCommit,
+    log_iterator::{read_boundary, LogIterator},
 };
 
-use super::log_iterator::LogIterator;
+pub const DEFAULT_LOG_SEGMENT_BLOCKS: i32 = 256;
 
+// The log is a series of segment files, `<logfile>.<n>`, where n is the log-wide
+// number of the segment's first block. Records never span segments, and
+// segments that no checkpoint or running transaction needs are removed.
 #[derive(Debug)]
 pub struct LogManager {
     fm: Arc<FileManager>,
     logfile: String,
     logpage: Page,
+    // the first block of each segment, oldest first; the last one is written to
+    segments: Vec<i64>,
+    segment_blocks: i32,
+    archive_dir: Option<PathBuf>,
+    // a block of the last segment
     current_blk: BlockId,
-    latest_lsn: i32,
-    last_save_lsn: i32,
+    latest_lsn: i64,
+    last_save_lsn: i64,
+    last_synced_lsn: i64,
+    // transactions that have written a START record and have not finished
+    // committing, truncations included, or rolling back, each with an LSN older
+    // than its START record
+    active_txs: HashMap<i64, i64>,
+    // the number the next transaction gets, seeded from the log on startup
+    next_txnum: i64,
+    group: Arc<GroupCommit>,
 }
 
 impl LogManager {
     pub fn new(fm: Arc<FileManager>, logfile: String) -> Result<Self, String> {
         let mut logpage = Page::new_from_blocksize(fm.block_size() as usize);
-        let logsize = fm.len(&logfile).unwrap();
+        let mut segments = list_segments(&fm, &logfile)?;
+        if segments.is_empty() {
+            segments.push(0);
+        }
+        let tail = segment_name(&logfile, *segments.last().unwrap());
+        let logsize = fm.len(&tail)?;
 
         let current_blk = if logsize == 0 {
-            let blk = fm.append(&logfile).unwrap();
+            let blk = fm.append(&tail)?;
             logpage.set_int(0, fm.block_size() as i32)?;
-            let _ = fm.write(&blk, &logpage);
+            fm.write(&blk, &logpage)?;
+            fm.sync(&tail)?;
             blk
         } else {
-            let blk = BlockId::new(logfile.clone().to_string(), logsize - 1);
-            let _ = fm.read(&blk, &mut logpage);
+            let blk = BlockId::new(tail.clone(), logsize - 1);
+            let readable = match fm.read(&blk, &mut logpage) {
+                Ok(()) => read_boundary(&logpage, fm.block_size()).is_some(),
+                Err(PageReadException::Corrupt(_)) => false,
+                Err(e) => return Err(e.into()),
+            };
+            // A crash in the middle of writing the tail block leaves it torn; the
+            // records in it are lost and logging resumes on an empty page.
+            if !readable {
+                if fm.log_level().enabled(LogLevel::Info) {
+                    eprintln!("warning: discarding damaged log block {}", blk);
+                }
+                logpage = Page::new_from_blocksize(fm.block_size() as usize);
+                logpage.set_int(0, fm.block_size())?;
+                fm.write(&blk, &logpage)?;
+                fm.sync(&tail)?;
+            }
             blk
         };
+        // everything already in the file has been written and synced
+        let boundary = logpage.get_int(0)?;
+        let latest_lsn = lsn_at(
+            segments.last().unwrap() + current_blk.number() as i64,
+            boundary as usize,
+            fm.block_size(),
+        );
         return Ok(LogManager {
             fm,
             logfile: logfile,
             logpage: logpage,
+            segments,
+            segment_blocks: DEFAULT_LOG_SEGMENT_BLOCKS,
+            archive_dir: None,
             current_blk: current_blk,
-            latest_lsn: 0,
-            last_save_lsn: 0,
+            latest_lsn,
+            last_save_lsn: latest_lsn,
+            last_synced_lsn: latest_lsn,
+            active_txs: HashMap::new(),
+            next_txnum: 1,
+            group: Arc::new(GroupCommit::new(Duration::ZERO, latest_lsn)),
         });
     }
 
-    pub fn flush(&mut self, lsn: i32) -> Result<(), String> {
+    // Starts a new segment once the current one has `blocks` blocks.
+    pub fn with_segment_blocks(mut self, blocks: i32) -> Self {
+        self.segment_blocks = blocks.max(1);
+        self
+    }
+
+    // How long a group commit leader waits for more commits to join its flush.
+    pub fn with_group_commit_window(mut self, window: Duration) -> Self {
+        self.group = Arc::new(GroupCommit::new(window, self.last_synced_lsn));
+        self
+    }
+
+    // Moves unneeded segments to `dir` instead of deleting them.
+    pub fn with_archive_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.archive_dir = dir;
+        self
+    }
+
+    // Returns once the record is on disk, and synced unless durability is off.
+    pub fn flush(&mut self, lsn: i64) -> Result<(), String> {
         if lsn >= self.last_save_lsn {
             self.flush_internal()?
         }
+        if lsn > self.last_synced_lsn && self.fm.durability() != DurabilityMode::None {
+            self.sync()?
+        }
         Ok(())
     }
 
+    // Like flush, but concurrent callers share one write and sync; see GroupCommit.
+    pub fn commit_flush(lm: &Mutex<LogManager>, lsn: i64) -> Result<(), String> {
+        let (fm, group) = {
+            let lm = lm.lock().map_err(|_| "failed to get lock")?;
+            (lm.fm.clone(), lm.group.clone())
+        };
+        group.commit(&fm, lm, lsn)
+    }
+
+    pub fn group_commit(&self) -> Arc<GroupCommit> {
+        self.group.clone()
+    }
+
+    // Writes the page being filled for a group commit. Returns the newest LSN
+    // and, if it is not synced yet, the segment that the caller must sync.
+    pub(crate) fn write_tail(&mut self) -> Result<(i64, Option<String>), String> {
+        if self.latest_lsn > self.last_save_lsn {
+            self.flush_internal()?;
+        }
+        let unsynced = (self.latest_lsn > self.last_synced_lsn
+            && self.fm.durability() != DurabilityMode::None)
+            .then(|| self.current_blk.file_name());
+        Ok((self.latest_lsn, unsynced))
+    }
+
+    pub(crate) fn synced_through(&mut self, lsn: i64) {
+        self.last_synced_lsn = self.last_synced_lsn.max(lsn);
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        self.fm.log_level()
+    }
+
+    pub fn latest_lsn(&self) -> i64 {
+        self.latest_lsn
+    }
+
+    pub fn last_synced_lsn(&self) -> i64 {
+        self.last_synced_lsn
+    }
+
+    pub(crate) fn allocate_tx_number(&mut self) -> i64 {
+        let txnum = self.next_txnum;
+        self.next_txnum += 1;
+        txnum
+    }
+
+    pub fn next_tx_number(&self) -> i64 {
+        self.next_txnum
+    }
+
+    // Never moves the counter backwards.
+    pub(crate) fn seed_tx_numbers(&mut self, next: i64) {
+        self.next_txnum = self.next_txnum.max(next);
+    }
+
+    pub(crate) fn tx_started(&mut self, txnum: i64) {
+        self.active_txs.insert(txnum, self.latest_lsn);
+    }
+
+    pub(crate) fn tx_finished(&mut self, txnum: i64) {
+        self.active_txs.remove(&txnum);
+    }
+
+    pub fn active_txs(&self) -> Vec<i64> {
+        let mut txs: Vec<i64> = self.active_txs.keys().copied().collect();
+        txs.sort();
+        txs
+    }
+
+    // An LSN older than the START record of every running transaction.
+    pub(crate) fn oldest_active_lsn(&self) -> Option<i64> {
+        self.active_txs.values().copied().min()
+    }
+
+    // The bytes taken by every segment on disk.
+    pub fn log_size(&self) -> Result<u64, String> {
+        let mut blocks = 0;
+        for first in &self.segments {
+            blocks += self.fm.len(&segment_name(&self.logfile, *first))? as u64;
+        }
+        Ok(blocks * self.fm.physical_block_size() as u64)
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    // Deletes or archives the oldest segments as long as all their records are
+    // older than `lsn` and than the START of every running transaction. The
+    // segment being written is always kept. Returns how many were removed.
+    pub(crate) fn release_before(&mut self, lsn: i64) -> Result<usize, String> {
+        let keep_from = match self.oldest_active_lsn() {
+            Some(oldest) => lsn.min(oldest + 1),
+            None => lsn,
+        };
+        let block_size = self.fm.block_size();
+        let mut released = 0;
+        // a segment's newest possible LSN is the end of its last block
+        while self.segments.len() > 1 && self.segments[1] * (block_size as i64) < keep_from {
+            let first = self.segments.remove(0);
+            self.fm
+                .discard_file(&segment_name(&self.logfile, first), self.archive_dir.as_deref())?;
+            released += 1;
+        }
+        Ok(released)
+    }
+
     pub fn iterator(&mut self) -> Result<LogIterator, String> {
         // self.flush_internal();
         // TO-DO: In textbook, this code is needed but I think you cannot match requirement described in p84 if this code remains.
         // So if another problem happens related to this code, I will remove the comment out.
-        return LogIterator::new(self.fm.clone(), self.current_blk.clone());
+        return LogIterator::new(
+            self.fm.clone(),
+            self.logfile.clone(),
+            self.segments.clone(),
+            self.current_blk.clone(),
+        )
+        .map_err(|e| e.into());
     }
 
-    pub fn append(&mut self, logrec: Vec<u8>) -> Result<i32, String> {
+    pub fn append(&mut self, logrec: Vec<u8>) -> Result<i64, String> {
         let mut boundary = self.logpage.get_int(0).unwrap() as usize;
         let recsize = logrec.len();
         let bytesneeded = recsize + INTEGER_BYTES as usize;
@@ -69,26 +272,72 @@ impl LogManager {
 
         self.logpage.set_bytes(recpos, &logrec)?;
         self.logpage.set_int(0, recpos as i32)?;
-        self.latest_lsn += 1;
+        self.latest_lsn = lsn_at(self.current_block_number(), recpos, self.fm.block_size());
         return Ok(self.latest_lsn);
     }
 
     fn append_new_block(&mut self) -> Result<BlockId, String> {
-        let blk = self.fm.append(&self.logfile).unwrap();
+        if self.current_blk.number() + 1 >= self.segment_blocks {
+            // the old segment is complete, so no later sync has to cover it
+            if self.fm.durability() != DurabilityMode::None {
+                self.sync()?;
+            }
+            self.segments.push(self.current_block_number() + 1);
+        }
+        let blk = self.fm.append(&self.current_segment())?;
         self.logpage.set_int(0, self.fm.block_size() as i32)?;
         let _ = self.fm.write(&blk, &self.logpage);
         Ok(blk)
     }
 
+    fn current_segment(&self) -> String {
+        segment_name(&self.logfile, *self.segments.last().unwrap())
+    }
+
+    // The log-wide number of the block being written.
+    fn current_block_number(&self) -> i64 {
+        self.segments.last().unwrap() + self.current_blk.number() as i64
+    }
+
     fn flush_internal(&mut self) -> Result<(), String> {
-        let _ = self
-            .fm
-            .write(&self.current_blk, &self.logpage);
+        self.fm.write(&self.current_blk, &self.logpage)?;
         self.last_save_lsn = self.latest_lsn;
+        if self.fm.durability() == DurabilityMode::Full {
+            self.sync()?
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.fm.sync(&self.current_blk.file_name())?;
+        self.last_synced_lsn = self.last_save_lsn;
         Ok(())
     }
 }
 
+// A record's LSN is its distance from the start of the log, counting each block
+// from its end since records fill a block backwards. LSNs therefore grow with
+// every record and stay valid across restarts, so pages can carry them.
+pub(crate) fn lsn_at(blknum: i64, recpos: usize, block_size: i32) -> i64 {
+    blknum * block_size as i64 + (block_size as i64 - recpos as i64)
+}
+
+pub(crate) fn segment_name(logfile: &str, first: i64) -> String {
+    format!("{}.{}", logfile, first)
+}
+
+// The first blocks of the log's segments in the database directory, in order.
+fn list_segments(fm: &FileManager, logfile: &str) -> Result<Vec<i64>, String> {
+    let prefix = format!("{}.", logfile);
+    let mut segments: Vec<i64> = fm
+        .file_names()?
+        .iter()
+        .filter_map(|name| name.strip_prefix(&prefix)?.parse().ok())
+        .collect();
+    segments.sort();
+    Ok(segments)
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::{Arc, Mutex};
@@ -163,12 +412,13 @@ mod tests {
         let log_record1 = vec![1, 2, 3, 4];
         let lsn1 = log_mgr.append(log_record1.clone());
 
-        assert_eq!(lsn1.unwrap(), 1);
+        // an LSN counts the bytes logged so far, each record with its length
+        assert_eq!(lsn1.unwrap(), 8);
 
         let log_record2 = vec![5, 6, 7, 8];
         let lsn2 = log_mgr.append(log_record2.clone());
 
-        assert_eq!(lsn2.unwrap(), 2);
+        assert_eq!(lsn2.unwrap(), 16);
     }
 
     #[test]
@@ -183,6 +433,38 @@ mod tests {
         log_mgr.flush(lsn).unwrap();
 
         assert_eq!(log_mgr.last_save_lsn, lsn);
+        assert_eq!(log_mgr.last_synced_lsn(), lsn);
+    }
+
+    #[test]
+    fn test_log_mgr_flush_syncs_by_durability() {
+        let logfile = "test_log.log".to_string();
+
+        let temp_dir = TempDir::new().unwrap();
+        let fm = Arc::new(FileManager::new_with_durability(
+            temp_dir.path(),
+            400,
+            DurabilityMode::Group,
+        ));
+        let mut log_mgr = LogManager::new(fm, logfile.clone()).unwrap();
+        let lsn1 = log_mgr.append(vec![1, 2, 3, 4]).unwrap();
+        let lsn2 = log_mgr.append(vec![5, 6, 7, 8]).unwrap();
+        assert_eq!(log_mgr.last_synced_lsn(), 0);
+        // one sync covers every record written so far
+        log_mgr.flush(lsn1).unwrap();
+        assert_eq!(log_mgr.last_synced_lsn(), lsn2);
+
+        let temp_dir = TempDir::new().unwrap();
+        let fm = Arc::new(FileManager::new_with_durability(
+            temp_dir.path(),
+            400,
+            DurabilityMode::None,
+        ));
+        let mut log_mgr = LogManager::new(fm, logfile).unwrap();
+        let lsn = log_mgr.append(vec![1, 2, 3, 4]).unwrap();
+        log_mgr.flush(lsn).unwrap();
+        assert_eq!(log_mgr.last_save_lsn, lsn);
+        assert_eq!(log_mgr.last_synced_lsn(), 0);
     }
 
     #[test]
@@ -200,6 +482,131 @@ mod tests {
         assert_ne!(log_mgr.current_blk, initial_block);
     }
 
+    #[test]
+    fn test_log_mgr_discards_torn_tail_block() {
+        let (fm, temp_dir) = create_test_file_manager();
+        let logfile = "test_log.log".to_string();
+        let lm = Arc::new(Mutex::new(LogManager::new(fm.clone(), logfile.clone()).unwrap()));
+        create_records(lm.clone(), 1, 35);
+        let lsn = lm.lock().unwrap().append(vec![0; 8]).unwrap();
+        lm.lock().unwrap().flush(lsn).unwrap();
+        let segment = segment_name(&logfile, 0);
+        let numblocks = fm.len(&segment).unwrap();
+        assert!(numblocks > 1);
+
+        // damage the middle of the last block, as if a write had been cut short
+        let path = temp_dir.path().join(&segment);
+        let mut bytes = std::fs::read(&path).unwrap();
+        let tail = (numblocks - 1) as usize * fm.physical_block_size() as usize;
+        bytes[tail + 200] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let fm = Arc::new(FileManager::new_from_blocksize(temp_dir.path(), 400));
+        let mut lm = LogManager::new(fm.clone(), logfile.clone()).unwrap();
+        let recs: Vec<_> = lm.iterator().unwrap().collect();
+        assert!(!recs.is_empty());
+        assert!(recs.iter().all(|r| r.is_ok()));
+        assert_eq!(fm.len(&segment).unwrap(), numblocks);
+
+        // damage an older block: iteration stops there with an error
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[10] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+        let recs: Vec<_> = lm.iterator().unwrap().collect();
+        assert!(matches!(recs.last(), Some(Err(PageReadException::Corrupt(_)))));
+    }
+
+    #[test]
+    fn test_log_mgr_segments() {
+        let (fm, temp_dir) = create_test_file_manager();
+        let logfile = "test_log.log".to_string();
+        let archive = temp_dir.path().join("archive");
+        let lm = Arc::new(Mutex::new(
+            LogManager::new(fm.clone(), logfile.clone())
+                .unwrap()
+                .with_segment_blocks(1)
+                .with_archive_dir(Some(archive.clone())),
+        ));
+        create_records(lm.clone(), 1, 35);
+        lm.lock().unwrap().tx_started(1);
+        create_records(lm.clone(), 36, 70);
+        let segments = lm.lock().unwrap().segment_count();
+        assert!(segments > 2);
+        let blocks: i32 = fm
+            .file_names()
+            .unwrap()
+            .iter()
+            .filter(|name| name.starts_with("test_log.log."))
+            .map(|name| fm.len(name).unwrap())
+            .sum();
+        assert_eq!(
+            lm.lock().unwrap().log_size().unwrap(),
+            blocks as u64 * fm.physical_block_size() as u64
+        );
+
+        // records are read back across segments, newest first
+        let mut lm_guard = lm.lock().unwrap();
+        let latest = lm_guard.latest_lsn();
+        lm_guard.flush(latest).unwrap();
+        let vals: Vec<i32> = lm_guard
+            .iterator()
+            .unwrap()
+            .map(|rec| {
+                let p = Page::new_from_bytes(rec.unwrap());
+                let npos = Page::max_length(p.get_string(0).unwrap().len());
+                p.get_int(npos).unwrap()
+            })
+            .collect();
+        assert_eq!(vals, (1..=70).rev().collect::<Vec<i32>>());
+
+        // the running transaction keeps the segments after its start
+        let released = lm_guard.release_before(latest + 1).unwrap();
+        assert!(released > 0);
+        let recs: Vec<_> = lm_guard.iterator().unwrap().collect();
+        assert!(recs.len() >= 35 && recs.len() < 70);
+        assert!(recs.iter().all(|r| r.is_ok()));
+
+        lm_guard.tx_finished(1);
+        lm_guard.release_before(latest + 1).unwrap();
+        assert_eq!(lm_guard.segment_count(), 1);
+        assert_eq!(archive.read_dir().unwrap().count(), segments - 1);
+
+        // a reopened log continues after the newest segment
+        drop(lm_guard);
+        let lsn = lm.lock().unwrap().append(vec![0; 8]).unwrap();
+        lm.lock().unwrap().flush(lsn).unwrap();
+        let lm = LogManager::new(fm.clone(), logfile).unwrap();
+        assert_eq!(lm.latest_lsn(), lsn);
+        assert_eq!(lm.segment_count(), 1);
+    }
+
+    #[test]
+    fn test_log_mgr_segments_past_32_bits() {
+        let (fm, _temp_dir) = create_test_file_manager();
+        let logfile = "test_log.log".to_string();
+        // a long-running log whose segments start beyond 2^31 blocks
+        let first = i32::MAX as i64 + 10;
+        fm.append(&segment_name(&logfile, first)).unwrap();
+        let lm = Arc::new(Mutex::new(
+            LogManager::new(fm.clone(), logfile.clone())
+                .unwrap()
+                .with_segment_blocks(1),
+        ));
+        assert_eq!(lm.lock().unwrap().latest_lsn(), first * 400);
+        create_records(lm.clone(), 1, 35);
+        let mut lm_guard = lm.lock().unwrap();
+        assert!(lm_guard.segment_count() > 1);
+        let latest = lm_guard.latest_lsn();
+        lm_guard.flush(latest).unwrap();
+        let recs: Vec<_> = lm_guard.iterator().unwrap().collect();
+        assert_eq!(recs.len(), 35);
+        assert!(recs.iter().all(|r| r.is_ok()));
+
+        drop(lm_guard);
+        let lm = LogManager::new(fm.clone(), logfile).unwrap();
+        assert_eq!(lm.latest_lsn(), latest);
+    }
+
     #[test]
     fn test_main() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,2 +1,3 @@
+pub mod group_commit;
 pub mod log_iterator;
 pub mod log_manager;
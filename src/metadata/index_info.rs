@@ -89,20 +89,33 @@ impl IndexInfo {
         }
     }
 
+    pub fn field_name(&self) -> String {
+        self.fldname.clone()
+    }
+
+    pub fn index_name(&self) -> String {
+        self.idxname.clone()
+    }
+
     fn create_idx_layout(&self) -> Result<Layout, String> {
-        let sch = Schema::new();
-        sch.add_int_field(&"block".to_string())?;
-        sch.add_int_field(&"id".to_string())?;
-        let tbl_sch = &self.tbl_schema;
-        if tbl_sch.field_type(&self.fldname)? == INTEGER {
-            sch.add_int_field(&"dataval".to_string())?;
-        } else if tbl_sch.field_type(&self.fldname)? == VARCHAR {
-            let fldlen = tbl_sch.length(&self.fldname)?;
-            sch.add_string_field(&"dataval".to_string(), fldlen)?;
-        } else {
-            panic!("an unexpected type");
-        }
-        let ret = Layout::new_from_schema(sch)?;
-        Ok(ret)
+        index_layout(
+            self.tbl_schema.field_type(&self.fldname)?,
+            self.tbl_schema.length(&self.fldname)?,
+        )
+    }
+}
+
+// The layout of the records of an index on a field of the given type and length.
+pub(crate) fn index_layout(fldtype: i32, fldlen: i32) -> Result<Layout, String> {
+    let sch = Schema::new();
+    sch.add_int_field(&"block".to_string())?;
+    sch.add_int_field(&"id".to_string())?;
+    if fldtype == INTEGER {
+        sch.add_int_field(&"dataval".to_string())?;
+    } else if fldtype == VARCHAR {
+        sch.add_string_field(&"dataval".to_string(), fldlen)?;
+    } else {
+        panic!("an unexpected type");
     }
+    Layout::new_from_schema(sch)
 }
@@ -5,7 +5,7 @@ use std::{
 
 use crate::{
     record::{layout::Layout, schema::Schema},
-    tx::transaction::Transaction,
+    tx::{concurrency::lock_mode::LockMode, transaction::Transaction},
 };
 
 use super::{
@@ -53,11 +53,18 @@ impl MetadataManager {
         sch: Schema,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<(), String> {
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .lock_file(&format!("{}.tbl", tblname), LockMode::Exclusive)?;
         self.tbl_manager
             .create_table(tblname, sch.clone(), tx.clone())?;
         Ok(())
     }
 
+    pub fn get_table_names(&self, tx: Arc<Mutex<Transaction>>) -> Result<Vec<String>, String> {
+        self.tbl_manager.table_names(tx)
+    }
+
     pub fn get_layout(
         &self,
         tblname: String,
@@ -98,6 +105,10 @@ impl MetadataManager {
         fldname: String,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<(), String> {
+        // no changes to the table while the index is defined
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .lock_file(&format!("{}.tbl", tblname), LockMode::Shared)?;
         self.idx_manager
             .lock()
             .map_err(|_| "failed to get lock")?
@@ -131,6 +142,13 @@ impl MetadataManager {
             .get_stat_info(tblname, layout, tx)?;
         Ok(ret)
     }
+
+    pub fn invalidate_stat_info(&self, tblname: &str) -> Result<(), String> {
+        self.stat_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .invalidate(tblname)
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +56,14 @@ impl StatManager {
         }
     }
 
+    pub fn invalidate(&self, tblname: &str) -> Result<(), String> {
+        self.table_stats
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .remove(tblname);
+        Ok(())
+    }
+
     fn refreash_statistics_internal(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
         let tcatlayout = self.table_manager.get_layout("tblcat".to_string(), tx.clone())?;
         let mut tcat = TableScan::new(tx.clone(), "tblcat".to_string(), tcatlayout)?;
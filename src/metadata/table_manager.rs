@@ -10,6 +10,8 @@ use crate::{
 };
 
 pub const MAX_NAME: i32 = 16;
+// the tables the metadata managers keep their catalogs in
+pub const CATALOG_TABLES: [&str; 4] = ["tblcat", "fldcat", "idxcat", "viewcat"];
 
 #[derive(Debug)]
 pub struct TableManager {
@@ -118,6 +120,16 @@ impl TableManager {
 
         Ok(ret)
     }
+
+    pub fn table_names(&self, tx: Arc<Mutex<Transaction>>) -> Result<Vec<String>, String> {
+        let mut ret = Vec::new();
+        let mut tcat = TableScan::new(tx, "tblcat".to_string(), self.tcat_layout.clone())?;
+        while tcat.next()? {
+            ret.push(tcat.get_string(&"tblname".to_string())?);
+        }
+        tcat.close()?;
+        Ok(ret)
+    }
 }
 
 #[cfg(test)]
@@ -27,6 +27,11 @@ impl ChunkScan {
         startbnum: i32,
         endbnum: i32,
     ) -> Result<Self, String> {
+        // one read for as much of the chunk as read-ahead allows, instead of one
+        // per block
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .read_ahead(&BlockId::new(filename.clone(), startbnum), endbnum - startbnum + 1)?;
         let mut buffs = vec![];
         for i in startbnum..=endbnum {
             let blk = BlockId::new(filename.clone(), i);
@@ -6,6 +6,9 @@ use std::str::Chars;
 #[derive(Debug)]
 pub struct Lexer<'a> {
     keywords: HashSet<&'a str>,
+    // words that are keywords only where a statement expects them and can
+    // otherwise name tables, fields and savepoints
+    contextual: HashSet<&'a str>,
     input: Peekable<Chars<'a>>,
     current_token: Option<Token>,
 }
@@ -47,6 +50,10 @@ impl<'a> Lexer<'a> {
                 "select", "from", "where", "and", "in", "insert", "into", "values", "delete", "update",
                 "set", "create", "table", "int", "varchar", "boolean", "true", "false", "view", "as", "index", "on",
             ]),
+            contextual: HashSet::from([
+                "vacuum", "checkpoint", "savepoint", "rollback", "to", "release", "transaction", "isolation",
+                "level", "read", "uncommitted", "committed", "repeatable", "serializable",
+            ]),
             input: input.chars().peekable(),
             current_token: None,
         };
@@ -71,10 +78,10 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn match_keyword(&self, w: &str) -> bool {
-        if let Some(Token::Keyword(kw)) = &self.current_token {
-            kw == w
-        } else {
-            false
+        match &self.current_token {
+            Some(Token::Keyword(kw)) => kw == w,
+            Some(Token::Id(id)) => self.contextual.contains(w) && id.eq_ignore_ascii_case(w),
+            _ => false,
         }
     }
 
@@ -263,4 +270,18 @@ mod tests {
         assert_eq!(lex.match_keyword("false"), true);
         lex.eat_keyword("false").unwrap();
     }
+
+    #[test]
+    fn test_lexer_contextual_keywords() {
+        let mut lex = Lexer::new("Rollback to to");
+        assert!(lex.match_keyword("rollback"));
+        lex.eat_keyword("rollback").unwrap();
+        lex.eat_keyword("to").unwrap();
+        assert!(lex.match_id());
+        assert_eq!("to", lex.eat_id().unwrap());
+
+        let mut lex = Lexer::new("select");
+        assert!(!lex.match_id());
+        assert!(lex.eat_id().is_err());
+    }
 }
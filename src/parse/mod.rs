@@ -8,3 +8,4 @@ pub mod modify_data;
 pub mod parser;
 pub mod pred_parser;
 pub mod query_data;
+pub mod vacuum_data;
@@ -1,5 +1,6 @@
 use crate::{
     parse::lexer::BadSyntaxException,
+    tx::concurrency::isolation_level::IsolationLevel,
     query::{constant::Constant, expression::Expression, predicate::Predicate, term::Term},
     record::schema::Schema,
 };
@@ -7,7 +8,7 @@ use crate::{
 use super::{
     create_index_data::CreateIndexData, create_table_data::CreateTableData,
     create_view_data::CreateViewData, delete_data::DeleteData, insert_data::InsertData,
-    lexer::Lexer, modify_data::ModifyData, query_data::QueryData,
+    lexer::Lexer, modify_data::ModifyData, query_data::QueryData, vacuum_data::VacuumData,
 };
 
 #[derive(Debug)]
@@ -18,6 +19,26 @@ pub enum UpdateCommand {
     CreateTable(CreateTableData),
     CreateView(CreateViewData),
     CreateIndex(CreateIndexData),
+    Vacuum(VacuumData),
+    Checkpoint,
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
+    SetIsolationLevel(IsolationLevel),
+}
+
+impl UpdateCommand {
+    // Statements that change data run atomically, under an implicit savepoint.
+    pub fn changes_data(&self) -> bool {
+        !matches!(
+            self,
+            UpdateCommand::Checkpoint
+                | UpdateCommand::Savepoint(_)
+                | UpdateCommand::RollbackTo(_)
+                | UpdateCommand::Release(_)
+                | UpdateCommand::SetIsolationLevel(_)
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -133,13 +154,63 @@ impl<'a> Parser<'a> {
             Ok(UpdateCommand::Modify(self.modify()?))
         } else if self.lex.match_keyword("create") {
             self.create()
+        } else if self.lex.match_keyword("vacuum") {
+            Ok(UpdateCommand::Vacuum(self.vacuum()?))
+        } else if self.lex.match_keyword("checkpoint") {
+            self.lex.eat_keyword("checkpoint")?;
+            Ok(UpdateCommand::Checkpoint)
+        } else if self.lex.match_keyword("savepoint") {
+            self.lex.eat_keyword("savepoint")?;
+            Ok(UpdateCommand::Savepoint(self.lex.eat_id()?))
+        } else if self.lex.match_keyword("rollback") {
+            self.lex.eat_keyword("rollback")?;
+            self.lex.eat_keyword("to")?;
+            Ok(UpdateCommand::RollbackTo(self.savepoint_name()?))
+        } else if self.lex.match_keyword("release") {
+            self.lex.eat_keyword("release")?;
+            Ok(UpdateCommand::Release(self.savepoint_name()?))
+        } else if self.lex.match_keyword("set") {
+            Ok(UpdateCommand::SetIsolationLevel(self.set_isolation_level()?))
         } else {
             return Err(BadSyntaxException::new(
-                "Expected insert, delete, update, or create command",
+                "Expected insert, delete, update, create, vacuum, checkpoint, savepoint, rollback, release, or set command",
             ));
         }
     }
 
+    // SET TRANSACTION ISOLATION LEVEL {READ UNCOMMITTED | READ COMMITTED |
+    // REPEATABLE READ | SERIALIZABLE}
+    fn set_isolation_level(&mut self) -> Result<IsolationLevel, super::lexer::BadSyntaxException> {
+        self.lex.eat_keyword("set")?;
+        self.lex.eat_keyword("transaction")?;
+        self.lex.eat_keyword("isolation")?;
+        self.lex.eat_keyword("level")?;
+        if self.lex.match_keyword("read") {
+            self.lex.eat_keyword("read")?;
+            if self.lex.match_keyword("uncommitted") {
+                self.lex.eat_keyword("uncommitted")?;
+                return Ok(IsolationLevel::ReadUncommitted);
+            }
+            self.lex.eat_keyword("committed")?;
+            Ok(IsolationLevel::ReadCommitted)
+        } else if self.lex.match_keyword("repeatable") {
+            self.lex.eat_keyword("repeatable")?;
+            self.lex.eat_keyword("read")?;
+            Ok(IsolationLevel::RepeatableRead)
+        } else {
+            self.lex.eat_keyword("serializable")?;
+            Ok(IsolationLevel::Serializable)
+        }
+    }
+
+    // The SAVEPOINT keyword is optional after ROLLBACK TO and RELEASE.
+    fn savepoint_name(&mut self) -> Result<String, super::lexer::BadSyntaxException> {
+        if self.lex.match_keyword("savepoint") {
+            self.lex.eat_keyword("savepoint")?;
+        }
+        self.lex.eat_id()
+    }
+
     fn create(&mut self) -> Result<UpdateCommand, super::lexer::BadSyntaxException> {
         self.lex.eat_keyword("create")?;
         if self.lex.match_keyword("table") {
@@ -300,6 +371,16 @@ impl<'a> Parser<'a> {
         self.lex.eat_delim(')')?;
         Ok(CreateIndexData::new(idxname, tblname, fldname))
     }
+
+    fn vacuum(&mut self) -> Result<VacuumData, super::lexer::BadSyntaxException> {
+        self.lex.eat_keyword("vacuum")?;
+        let tblname = if self.lex.match_id() {
+            Some(self.lex.eat_id()?)
+        } else {
+            None
+        };
+        Ok(VacuumData::new(tblname))
+    }
 }
 
 #[cfg(test)]
@@ -308,6 +389,7 @@ mod tests {
     use crate::{
         parse::parser::{Parser, UpdateCommand},
         query::{constant::Constant, expression::Expression, predicate::Predicate, term::Term},
+        tx::concurrency::isolation_level::IsolationLevel,
     };
 
     #[test]
@@ -411,6 +493,62 @@ mod tests {
         assert_eq!("idx_a", uc.idx_name());
     }
 
+    #[test]
+    fn test_pred_parser_vacuum() {
+        let mut p = Parser::new("vacuum tab_a");
+        let UpdateCommand::Vacuum(uc) = p.update_cmd().unwrap() else {
+            panic!("unreachable!!")
+        };
+        assert_eq!(Some("tab_a".to_string()), uc.table_name());
+
+        let mut p = Parser::new("vacuum");
+        let UpdateCommand::Vacuum(uc) = p.update_cmd().unwrap() else {
+            panic!("unreachable!!")
+        };
+        assert_eq!(None, uc.table_name());
+    }
+
+    #[test]
+    fn test_pred_parser_checkpoint() {
+        let mut p = Parser::new("checkpoint");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::Checkpoint));
+    }
+
+    #[test]
+    fn test_pred_parser_savepoints() {
+        let mut p = Parser::new("savepoint first_batch");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::Savepoint(n) if n == "first_batch"));
+        let mut p = Parser::new("rollback to first_batch");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::RollbackTo(n) if n == "first_batch"));
+        let mut p = Parser::new("rollback to savepoint first_batch");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::RollbackTo(n) if n == "first_batch"));
+        let mut p = Parser::new("release savepoint first_batch");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::Release(n) if n == "first_batch"));
+        assert!(Parser::new("rollback first_batch").update_cmd().is_err());
+
+        // the words of these statements can still name things elsewhere
+        let mut p = Parser::new("release to");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::Release(n) if n == "to"));
+        let mut p = Parser::new("create table to (release int, level varchar(10))");
+        assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::CreateTable(_)));
+        let q = Parser::new("select release, level from to where read = 1").query().unwrap();
+        assert_eq!(vec!["to".to_string()], q.tables());
+    }
+
+    #[test]
+    fn test_pred_parser_isolation_levels() {
+        for (sql, level) in [
+            ("set transaction isolation level read uncommitted", IsolationLevel::ReadUncommitted),
+            ("SET TRANSACTION ISOLATION LEVEL READ COMMITTED", IsolationLevel::ReadCommitted),
+            ("set transaction isolation level repeatable read", IsolationLevel::RepeatableRead),
+            ("set transaction isolation level serializable", IsolationLevel::Serializable),
+        ] {
+            let mut p = Parser::new(sql);
+            assert!(matches!(p.update_cmd().unwrap(), UpdateCommand::SetIsolationLevel(l) if l == level));
+        }
+        assert!(Parser::new("set transaction isolation level snapshot").update_cmd().is_err());
+    }
+
     #[test]
     fn test_pred_parser_select_in() {
         let s = "select col_a from tab_a where col_b in (1, 2, 3)";
@@ -433,7 +571,9 @@ mod tests {
         let res = p.update_cmd();
         assert!(res.is_err());
         let err = res.unwrap_err();
-        assert!(err.message.contains("Expected insert, delete, update, or create command"));
+        assert!(err
+            .message
+            .contains("Expected insert, delete, update, create, vacuum, checkpoint, savepoint, rollback, release, or set command"));
     }
 
     #[test]
@@ -0,0 +1,14 @@
+#[derive(Debug, Clone)]
+pub struct VacuumData {
+    tblname: Option<String>,
+}
+
+impl VacuumData {
+    pub fn new(tblname: Option<String>) -> Self {
+        VacuumData { tblname }
+    }
+
+    pub fn table_name(&self) -> Option<String> {
+        self.tblname.clone()
+    }
+}
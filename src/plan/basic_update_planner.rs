@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex};
 
-use crate::metadata::matadata_manager::MetadataManager;
+use crate::{metadata::matadata_manager::MetadataManager, record::table_compactor::vacuum};
 
 use super::{
     plan::Plan, select_plan::SelectPlan, table_plan::TablePlan, update_planner::UpdatePlanner,
@@ -136,6 +136,14 @@ impl UpdatePlanner for BasicUpdatePlanner {
             .create_index(data.idx_name(), data.table_name(), data.field_name(), tx)?;
         Ok(0)
     }
+    fn execute_vacuum(
+        &self,
+        data: crate::parse::vacuum_data::VacuumData,
+        tx: Arc<Mutex<crate::tx::transaction::Transaction>>,
+    ) -> Result<i32, String> {
+        let mdm = self.mdm.lock().map_err(|_| "failed to get lock")?;
+        vacuum(&mdm, data.table_name(), tx)
+    }
 }
 
 impl BasicUpdatePlanner {
@@ -1,7 +1,11 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    parse::{lexer::BadSyntaxException, parser::Parser, query_data::QueryData},
+    parse::{
+        lexer::BadSyntaxException,
+        parser::{Parser, UpdateCommand},
+        query_data::QueryData,
+    },
     tx::transaction::Transaction,
 };
 
@@ -32,6 +36,10 @@ impl Planner {
         let mut parser = Parser::new(qry);
         let data = parser.query()?;
         // Self::verify_query(&data);
+        tx.lock()
+            .map_err(|_| BadSyntaxException::new("Lock failed"))?
+            .check_wounded()
+            .map_err(|e| BadSyntaxException::new(&e))?;
 
         self.qplanner
             .lock()
@@ -47,43 +55,105 @@ impl Planner {
     ) -> Result<i32, super::super::parse::lexer::BadSyntaxException> {
         let mut p = Parser::new(cmd);
         // Self::verify_update(&data);
-        match p.update_cmd()? {
+        let cmd = p.update_cmd()?;
+        if !cmd.changes_data() {
+            return self.execute_command(cmd, tx);
+        }
+
+        // a failed statement leaves no trace, and the transaction goes on
+        let mark = tx
+            .lock()
+            .map_err(|_| BadSyntaxException::new("Lock failed"))?
+            .begin_statement()
+            .map_err(|e| BadSyntaxException::new(&e))?;
+        let res = self.execute_command(cmd, tx.clone());
+        if res.is_err() {
+            tx.lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .rollback_statement(mark)
+                .map_err(|e| BadSyntaxException::new(&format!("Statement rollback failed: {}", e)))?;
+        }
+        res
+    }
+
+    fn execute_command(
+        &mut self,
+        cmd: UpdateCommand,
+        tx: Arc<Mutex<Transaction>>,
+    ) -> Result<i32, BadSyntaxException> {
+        match cmd {
             crate::parse::parser::UpdateCommand::Insert(insert_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_insert(insert_data, tx)
-                .map_err(|_| BadSyntaxException::new("Insert failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("Insert failed: {}", e))),
             crate::parse::parser::UpdateCommand::Delete(delete_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_delete(delete_data, tx)
-                .map_err(|_| BadSyntaxException::new("Delete failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("Delete failed: {}", e))),
             crate::parse::parser::UpdateCommand::Modify(modify_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_modify(modify_data, tx)
-                .map_err(|_| BadSyntaxException::new("Modify failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("Modify failed: {}", e))),
             crate::parse::parser::UpdateCommand::CreateTable(create_table_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_create_table(create_table_data, tx)
-                .map_err(|_| BadSyntaxException::new("CreateTable failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("CreateTable failed: {}", e))),
             crate::parse::parser::UpdateCommand::CreateView(create_view_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_create_view(create_view_data, tx)
-                .map_err(|_| BadSyntaxException::new("CreateView failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("CreateView failed: {}", e))),
             crate::parse::parser::UpdateCommand::CreateIndex(create_index_data) => self
                 .uplanner
                 .lock()
                 .map_err(|_| BadSyntaxException::new("Lock failed"))?
                 .execute_create_index(create_index_data, tx)
-                .map_err(|_| BadSyntaxException::new("CreateIndex failed")),
+                .map_err(|e| BadSyntaxException::new(&format!("CreateIndex failed: {}", e))),
+            crate::parse::parser::UpdateCommand::Vacuum(vacuum_data) => self
+                .uplanner
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .execute_vacuum(vacuum_data, tx)
+                .map_err(|e| BadSyntaxException::new(&format!("Vacuum failed: {}", e))),
+            crate::parse::parser::UpdateCommand::Checkpoint => tx
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .take_checkpoint()
+                .map(|_| 0)
+                .map_err(|e| BadSyntaxException::new(&format!("Checkpoint failed: {}", e))),
+            crate::parse::parser::UpdateCommand::Savepoint(name) => tx
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .savepoint(&name)
+                .map(|_| 0)
+                .map_err(|e| BadSyntaxException::new(&format!("Savepoint failed: {}", e))),
+            crate::parse::parser::UpdateCommand::RollbackTo(name) => tx
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .rollback_to_savepoint(&name)
+                .map(|_| 0)
+                .map_err(|e| BadSyntaxException::new(&format!("Rollback to savepoint failed: {}", e))),
+            crate::parse::parser::UpdateCommand::Release(name) => tx
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .release_savepoint(&name)
+                .map(|_| 0)
+                .map_err(|e| BadSyntaxException::new(&format!("Release savepoint failed: {}", e))),
+            crate::parse::parser::UpdateCommand::SetIsolationLevel(level) => tx
+                .lock()
+                .map_err(|_| BadSyntaxException::new("Lock failed"))?
+                .set_isolation_level(level)
+                .map(|_| 0)
+                .map_err(|e| BadSyntaxException::new(&format!("Set isolation level failed: {}", e))),
         }
     }
 
@@ -97,7 +167,11 @@ impl Planner {
 #[cfg(test)]
 mod tests {
 
-    use std::sync::{Arc, Mutex};
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
 
     use rand::Rng;
     use tempfile::TempDir;
@@ -108,7 +182,17 @@ mod tests {
             select_plan::SelectPlan, table_plan::TablePlan,
         },
         query::{constant::Constant, expression::Expression, predicate::Predicate, term::Term},
-        server::simple_db::SimpleDB,
+        server::{
+            db_options::{QueryPlannerKind, UpdatePlannerKind},
+            simple_db::SimpleDB,
+        },
+        testlib::helper::count_rows,
+        tx::{
+            concurrency::{
+                deadlock_policy::DeadlockPolicy, isolation_level::IsolationLevel, lock_mode::LockMode,
+            },
+            transaction::Transaction,
+        },
     };
 
     #[test]
@@ -351,6 +435,202 @@ mod tests {
         assert!(cnt == n)
     }
 
+    #[test]
+    fn test_vacuum() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_refined_planners(temp_dir.path());
+        let fm = db.file_manager();
+        let mut planner = db.planner.clone().unwrap();
+
+        let tx = db.new_tx();
+        planner
+            .execute_update("create table T(A int, B varchar(9))", tx.clone())
+            .unwrap();
+        planner
+            .execute_update("create index a_idx on T(A)", tx.clone())
+            .unwrap();
+        for i in 0..100 {
+            let b = if i % 5 == 0 { "keep" } else { "del" };
+            let cmd = format!("insert into T(A,B) values({}, '{}')", i, b);
+            planner.execute_update(&cmd, tx.clone()).unwrap();
+        }
+        planner
+            .execute_update("delete from T where B = 'del'", tx.clone())
+            .unwrap();
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 6);
+
+        // nothing is truncated when the vacuum is rolled back
+        let tx = db.new_tx();
+        assert_eq!(planner.execute_update("vacuum T", tx.clone()).unwrap(), 4);
+        tx.lock().unwrap().rollback().unwrap();
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 6);
+
+        let tx = db.new_tx();
+        assert_eq!(planner.execute_update("vacuum T", tx.clone()).unwrap(), 4);
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), 2);
+
+        let tx = db.new_tx();
+        for i in (0..100).step_by(5) {
+            let qry = format!("select B from T where A = {}", i);
+            let p = planner.create_query_planner(&qry, tx.clone()).unwrap();
+            let s = p.lock().unwrap().open().unwrap();
+            assert!(s.lock().unwrap().next().unwrap());
+            assert_eq!(s.lock().unwrap().get_string(&"B".to_string()).unwrap(), "keep");
+            assert!(!s.lock().unwrap().next().unwrap());
+            s.lock().unwrap().close().unwrap();
+        }
+        assert!(planner.execute_update("vacuum U", tx.clone()).is_err());
+        tx.lock().unwrap().commit().unwrap();
+
+        // a bare vacuum compacts the user tables but not the catalogs, which
+        // another transaction is reading
+        let reader = db.new_tx();
+        for tblname in ["tblcat", "fldcat", "idxcat", "viewcat"] {
+            let filename = format!("{}.tbl", tblname);
+            reader.lock().unwrap().lock_file(&filename, LockMode::Shared).unwrap();
+        }
+        let tx = db.new_tx();
+        assert_eq!(planner.execute_update("vacuum", tx.clone()).unwrap(), 0);
+        tx.lock().unwrap().commit().unwrap();
+        reader.lock().unwrap().commit().unwrap();
+
+        // a truncation waits for the snapshots older than its commit, which
+        // still read the records moved out of the truncated blocks
+        let tx = db.new_tx();
+        for i in 100..200 {
+            let b = if i < 150 { "early" } else { "late" };
+            let cmd = format!("insert into T(A,B) values({}, '{}')", i, b);
+            planner.execute_update(&cmd, tx.clone()).unwrap();
+        }
+        for b in ["keep", "early"] {
+            let cmd = format!("delete from T where B = '{}'", b);
+            planner.execute_update(&cmd, tx.clone()).unwrap();
+        }
+        tx.lock().unwrap().commit().unwrap();
+        let size = fm.len(&"T.tbl".to_string()).unwrap();
+        let count = |tx: &Arc<Mutex<Transaction>>| count_rows(&planner, "select A from T", tx);
+        let reader = db.new_tx();
+        assert_eq!(count(&reader), 50);
+        let tx = db.new_tx();
+        let reclaimed = planner.clone().execute_update("vacuum T", tx.clone()).unwrap();
+        assert!(reclaimed > 0);
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), size);
+        assert_eq!(count(&reader), 50);
+        reader.lock().unwrap().commit().unwrap();
+        assert_eq!(fm.len(&"T.tbl".to_string()).unwrap(), size - reclaimed);
+        assert_eq!(count(&db.new_tx()), 50);
+    }
+
+    #[test]
+    fn test_wounded_transactions_give_up_at_their_next_statement() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .deadlock_policy(DeadlockPolicy::WoundWait)
+            .lock_timeout_ms(5_000)
+            .build()
+            .unwrap();
+        let mut planner = db.planner.clone().unwrap();
+        let tx = db.new_tx();
+        planner.execute_update("create table T(A int)", tx.clone()).unwrap();
+        planner.execute_update("insert into T(A) values(1)", tx.clone()).unwrap();
+        tx.lock().unwrap().commit().unwrap();
+
+        let older = db.new_tx();
+        let younger = db.new_tx();
+        planner
+            .execute_update("update T set A = 2", younger.clone())
+            .unwrap();
+        let start = Instant::now();
+        let h = {
+            let (mut planner, older) = (planner.clone(), older.clone());
+            thread::spawn(move || {
+                let ret = planner.execute_update("update T set A = 3", older.clone());
+                older.lock().unwrap().commit().unwrap();
+                ret
+            })
+        };
+        // the younger one asks for no more locks, but its next statement fails
+        while start.elapsed() < Duration::from_secs(5)
+            && planner
+                .create_query_planner(&"select A from T".to_string(), younger.clone())
+                .is_ok()
+        {
+            thread::yield_now();
+        }
+        younger.lock().unwrap().rollback().unwrap();
+        assert_eq!(h.join().unwrap(), Ok(1));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_serializable_reads_see_no_phantoms() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .query_planner(QueryPlannerKind::Heuristic)
+            .update_planner(UpdatePlannerKind::Index)
+            .lock_timeout_ms(200)
+            .build()
+            .unwrap();
+        let mut planner = db.planner.clone().unwrap();
+        let tx = db.new_tx();
+        planner
+            .execute_update("create table T(A int, B int)", tx.clone())
+            .unwrap();
+        planner
+            .execute_update("create index a_idx on T(A)", tx.clone())
+            .unwrap();
+        for i in 0..10 {
+            let cmd = format!("insert into T(A,B) values({}, {})", i * 2, i);
+            planner.execute_update(&cmd, tx.clone()).unwrap();
+        }
+        // the inserts below go into free slots of the table's one block
+        tx.lock().unwrap().commit().unwrap();
+
+        let qp = planner.clone();
+        let count = |tx: &Arc<Mutex<Transaction>>, qry: &str| count_rows(&qp, qry, tx);
+        let begin = |level| {
+            let tx = db.new_tx();
+            tx.lock().unwrap().set_isolation_level(level).unwrap();
+            tx
+        };
+        let mut insert = |cmd: &str| {
+            let tx = db.new_tx();
+            let ret = planner.execute_update(cmd, tx.clone());
+            if ret.is_ok() {
+                tx.lock().unwrap().commit().unwrap();
+            } else {
+                tx.lock().unwrap().rollback().unwrap();
+            }
+            ret.is_ok()
+        };
+
+        // the anomaly: a second lookup finds a row the first did not
+        let reader = begin(IsolationLevel::ReadCommitted);
+        assert_eq!(count(&reader, "select B from T where A = 5"), 0);
+        assert!(insert("insert into T(A,B) values(5, 50)"));
+        assert_eq!(count(&reader, "select B from T where A = 5"), 1);
+        reader.lock().unwrap().commit().unwrap();
+
+        // a lookup through the index locks its key
+        let reader = begin(IsolationLevel::Serializable);
+        assert_eq!(count(&reader, "select B from T where A = 7"), 0);
+        assert!(!insert("insert into T(A,B) values(7, 70)"));
+        assert_eq!(count(&reader, "select B from T where A = 7"), 0);
+        reader.lock().unwrap().commit().unwrap();
+        assert!(insert("insert into T(A,B) values(7, 70)"));
+
+        // and a scan locks the whole table, its free slots included
+        let reader = begin(IsolationLevel::Serializable);
+        assert_eq!(count(&reader, "select A from T where B = 90"), 0);
+        assert!(!insert("insert into T(A,B) values(9, 90)"));
+        assert_eq!(count(&reader, "select A from T where B = 90"), 0);
+        reader.lock().unwrap().commit().unwrap();
+        assert!(insert("insert into T(A,B) values(9, 90)"));
+    }
+
     fn print_stats(n: i32, p: Arc<Mutex<dyn Plan>>) {
         let p = p.lock().unwrap();
         println!("Here are the stats for plan p {}", n);
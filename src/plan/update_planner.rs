@@ -4,7 +4,7 @@ use crate::{
     parse::{
         create_index_data::CreateIndexData, create_table_data::CreateTableData,
         create_view_data::CreateViewData, delete_data::DeleteData, insert_data::InsertData,
-        modify_data::ModifyData,
+        modify_data::ModifyData, vacuum_data::VacuumData,
     },
     tx::transaction::Transaction,
 };
@@ -28,4 +28,6 @@ pub trait UpdatePlanner: Sync + Send {
         data: CreateIndexData,
         tx: Arc<Mutex<Transaction>>,
     ) -> Result<i32, String>;
+    fn execute_vacuum(&self, data: VacuumData, tx: Arc<Mutex<Transaction>>)
+        -> Result<i32, String>;
 }
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use crate::{
     rdbc::{connection_adapter::ConnectionAdapter, sql_exception::SQLException},
     server::simple_db::SimpleDB,
-    tx::transaction::Transaction,
+    tx::{concurrency::isolation_level::IsolationLevel, transaction::Transaction},
 };
 
 use super::embedded_statement::EmbeddedStatement;
@@ -11,12 +11,70 @@ use super::embedded_statement::EmbeddedStatement;
 pub struct EmbeddedConnection {
     pub(crate) db: SimpleDB,
     current_tx: Arc<Mutex<Transaction>>,
+    auto_commit: bool,
 }
 
 impl EmbeddedConnection {
     pub fn new(db: SimpleDB) -> Self {
         let current_tx = db.new_tx();
-        EmbeddedConnection { db, current_tx }
+        EmbeddedConnection {
+            db,
+            current_tx,
+            auto_commit: true,
+        }
+    }
+
+    // With auto-commit off, statements share one transaction until `commit`,
+    // so that savepoints can span several of them.
+    pub fn set_auto_commit(&mut self, auto_commit: bool) {
+        self.auto_commit = auto_commit;
+    }
+
+    pub fn auto_commit(&self) -> bool {
+        self.auto_commit
+    }
+
+    // Applies to the current transaction and the ones after it, like SET
+    // TRANSACTION ISOLATION LEVEL, and so fails once the current transaction
+    // has run a statement.
+    pub fn set_transaction_isolation(&mut self, level: IsolationLevel) -> Result<(), SQLException> {
+        self.current_tx
+            .lock()
+            .map_err(|e| SQLException::new(e.to_string()))?
+            .set_isolation_level(level)
+            .map_err(SQLException::new)
+    }
+
+    pub fn transaction_isolation(&self) -> Result<IsolationLevel, SQLException> {
+        self.current_tx
+            .lock()
+            .map_err(|e| SQLException::new(e.to_string()))?
+            .isolation_level()
+            .map_err(SQLException::new)
+    }
+
+    pub fn savepoint(&mut self, name: &str) -> Result<(), SQLException> {
+        self.current_tx
+            .lock()
+            .map_err(|e| SQLException::new(e.to_string()))?
+            .savepoint(&name.to_string())
+            .map_err(SQLException::new)
+    }
+
+    pub fn rollback_to_savepoint(&mut self, name: &str) -> Result<(), SQLException> {
+        self.current_tx
+            .lock()
+            .map_err(|e| SQLException::new(e.to_string()))?
+            .rollback_to_savepoint(&name.to_string())
+            .map_err(SQLException::new)
+    }
+
+    pub fn release_savepoint(&mut self, name: &str) -> Result<(), SQLException> {
+        self.current_tx
+            .lock()
+            .map_err(|e| SQLException::new(e.to_string()))?
+            .release_savepoint(&name.to_string())
+            .map_err(SQLException::new)
     }
 
     pub fn commit(&mut self) -> Result<(), crate::rdbc::sql_exception::SQLException> {
@@ -25,7 +83,21 @@ impl EmbeddedConnection {
             .map_err(|e| SQLException::new(e.to_string()))?
             .commit()
             .map_err(|e| SQLException::new(e.to_string()))?;
+        self.renew_transaction()
+    }
+
+    // The next transaction keeps the isolation level.
+    fn renew_transaction(&mut self) -> Result<(), SQLException> {
+        let level = self.transaction_isolation()?;
         self.current_tx = self.db.new_tx();
+        self.set_transaction_isolation(level)
+    }
+
+    // Called after each statement.
+    pub(crate) fn statement_done(&mut self) -> Result<(), SQLException> {
+        if self.auto_commit {
+            self.commit()?;
+        }
         Ok(())
     }
 
@@ -39,9 +111,7 @@ impl EmbeddedConnection {
             .map_err(|e| SQLException::new(e.to_string()))?
             .rollback()
             .map_err(|e| SQLException::new(e.to_string()))?;
-        self.current_tx = self.db.new_tx();
-
-        Ok(())
+        self.renew_transaction()
     }
 }
 
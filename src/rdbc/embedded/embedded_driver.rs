@@ -1,20 +1,33 @@
 use std::path::Path;
 
-use crate::{rdbc::driver_adapter::DriverAdapter, server::simple_db::SimpleDB};
+use crate::{
+    rdbc::{driver_adapter::DriverAdapter, sql_exception::SQLException},
+    server::simple_db::{SimpleDB, SimpleDBBuilder},
+};
 
 use super::embedded_connection::EmbeddedConnection;
 
 pub struct EmbeddedDriver {}
 
+impl EmbeddedDriver {
+    // Opens the database described by `builder`, e.g. one configured from
+    // command-line flags on top of the directory's sabidb.conf.
+    pub fn connect_with(builder: SimpleDBBuilder) -> Result<EmbeddedConnection, SQLException> {
+        let db = builder.build().map_err(SQLException::new)?;
+        Ok(EmbeddedConnection::new(db))
+    }
+}
+
 impl DriverAdapter for EmbeddedDriver {
     type Con = EmbeddedConnection;
 
+    // Uses the options in the directory's sabidb.conf, or the defaults without one.
     fn connect(dbpath: &Path) -> Self::Con {
-        let db = SimpleDB::new(dbpath);
-        // if you try HeuristicQueryPlanner, please comment out the above row and use the below row instead.
-        // Please be careful HeuristicQueryPlanner is not incomplete and it does not perform some funcitons like views.
-        // let db = SimpleDB::new_with_refined_planners(dbpath);
-        EmbeddedConnection::new(db)
+        SimpleDB::builder(dbpath)
+            .config_file()
+            .map_err(SQLException::new)
+            .and_then(Self::connect_with)
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", dbpath.display(), e))
     }
 
     fn get_major_version() -> i32 {
@@ -95,7 +95,7 @@ impl<'a> ResultSetAdapter for EmbeddedResultSet<'a> {
             .map_err(|e| SQLException::new(e.to_string()))?
             .close()
             .map_err(|e| SQLException::new(e.to_string()))?;
-        self.conn.commit()?;
+        self.conn.statement_done()?;
 
         Ok(())
     }
@@ -44,7 +44,7 @@ impl<'a> StatementAdapter<'a> for EmbeddedStatement<'a> {
             .unwrap()
             .execute_update(&cmd, tx)
             .map_err(|e| SQLException::new(e.to_string()))?;
-        self.conn.commit()?;
+        self.conn.statement_done()?;
         Ok(result)
     }
 }
@@ -53,8 +53,11 @@ impl<'a> StatementAdapter<'a> for EmbeddedStatement<'a> {
 mod tests {
     use crate::rdbc::connection_adapter::ConnectionAdapter;
     use crate::rdbc::driver_adapter::DriverAdapter;
-    use crate::rdbc::embedded::embedded_driver::EmbeddedDriver;
+    use crate::rdbc::embedded::{embedded_connection::EmbeddedConnection, embedded_driver::EmbeddedDriver};
+    use crate::rdbc::sql_exception::SQLException;
+    use crate::rdbc::result_set_adapter::ResultSetAdapter;
     use crate::rdbc::statement_adapter::StatementAdapter;
+    use crate::tx::concurrency::isolation_level::IsolationLevel;
     use tempfile::TempDir;
 
     #[test]
@@ -77,4 +80,76 @@ mod tests {
             panic!("Should have been an error");
         }
     }
+
+    fn update(conn: &mut EmbeddedConnection, cmd: &str) -> Result<i32, SQLException> {
+        conn.create_statement()?.execute_update(&cmd.to_string())
+    }
+
+    #[test]
+    fn test_savepoints_skip_a_bad_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut conn = EmbeddedDriver::connect(temp_dir.path());
+        update(&mut conn, "create table t (a int)").unwrap();
+
+        conn.set_auto_commit(false);
+        update(&mut conn, "insert into t (a) values (1)").unwrap();
+        update(&mut conn, "savepoint second_batch").unwrap();
+        update(&mut conn, "insert into t (a) values (2)").unwrap();
+        update(&mut conn, "rollback to second_batch").unwrap();
+        update(&mut conn, "release savepoint second_batch").unwrap();
+        assert!(update(&mut conn, "rollback to second_batch").is_err());
+        conn.savepoint("third_batch").unwrap();
+        update(&mut conn, "insert into t (a) values (3)").unwrap();
+        conn.rollback_to_savepoint("third_batch").unwrap();
+        update(&mut conn, "insert into t (a) values (4)").unwrap();
+        conn.commit().unwrap();
+
+        let mut stmt = conn.create_statement().unwrap();
+        let rs = stmt.execute_query(&"select a from t".to_string()).unwrap();
+        let mut values = Vec::new();
+        while rs.next().unwrap() {
+            values.push(rs.get_int("a".to_string()).unwrap());
+        }
+        values.sort();
+        assert_eq!(values, vec![1, 4]);
+    }
+
+    #[test]
+    fn test_failed_statement_is_undone_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut conn = EmbeddedDriver::connect(temp_dir.path());
+        update(&mut conn, "create table t (a int, b int)").unwrap();
+
+        conn.set_auto_commit(false);
+        update(&mut conn, "insert into t (a, b) values (1, 1)").unwrap();
+        // fails after the row was inserted and its first field set
+        let err = update(&mut conn, "insert into t (a, b) values (2, 'x')").unwrap_err();
+        assert!(err.to_string().contains("Insert failed"));
+        update(&mut conn, "insert into t (a, b) values (3, 3)").unwrap();
+        conn.commit().unwrap();
+
+        let mut stmt = conn.create_statement().unwrap();
+        let rs = stmt.execute_query(&"select a from t".to_string()).unwrap();
+        let mut values = Vec::new();
+        while rs.next().unwrap() {
+            values.push(rs.get_int("a".to_string()).unwrap());
+        }
+        values.sort();
+        assert_eq!(values, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_isolation_level_outlives_auto_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut conn = EmbeddedDriver::connect(temp_dir.path());
+        assert_eq!(conn.transaction_isolation().unwrap(), IsolationLevel::RepeatableRead);
+        update(&mut conn, "set transaction isolation level read committed").unwrap();
+        update(&mut conn, "create table t (a int)").unwrap();
+        update(&mut conn, "insert into t (a) values (1)").unwrap();
+        assert_eq!(conn.transaction_isolation().unwrap(), IsolationLevel::ReadCommitted);
+        conn.set_transaction_isolation(IsolationLevel::Serializable).unwrap();
+        update(&mut conn, "insert into t (a) values (2)").unwrap();
+        assert_eq!(conn.transaction_isolation().unwrap(), IsolationLevel::Serializable);
+        assert!(update(&mut conn, "set transaction isolation level snapshot").is_err());
+    }
 }
@@ -0,0 +1,204 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{constants::INTEGER_BYTES, file::block_id::BlockId, tx::transaction::Transaction};
+
+// A block is assumed to have room until an insert proves otherwise, so a map
+// that is shorter than its table (or missing entirely) is still correct.
+pub const HAS_ROOM: i32 = 0;
+pub const FULL: i32 = 1;
+
+const END_OF_FILE: i32 = -1;
+
+// The map is only a hint, so it is read and written as it is now, under short
+// page latches, without locks or logging: inserters do not wait for each other
+// on it, and a stale entry only costs an insert a look at a block.
+#[derive(Debug, Clone)]
+pub struct FreeSpaceMap {
+    tx: Arc<Mutex<Transaction>>,
+    filename: String,
+}
+
+impl FreeSpaceMap {
+    pub fn new(tx: Arc<Mutex<Transaction>>, tblname: &str) -> Self {
+        FreeSpaceMap {
+            tx,
+            filename: format!("{}.fsm", tblname),
+        }
+    }
+
+    pub fn is_full(&self, blknum: i32) -> Result<bool, String> {
+        self.latched(|tx| {
+            let (blk, offset) = self.entry_position(tx, blknum)?;
+            if blk.number() >= tx.size(self.filename.clone())? {
+                return Ok(false);
+            }
+            let _latch = tx.latch(&blk, false)?;
+            tx.pin(&blk)?;
+            let flag = tx.get_int(&blk, offset);
+            tx.unpin(&blk)?;
+            Ok(flag? == FULL)
+        })
+    }
+
+    // A block that has room again is where the next search starts, if it is
+    // before where the last one stopped.
+    pub fn set_full(&self, blknum: i32, full: bool) -> Result<(), String> {
+        self.latched(|tx| {
+            let (blk, offset) = self.entry_position(tx, blknum)?;
+            {
+                let _latch = tx.latch(&BlockId::new(self.filename.clone(), END_OF_FILE), true)?;
+                while tx.size(self.filename.clone())? <= blk.number() {
+                    tx.append(self.filename.clone())?;
+                }
+            }
+            let _latch = tx.latch(&blk, true)?;
+            tx.pin(&blk)?;
+            let res = tx.set_int(&blk, offset, if full { FULL } else { HAS_ROOM }, false);
+            tx.unpin(&blk)?;
+            res?;
+            if !full && blknum < tx.free_space_hint(&self.filename) {
+                tx.set_free_space_hint(&self.filename, blknum)?;
+            }
+            Ok(())
+        })
+    }
+
+    // Returns a block below `numblocks` that is not known to be full. The search
+    // goes on from where the last one stopped, and wraps around.
+    pub fn find_block_with_room(&self, numblocks: i32) -> Result<Option<i32>, String> {
+        self.latched(|tx| {
+            let hint = tx.free_space_hint(&self.filename);
+            let start = if hint < numblocks { hint } else { 0 };
+            let found = match self.search(tx, start, numblocks)? {
+                Some(blknum) => Some(blknum),
+                None => self.search(tx, 0, start)?,
+            };
+            if let Some(blknum) = found {
+                tx.set_free_space_hint(&self.filename, blknum)?;
+            }
+            Ok(found)
+        })
+    }
+
+    // The first block from `from` up to `to` that is not known to be full.
+    fn search(&self, tx: &Transaction, from: i32, to: i32) -> Result<Option<i32>, String> {
+        let per_block = Self::entries_per_block(tx)?;
+        let fsmsize = tx.size(self.filename.clone())?;
+        let mut blknum = from;
+        while blknum < to {
+            let fsmblknum = blknum / per_block;
+            if fsmblknum >= fsmsize {
+                return Ok(Some(blknum));
+            }
+            let blk = BlockId::new(self.filename.clone(), fsmblknum);
+            let end = to.min((fsmblknum + 1) * per_block);
+            let _latch = tx.latch(&blk, false)?;
+            tx.pin(&blk)?;
+            let found = self.first_with_room(tx, &blk, blknum, end);
+            tx.unpin(&blk)?;
+            if let Some(b) = found? {
+                return Ok(Some(b));
+            }
+            blknum = end;
+        }
+        Ok(None)
+    }
+
+    fn first_with_room(&self, tx: &Transaction, blk: &BlockId, from: i32, to: i32) -> Result<Option<i32>, String> {
+        let per_block = Self::entries_per_block(tx)?;
+        for blknum in from..to {
+            if tx.get_int(blk, ((blknum % per_block) * INTEGER_BYTES) as usize)? == HAS_ROOM {
+                return Ok(Some(blknum));
+            }
+        }
+        Ok(None)
+    }
+
+    fn latched<T>(&self, op: impl FnOnce(&Transaction) -> Result<T, String>) -> Result<T, String> {
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        let latched = tx.set_latched(true);
+        let res = op(&tx);
+        tx.set_latched(latched);
+        res
+    }
+
+    fn entry_position(&self, tx: &Transaction, blknum: i32) -> Result<(BlockId, usize), String> {
+        let per_block = Self::entries_per_block(tx)?;
+        let blk = BlockId::new(self.filename.clone(), blknum / per_block);
+        Ok((blk, ((blknum % per_block) * INTEGER_BYTES) as usize))
+    }
+
+    fn entries_per_block(tx: &Transaction) -> Result<i32, String> {
+        Ok(tx.block_size()? / INTEGER_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::FreeSpaceMap;
+    use crate::server::simple_db::SimpleDB;
+
+    #[test]
+    fn test_free_space_map() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        let tx = db.new_tx();
+        let fsm = FreeSpaceMap::new(tx.clone(), "T");
+
+        assert!(!fsm.is_full(0).unwrap());
+        assert_eq!(fsm.find_block_with_room(3).unwrap(), Some(0));
+        assert_eq!(fsm.find_block_with_room(0).unwrap(), None);
+
+        fsm.set_full(0, true).unwrap();
+        fsm.set_full(1, true).unwrap();
+        assert!(fsm.is_full(0).unwrap());
+        assert_eq!(fsm.find_block_with_room(3).unwrap(), Some(2));
+        assert_eq!(fsm.find_block_with_room(2).unwrap(), None);
+
+        // entries beyond the first map block
+        fsm.set_full(150, true).unwrap();
+        assert!(fsm.is_full(150).unwrap());
+        assert!(!fsm.is_full(149).unwrap());
+
+        fsm.set_full(1, false).unwrap();
+        assert_eq!(fsm.find_block_with_room(3).unwrap(), Some(1));
+
+        tx.lock().unwrap().rollback().unwrap();
+    }
+    #[test]
+    fn test_free_space_map_is_shared_without_locks() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .block_size(400)
+            .lock_timeout_ms(200)
+            .build()
+            .unwrap();
+        let tx1 = db.new_tx();
+        let tx2 = db.new_tx();
+        let fsm1 = FreeSpaceMap::new(tx1.clone(), "T");
+        let fsm2 = FreeSpaceMap::new(tx2.clone(), "T");
+
+        // neither transaction waits for the other, and both see the map as it is
+        for blknum in 0..5 {
+            fsm1.set_full(blknum, true).unwrap();
+        }
+        assert!(fsm2.is_full(3).unwrap());
+        fsm2.set_full(3, false).unwrap();
+        assert!(!fsm1.is_full(3).unwrap());
+
+        // a search goes on from where the last one stopped
+        assert_eq!(fsm1.find_block_with_room(10).unwrap(), Some(3));
+        fsm1.set_full(3, true).unwrap();
+        assert_eq!(fsm2.find_block_with_room(10).unwrap(), Some(5));
+        for blknum in 5..10 {
+            fsm2.set_full(blknum, true).unwrap();
+        }
+        assert_eq!(fsm1.find_block_with_room(10).unwrap(), None);
+        assert_eq!(fsm1.find_block_with_room(11).unwrap(), Some(10));
+
+        tx1.lock().unwrap().commit().unwrap();
+        tx2.lock().unwrap().commit().unwrap();
+    }
+}
@@ -1,5 +1,7 @@
+pub mod free_space_map;
 pub mod layout;
 pub mod record_page;
 pub mod rid;
 pub mod schema;
+pub mod table_compactor;
 pub mod table_scan;
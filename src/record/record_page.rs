@@ -1,6 +1,9 @@
 use std::sync::{Arc, Mutex};
 
-use crate::{file::block_id::BlockId, tx::transaction::Transaction};
+use crate::{
+    buffer::replacement_policy::AccessHint, file::block_id::BlockId,
+    tx::transaction::Transaction,
+};
 
 use super::{layout::Layout, schema::field_type::INTEGER};
 
@@ -20,7 +23,18 @@ impl RecordPage {
         blk: BlockId,
         layout: Layout,
     ) -> Result<Self, String> {
-        tx.lock().map_err(|_| "failed to get lock")?.pin(&blk)?;
+        Self::new_with_hint(tx, blk, layout, AccessHint::Normal)
+    }
+
+    pub fn new_with_hint(
+        tx: Arc<Mutex<Transaction>>,
+        blk: BlockId,
+        layout: Layout,
+        hint: AccessHint,
+    ) -> Result<Self, String> {
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .pin_with_hint(&blk, hint)?;
         Ok(RecordPage {
             tx: tx,
             blk: blk,
@@ -69,10 +83,9 @@ impl RecordPage {
                 .layout
                 .offset(&fldname)?;
 
-        self.tx
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .set_int(&self.blk, fldpos, val, true)?;
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.lock_record(&tx, slot)?;
+        tx.set_int(&self.blk, fldpos, val, true)?;
 
         Ok(())
     }
@@ -83,10 +96,9 @@ impl RecordPage {
                 .layout
                 .offset(&fldname)?;
 
-        self.tx
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .set_string(&self.blk, fldpos, val, true)?;
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.lock_record(&tx, slot)?;
+        tx.set_string(&self.blk, fldpos, val, true)?;
 
         Ok(())
     }
@@ -96,6 +108,9 @@ impl RecordPage {
     }
 
     pub fn delete(&mut self, slot: i32) -> Result<(), String> {
+        let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+        self.lock_record(&tx, slot)?;
+        drop(tx);
         self.set_flag(slot, EMPTY)
     }
 
@@ -135,12 +150,21 @@ impl RecordPage {
         Ok(())
     }
 
+    pub fn is_used(&self, slot: i32) -> Result<bool, String> {
+        Ok(self
+            .tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .get_int(&self.blk, self.offset(slot)?)?
+            == USED)
+    }
+
     pub fn next_after(&self, slot: i32) -> Result<i32, String> {
         self.search_after(slot, USED)
     }
 
     pub fn insert_after(&mut self, slot: i32) -> Result<i32, String> {
-        let newslot = self.search_after(slot, EMPTY)?;
+        let newslot = self.free_slot_after(slot)?;
         if newslot >= 0 {
             self.set_flag(newslot, USED)?;
         }
@@ -160,6 +184,33 @@ impl RecordPage {
         )
     }
 
+    // Changing a record locks just its slot; allocating or formatting slots
+    // locks the whole block.
+    fn lock_record(&self, tx: &Transaction, slot: i32) -> Result<(), String> {
+        tx.x_lock_record(&self.blk, slot, self.offset(slot)?, self.offset(slot + 1)?)
+    }
+
+    // Finds the next empty slot and locks just its record, so inserts into the
+    // block by other transactions go on. A slot a running transaction deleted
+    // from is empty too; the lock waits for that transaction to end. The lock
+    // on a slot that turns out not to be free is given back.
+    fn free_slot_after(&self, slot: i32) -> Result<i32, String> {
+        let mut slot = slot + 1;
+        while self.is_valid_slot(slot)? {
+            let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+            let (pos, end) = (self.offset(slot)?, self.offset(slot + 1)?);
+            if tx.peek_int(&self.blk, pos)? == EMPTY && tx.lock_free_slot(&self.blk, slot, pos, end)? {
+                // the delete may have been rolled back while the lock waited
+                if tx.peek_int(&self.blk, pos)? == EMPTY {
+                    return Ok(slot);
+                }
+                tx.unlock_free_slot(&self.blk, slot, pos, end)?;
+            }
+            slot += 1;
+        }
+        Ok(-1)
+    }
+
     fn search_after(&self, slot: i32, flag: i32) -> Result<i32, String> {
         let mut slot = slot + 1;
         while self.is_valid_slot(slot)? {
@@ -200,11 +251,16 @@ impl RecordPage {
 #[cfg(test)]
 mod tests {
 
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
 
     use tempfile::TempDir;
 
-    use crate::{record::schema::Schema, server::simple_db::SimpleDB};
+    use crate::{
+        file::block_id::BlockId,
+        record::schema::Schema,
+        server::simple_db::SimpleDB,
+        tx::{concurrency::isolation_level::IsolationLevel, transaction::Transaction},
+    };
 
     use super::{Layout, RecordPage};
 
@@ -311,4 +367,151 @@ mod tests {
         tx.lock().unwrap().unpin(&blk).unwrap();
         tx.lock().unwrap().commit().unwrap();
     }
+
+    #[test]
+    fn test_record_page_reads_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(SimpleDB::new_with_sizes(temp_dir.path(), 400, 8));
+        let sch = Schema::new();
+        sch.add_int_field(&"A".to_string()).unwrap();
+        let layout = Layout::new_from_schema(sch).unwrap();
+
+        let tx = db.new_tx();
+        let blk = tx.lock().unwrap().append("test_snapshot".to_string()).unwrap();
+        let mut rp = RecordPage::new(tx.clone(), blk.clone(), layout.clone()).unwrap();
+        rp.format().unwrap();
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_int(slot, "A".to_string(), 1).unwrap();
+        tx.lock().unwrap().commit().unwrap();
+
+        let reader = db.new_tx();
+        let mut reader_rp = RecordPage::new(reader.clone(), blk.clone(), layout.clone()).unwrap();
+        let writer = db.new_tx();
+        let mut writer_rp = RecordPage::new(writer.clone(), blk.clone(), layout.clone()).unwrap();
+        writer_rp.set_int(slot, "A".to_string(), 2).unwrap();
+
+        // the reader neither waits for the writer nor sees its change
+        assert_eq!(reader_rp.get_int(slot, "A".to_string()).unwrap(), 1);
+        writer.lock().unwrap().commit().unwrap();
+        assert_eq!(reader_rp.get_int(slot, "A".to_string()).unwrap(), 1);
+
+        // the first updater wins
+        let err = reader_rp.set_int(slot, "A".to_string(), 3).unwrap_err();
+        assert!(err.contains("could not serialize"), "{}", err);
+        reader.lock().unwrap().rollback().unwrap();
+
+        let tx = db.new_tx();
+        let rp = RecordPage::new(tx.clone(), blk, layout).unwrap();
+        assert_eq!(rp.get_int(slot, "A".to_string()).unwrap(), 2);
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(db.version_store().size(), 0);
+    }
+
+    #[test]
+    fn test_record_page_gives_back_rejected_slots() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .lock_timeout_ms(100)
+            .build()
+            .unwrap();
+        let sch = Schema::new();
+        sch.add_int_field(&"A".to_string()).unwrap();
+        let layout = Layout::new_from_schema(sch).unwrap();
+
+        let tx = db.new_tx();
+        let blk = tx.lock().unwrap().append("test_slots".to_string()).unwrap();
+        let mut rp = RecordPage::new(tx.clone(), blk.clone(), layout.clone()).unwrap();
+        rp.format().unwrap();
+        for i in 0..2 {
+            let slot = rp.insert_after(-1).unwrap();
+            rp.set_int(slot, "A".to_string(), i).unwrap();
+        }
+        tx.lock().unwrap().commit().unwrap();
+
+        let reader = db.new_tx();
+        let deleter = db.new_tx();
+        RecordPage::new(deleter.clone(), blk.clone(), layout.clone())
+            .unwrap()
+            .delete(0)
+            .unwrap();
+        deleter.lock().unwrap().commit().unwrap();
+
+        // the reader's snapshot still sees the deleted record, so it inserts
+        // after it, and leaves the slot to others
+        let mut reader_rp = RecordPage::new(reader.clone(), blk.clone(), layout.clone()).unwrap();
+        assert_eq!(reader_rp.insert_after(-1).unwrap(), 2);
+        let other = db.new_tx();
+        let mut other_rp = RecordPage::new(other.clone(), blk.clone(), layout.clone()).unwrap();
+        assert_eq!(other_rp.insert_after(-1).unwrap(), 0);
+        other.lock().unwrap().commit().unwrap();
+        reader.lock().unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_record_page_isolation_levels() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .lock_timeout_ms(100)
+            .build()
+            .unwrap();
+        let sch = Schema::new();
+        sch.add_int_field(&"A".to_string()).unwrap();
+        let layout = Layout::new_from_schema(sch).unwrap();
+        let open = |tx: &Arc<Mutex<Transaction>>, blk: &BlockId| {
+            RecordPage::new(tx.clone(), blk.clone(), layout.clone()).unwrap()
+        };
+        let begin = |level| {
+            let tx = db.new_tx();
+            tx.lock().unwrap().set_isolation_level(level).unwrap();
+            tx
+        };
+
+        let tx = db.new_tx();
+        let blk = tx.lock().unwrap().append("test_levels".to_string()).unwrap();
+        let mut rp = open(&tx, &blk);
+        rp.format().unwrap();
+        let slot = rp.insert_after(-1).unwrap();
+        rp.set_int(slot, "A".to_string(), 1).unwrap();
+        tx.lock().unwrap().commit().unwrap();
+
+        let repeatable = begin(IsolationLevel::RepeatableRead);
+        let writer = db.new_tx();
+        open(&writer, &blk).set_int(slot, "A".to_string(), 2).unwrap();
+
+        let uncommitted = begin(IsolationLevel::ReadUncommitted);
+        assert_eq!(open(&uncommitted, &blk).get_int(slot, "A".to_string()).unwrap(), 2);
+        // read committed waits for the writer
+        let committed = begin(IsolationLevel::ReadCommitted);
+        assert!(open(&committed, &blk).get_int(slot, "A".to_string()).is_err());
+        writer.lock().unwrap().commit().unwrap();
+        assert_eq!(open(&committed, &blk).get_int(slot, "A".to_string()).unwrap(), 2);
+        assert_eq!(open(&repeatable, &blk).get_int(slot, "A".to_string()).unwrap(), 1);
+        // and holds no lock after reading
+        let writer = db.new_tx();
+        open(&writer, &blk).set_int(slot, "A".to_string(), 3).unwrap();
+        writer.lock().unwrap().commit().unwrap();
+
+        // serializable keeps its shared lock until it ends
+        let serializable = begin(IsolationLevel::Serializable);
+        assert_eq!(open(&serializable, &blk).get_int(slot, "A".to_string()).unwrap(), 3);
+        let writer = db.new_tx();
+        assert!(open(&writer, &blk).set_int(slot, "A".to_string(), 4).is_err());
+        writer.lock().unwrap().rollback().unwrap();
+
+        // a transaction keeps the level it read with
+        for level in [IsolationLevel::ReadCommitted, IsolationLevel::Serializable] {
+            assert!(repeatable.lock().unwrap().set_isolation_level(level).is_err());
+        }
+        assert!(repeatable
+            .lock()
+            .unwrap()
+            .set_isolation_level(IsolationLevel::RepeatableRead)
+            .is_ok());
+        assert_eq!(open(&repeatable, &blk).get_int(slot, "A".to_string()).unwrap(), 1);
+
+        for tx in [repeatable, uncommitted, committed, serializable] {
+            tx.lock().unwrap().commit().unwrap();
+        }
+        assert_eq!(db.version_store().size(), 0);
+    }
 }
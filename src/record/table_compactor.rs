@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    file::block_id::BlockId,
+    index::index::Index,
+    metadata::{
+        index_info::IndexInfo, matadata_manager::MetadataManager, table_manager::CATALOG_TABLES,
+    },
+    query::constant::Constant,
+    tx::{concurrency::lock_mode::LockMode, transaction::Transaction},
+};
+
+use super::{
+    free_space_map::FreeSpaceMap,
+    layout::Layout,
+    record_page::RecordPage,
+    rid::RID,
+    schema::field_type::{BOOLEAN, INTEGER},
+};
+
+// Compacts the named table, or every user table when none is named. The
+// catalog tables are left alone unless named, so a bare VACUUM does not lock
+// them against the rest of the database. Returns the blocks reclaimed.
+pub fn vacuum(
+    mdm: &MetadataManager,
+    tblname: Option<String>,
+    tx: Arc<Mutex<Transaction>>,
+) -> Result<i32, String> {
+    let tblnames = mdm.get_table_names(tx.clone())?;
+    let targets = match tblname {
+        Some(tblname) if !tblnames.contains(&tblname) => {
+            return Err(format!("table {} does not exist", tblname))
+        }
+        Some(tblname) => vec![tblname],
+        None => tblnames
+            .into_iter()
+            .filter(|tblname| !CATALOG_TABLES.contains(&tblname.as_str()))
+            .collect(),
+    };
+
+    let mut reclaimed = 0;
+    for tblname in targets {
+        let layout = mdm.get_layout(tblname.clone(), tx.clone())?;
+        let indexes = mdm.get_index_info(tblname.clone(), tx.clone())?;
+        let compactor = TableCompactor::new(tx.clone(), tblname.clone(), layout, indexes);
+        reclaimed += compactor.compact()?;
+        mdm.invalidate_stat_info(&tblname)?;
+    }
+    Ok(reclaimed)
+}
+
+// Moves records from the end of a table into free slots near its start and
+// truncates the emptied tail blocks when the transaction commits.
+pub struct TableCompactor {
+    tx: Arc<Mutex<Transaction>>,
+    layout: Layout,
+    fsm: FreeSpaceMap,
+    filename: String,
+    indexes: HashMap<String, IndexInfo>,
+}
+
+impl TableCompactor {
+    pub fn new(
+        tx: Arc<Mutex<Transaction>>,
+        tblname: String,
+        layout: Layout,
+        indexes: HashMap<String, IndexInfo>,
+    ) -> Self {
+        TableCompactor {
+            fsm: FreeSpaceMap::new(tx.clone(), &tblname),
+            tx,
+            layout,
+            filename: tblname + ".tbl",
+            indexes,
+        }
+    }
+
+    // Returns the number of blocks reclaimed. The table is locked against other
+    // writers, and records are moved as they are now, not as the snapshot sees
+    // them.
+    pub fn compact(&self) -> Result<i32, String> {
+        let locking = {
+            let tx = self.tx.lock().map_err(|_| "failed to get lock")?;
+            tx.lock_file(&self.filename, LockMode::Exclusive)?;
+            tx.set_locking_reads(true)
+        };
+        let ret = self.compact_locked();
+        self.tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .set_locking_reads(locking);
+        ret
+    }
+
+    fn compact_locked(&self) -> Result<i32, String> {
+        let size = self
+            .tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .size(self.filename.clone())?;
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let mut idxs = HashMap::new();
+        for (fldname, ii) in self.indexes.iter() {
+            idxs.insert(fldname.clone(), ii.open()?);
+        }
+        self.sweep(&idxs, size)?;
+
+        let mut low = 0;
+        let mut high = size - 1;
+        let mut dst: Option<RecordPage> = None;
+        while low < high {
+            let mut src = self.record_page(high)?;
+            let mut slot = src.next_after(-1)?;
+            while slot >= 0 && low < high {
+                let rp = match dst.as_mut() {
+                    Some(rp) => rp,
+                    None => dst.insert(self.record_page(low)?),
+                };
+                let newslot = rp.insert_after(-1)?;
+                if newslot < 0 {
+                    self.fsm.set_full(low, true)?;
+                    self.close(rp)?;
+                    dst = None;
+                    low += 1;
+                    continue;
+                }
+                self.move_record(&mut src, slot, rp, newslot, &idxs)?;
+                slot = src.next_after(slot)?;
+            }
+            self.close(&src)?;
+            if slot >= 0 {
+                break;
+            }
+            high -= 1;
+        }
+        if let Some(rp) = dst {
+            self.close(&rp)?;
+        }
+        for (_, idx) in idxs.iter() {
+            idx.lock().map_err(|_| "failed to get lock")?.close()?;
+        }
+
+        // Blocks that lost records may have room again.
+        for blknum in high.max(0)..size {
+            if self.fsm.is_full(blknum)? {
+                self.fsm.set_full(blknum, false)?;
+            }
+        }
+
+        let mut newsize = high + 1;
+        while newsize > 0 && self.is_empty_block(newsize - 1)? {
+            newsize -= 1;
+        }
+        if newsize < size {
+            self.tx
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .truncate(self.filename.clone(), newsize)?;
+        }
+        Ok(size - newsize)
+    }
+
+    // Retires the index entries whose records are gone. Deleted records normally
+    // retire their own entries, but the retirements live only in memory until
+    // they expire, so a crash can leave entries behind. Entries still waiting
+    // on older snapshots are left to go the usual way.
+    fn sweep(&self, idxs: &HashMap<String, Arc<Mutex<dyn Index>>>, size: i32) -> Result<(), String> {
+        for (fldname, idx) in idxs.iter() {
+            let idxname = self.indexes[fldname].index_name();
+            let mut idx = idx.lock().map_err(|_| "failed to get lock")?;
+            for (val, rid) in idx.entries()? {
+                if self.has_record(fldname, &val, &rid, size)?
+                    || self
+                        .tx
+                        .lock()
+                        .map_err(|_| "failed to get lock")?
+                        .is_retired(&idxname, &val, &rid)?
+                {
+                    continue;
+                }
+                idx.delete(&val, rid)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the record the entry leads to is there, with the value indexed.
+    fn has_record(&self, fldname: &String, val: &Constant, rid: &RID, size: i32) -> Result<bool, String> {
+        if rid.block_number() >= size {
+            return Ok(false);
+        }
+        let rp = self.record_page(rid.block_number())?;
+        let found = match rp.next_after(rid.slot() - 1) {
+            Ok(slot) if slot == rid.slot() => self.get_val(&rp, slot, fldname).map(|v| v.eq(val)),
+            Ok(_) => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.close(&rp)?;
+        found
+    }
+
+    fn move_record(
+        &self,
+        src: &mut RecordPage,
+        srcslot: i32,
+        dst: &mut RecordPage,
+        dstslot: i32,
+        idxs: &HashMap<String, Arc<Mutex<dyn Index>>>,
+    ) -> Result<(), String> {
+        let fldnames = self
+            .layout
+            .schema()
+            .fields()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clone();
+        for fldname in fldnames.iter() {
+            if self.layout.schema().field_type(fldname)? == INTEGER
+                || self.layout.schema().field_type(fldname)? == BOOLEAN
+            {
+                dst.set_int(dstslot, fldname.clone(), src.get_int(srcslot, fldname.clone())?)?;
+            } else {
+                dst.set_string(
+                    dstslot,
+                    fldname.clone(),
+                    src.get_string(srcslot, fldname.clone())?,
+                )?;
+            }
+        }
+        src.delete(srcslot)?;
+
+        let oldrid = RID::new(src.block().number(), srcslot);
+        let newrid = RID::new(dst.block().number(), dstslot);
+        for (fldname, idx) in idxs.iter() {
+            let val = self.get_val(dst, dstslot, fldname)?;
+            let mut idx = idx.lock().map_err(|_| "failed to get lock")?;
+            idx.delete(&val, oldrid.clone())?;
+            idx.insert(&val, newrid.clone())?;
+        }
+        Ok(())
+    }
+
+    fn get_val(&self, rp: &RecordPage, slot: i32, fldname: &String) -> Result<Constant, String> {
+        match self.layout.schema().field_type(fldname)? {
+            INTEGER => Ok(Constant::new_from_i32(rp.get_int(slot, fldname.clone())?)),
+            BOOLEAN => Ok(Constant::new_from_bool(rp.get_bool(slot, fldname.clone())?)),
+            _ => Ok(Constant::new_from_string(rp.get_string(slot, fldname.clone())?)),
+        }
+    }
+
+    fn is_empty_block(&self, blknum: i32) -> Result<bool, String> {
+        let rp = self.record_page(blknum)?;
+        let slot = rp.next_after(-1);
+        self.close(&rp)?;
+        Ok(slot? < 0)
+    }
+
+    fn record_page(&self, blknum: i32) -> Result<RecordPage, String> {
+        let blk = BlockId::new(self.filename.clone(), blknum);
+        RecordPage::new(self.tx.clone(), blk, self.layout.clone())
+    }
+
+    fn close(&self, rp: &RecordPage) -> Result<(), String> {
+        self.tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .unpin(&rp.block())
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
+    buffer::replacement_policy::AccessHint,
     file::block_id::BlockId,
     materialize::sort_scan::SortScan,
     query::{constant::Constant, scan::Scan, update_scan::UpdateScan},
@@ -8,13 +9,14 @@ use crate::{
     tx::transaction::Transaction,
 };
 
-use super::{layout::Layout, record_page::RecordPage, rid::RID};
+use super::{free_space_map::FreeSpaceMap, layout::Layout, record_page::RecordPage, rid::RID};
 
 #[derive(Debug, Clone)]
 pub struct TableScan {
     tx: Arc<Mutex<Transaction>>,
     layout: Layout,
     rp: Arc<Mutex<RecordPage>>,
+    fsm: FreeSpaceMap,
     filename: String,
     current_slot: i32,
 }
@@ -25,6 +27,7 @@ impl TableScan {
         tblname: String,
         layout: Layout,
     ) -> Result<Self, String> {
+        let fsm = FreeSpaceMap::new(tx.clone(), &tblname);
         let filename = tblname + ".tbl";
         let blk = if tx
             .lock()
@@ -48,19 +51,29 @@ impl TableScan {
             tx: tx,
             layout: layout,
             rp: Arc::new(Mutex::new(rp)),
+            fsm,
             filename: filename,
             current_slot: -1,
         })
     }
 
+    // Whether there is a record at the current slot, as the transaction sees it.
+    pub fn is_used(&self) -> Result<bool, String> {
+        self.rp
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .is_used(self.current_slot)
+    }
+
     // Private auxiliary methods
-    fn move_to_block(&mut self, blknum: i32) -> Result<(), String> {
+    fn move_to_block(&mut self, blknum: i32, hint: AccessHint) -> Result<(), String> {
         self.close()?;
         let blk = BlockId::new(self.filename.clone(), blknum);
-        self.rp = Arc::new(Mutex::new(RecordPage::new(
+        self.rp = Arc::new(Mutex::new(RecordPage::new_with_hint(
             self.tx.clone(),
             blk,
             self.layout.clone(),
+            hint,
         )?));
         self.current_slot = -1;
 
@@ -102,7 +115,11 @@ impl TableScan {
 
 impl Scan for TableScan {
     fn before_first(&mut self) -> Result<(), String> {
-        self.move_to_block(0)
+        self.tx
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .lock_for_scan(&self.filename)?;
+        self.move_to_block(0, AccessHint::Sequential)
     }
 
     fn next(&mut self) -> Result<bool, String> {
@@ -122,7 +139,7 @@ impl Scan for TableScan {
                 .block()
                 .number()
                 + 1;
-            self.move_to_block(blknum)?;
+            self.move_to_block(blknum, AccessHint::Sequential)?;
             self.current_slot = self
                 .rp
                 .lock()
@@ -235,38 +252,49 @@ impl UpdateScan for TableScan {
     }
 
     fn insert(&mut self) -> Result<(), String> {
-        self.current_slot = self
-            .rp
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .insert_after(self.current_slot)?;
-        while self.current_slot < 0 {
-            if self.at_last_block()? {
-                self.move_to_new_block()?;
-            } else {
-                let blknum = self
+        loop {
+            let blknum = self
+                .rp
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .block()
+                .number();
+            if !self.fsm.is_full(blknum)? {
+                let searched_whole_block = self.current_slot < 0;
+                self.current_slot = self
                     .rp
                     .lock()
                     .map_err(|_| "failed to get lock")?
-                    .block()
-                    .number()
-                    + 1;
-                self.move_to_block(blknum)?;
+                    .insert_after(self.current_slot)?;
+                if self.current_slot >= 0 {
+                    return Ok(());
+                }
+                if searched_whole_block {
+                    self.fsm.set_full(blknum, true)?;
+                }
             }
-            self.current_slot = self
-                .rp
+
+            let size = self
+                .tx
                 .lock()
                 .map_err(|_| "failed to get lock")?
-                .insert_after(self.current_slot)?;
+                .size(self.filename.clone())?;
+            match self.fsm.find_block_with_room(size)? {
+                Some(blknum) => self.move_to_block(blknum, AccessHint::Normal)?,
+                None => self.move_to_new_block()?,
+            }
         }
-        Ok(())
     }
 
     fn delete(&mut self) -> Result<(), String> {
-        self.rp
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .delete(self.current_slot)?;
+        let blknum = {
+            let mut rp = self.rp.lock().map_err(|_| "failed to get lock")?;
+            rp.delete(self.current_slot)?;
+            rp.block().number()
+        };
+        if self.fsm.is_full(blknum)? {
+            self.fsm.set_full(blknum, false)?;
+        }
         Ok(())
     }
 
@@ -387,4 +415,116 @@ mod tests {
         ts.close().unwrap();
         tx.lock().unwrap().commit().unwrap();
     }
+
+    #[test]
+    fn test_insert_reuses_free_slot_in_earlier_block() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(SimpleDB::new_with_sizes(temp_dir.path(), 400, 8));
+        let tx = db.new_tx();
+
+        let sch = Schema::new();
+        sch.add_int_field(&"A".to_string()).unwrap();
+        sch.add_string_field(&"B".to_string(), 9).unwrap();
+        let layout = Layout::new_from_schema(sch).unwrap();
+
+        let mut ts = TableScan::new(tx.clone(), "T".to_string(), layout.clone()).unwrap();
+        // 19 slots fit in a block, so this fills exactly three blocks
+        for i in 0..57 {
+            ts.insert().unwrap();
+            ts.set_int("A".to_string(), i).unwrap();
+        }
+        assert_eq!(ts.get_rid().unwrap().block_number(), 2);
+
+        let mut deleter = TableScan::new(tx.clone(), "T".to_string(), layout).unwrap();
+        while deleter.next().unwrap() {
+            if deleter.get_int(&"A".to_string()).unwrap() == 3 {
+                deleter.delete().unwrap();
+                break;
+            }
+        }
+        deleter.close().unwrap();
+
+        // the scan is positioned on the last block, yet the freed slot in block 0 is reused
+        ts.insert().unwrap();
+        assert_eq!(ts.get_rid().unwrap().block_number(), 0);
+        assert_eq!(tx.lock().unwrap().size("T.tbl".to_string()).unwrap(), 3);
+        ts.close().unwrap();
+        tx.lock().unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_updates_lock_records_not_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .lock_timeout_ms(200)
+            .build()
+            .unwrap();
+        let sch = Schema::new();
+        sch.add_int_field(&"A".to_string()).unwrap();
+        let layout = Layout::new_from_schema(sch).unwrap();
+
+        let tx = db.new_tx();
+        let mut ts = TableScan::new(tx.clone(), "T".to_string(), layout.clone()).unwrap();
+        let mut rids = Vec::new();
+        for i in 0..2 {
+            ts.insert().unwrap();
+            ts.set_int("A".to_string(), i).unwrap();
+            rids.push(ts.get_rid().unwrap());
+        }
+        ts.close().unwrap();
+        tx.lock().unwrap().commit().unwrap();
+        assert_eq!(rids[0].block_number(), rids[1].block_number());
+
+        // two records in the same block are changed at once
+        let tx1 = db.new_tx();
+        let mut ts1 = TableScan::new(tx1.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts1.move_to_rid(rids[0].clone()).unwrap();
+        ts1.set_int("A".to_string(), 10).unwrap();
+        let tx2 = db.new_tx();
+        let mut ts2 = TableScan::new(tx2.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts2.move_to_rid(rids[1].clone()).unwrap();
+        ts2.set_int("A".to_string(), 11).unwrap();
+
+        // but one record only by one transaction
+        ts2.move_to_rid(rids[0].clone()).unwrap();
+        assert!(ts2.set_int("A".to_string(), 12).is_err());
+        // inserts into the block lock only the slots they take
+        ts2.insert().unwrap();
+        let inserted = ts2.get_rid().unwrap();
+        ts1.insert().unwrap();
+        assert_eq!(inserted.block_number(), rids[0].block_number());
+        assert_ne!(ts1.get_rid().unwrap(), inserted);
+
+        ts1.close().unwrap();
+        tx1.lock().unwrap().commit().unwrap();
+        ts2.close().unwrap();
+        tx2.lock().unwrap().commit().unwrap();
+
+        // a slot freed by a running transaction is not taken until it ends
+        let tx1 = db.new_tx();
+        let mut ts1 = TableScan::new(tx1.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts1.move_to_rid(rids[0].clone()).unwrap();
+        ts1.delete().unwrap();
+        let tx2 = db.new_tx();
+        let mut ts2 = TableScan::new(tx2.clone(), "T".to_string(), layout.clone()).unwrap();
+        assert!(ts2.insert().is_err());
+        ts2.close().unwrap();
+        tx2.lock().unwrap().rollback().unwrap();
+        let tx2 = db.new_tx();
+        ts1.close().unwrap();
+        tx1.lock().unwrap().commit().unwrap();
+
+        // nor by a repeatable read transaction whose snapshot still has the record
+        let mut ts2 = TableScan::new(tx2.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts2.insert().unwrap();
+        assert_ne!(ts2.get_rid().unwrap(), rids[0]);
+        ts2.close().unwrap();
+        tx2.lock().unwrap().commit().unwrap();
+        let tx3 = db.new_tx();
+        let mut ts3 = TableScan::new(tx3.clone(), "T".to_string(), layout.clone()).unwrap();
+        ts3.insert().unwrap();
+        assert_eq!(ts3.get_rid().unwrap(), rids[0]);
+        ts3.close().unwrap();
+        tx3.lock().unwrap().commit().unwrap();
+    }
 }
@@ -0,0 +1,252 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::buffer_manager::BufferManager, log::log_manager::LogManager,
+    tx::transaction::Transaction,
+};
+
+use super::log_level::LogLevel;
+
+#[derive(Debug, Default)]
+struct WriterStats {
+    pages_written: AtomicU64,
+    checkpoints: AtomicU64,
+}
+
+// Flushes dirty buffers every `writer_interval` and takes a non-quiescent
+// checkpoint every `checkpoint_interval`, so that commits and evictions find
+// fewer pages to write. The thread stops when the writer is dropped.
+pub struct BackgroundWriter {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    stats: Arc<WriterStats>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWriter {
+    pub fn start(
+        bm: Arc<BufferManager>,
+        lm: Arc<Mutex<LogManager>>,
+        writer_interval: Option<Duration>,
+        checkpoint_interval: Option<Duration>,
+    ) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let stats = Arc::new(WriterStats::default());
+        let handle = {
+            let stop = stop.clone();
+            let stats = stats.clone();
+            thread::spawn(move || {
+                run(bm, lm, writer_interval, checkpoint_interval, stop, stats)
+            })
+        };
+        BackgroundWriter {
+            stop,
+            stats,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn pages_written(&self) -> u64 {
+        self.stats.pages_written.load(Ordering::Relaxed)
+    }
+
+    pub fn checkpoints(&self) -> u64 {
+        self.stats.checkpoints.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(&mut self) {
+        let (stopped, wakeup) = &*self.stop;
+        if let Ok(mut stopped) = stopped.lock() {
+            *stopped = true;
+        }
+        wakeup.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    bm: Arc<BufferManager>,
+    lm: Arc<Mutex<LogManager>>,
+    writer_interval: Option<Duration>,
+    checkpoint_interval: Option<Duration>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    stats: Arc<WriterStats>,
+) {
+    let level = lm.lock().map(|lm| lm.log_level()).unwrap_or_default();
+    let mut next_write = writer_interval.map(|d| Instant::now() + d);
+    let mut next_checkpoint = checkpoint_interval.map(|d| Instant::now() + d);
+    let (stopped, wakeup) = &*stop;
+    let Ok(mut st) = stopped.lock() else {
+        return;
+    };
+    loop {
+        let deadline = match (next_write, next_checkpoint) {
+            (Some(w), Some(c)) => w.min(c),
+            (Some(w), None) => w,
+            (None, Some(c)) => c,
+            (None, None) => Instant::now() + Duration::from_secs(3600),
+        };
+        while !*st {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            st = match wakeup.wait_timeout(st, deadline - now) {
+                Ok((st, _)) => st,
+                Err(_) => return,
+            };
+        }
+        if *st {
+            return;
+        }
+        // the stop flag is not held while doing I/O so that drop never waits on it
+        drop(st);
+
+        let now = Instant::now();
+        if let (Some(at), Some(interval)) = (next_write, writer_interval) {
+            if now >= at {
+                match bm.flush_dirty() {
+                    Ok(n) => {
+                        stats.pages_written.fetch_add(n as u64, Ordering::Relaxed);
+                    }
+                    Err(e) => {
+                        if level.enabled(LogLevel::Info) {
+                            println!("background writer failed: {}", e);
+                        }
+                    }
+                }
+                next_write = Some(now + interval);
+            }
+        }
+        if let (Some(at), Some(interval)) = (next_checkpoint, checkpoint_interval) {
+            if now >= at {
+                match Transaction::checkpoint(lm.clone(), bm.clone()) {
+                    Ok(_) => {
+                        stats.checkpoints.fetch_add(1, Ordering::Relaxed);
+                        if level.enabled(LogLevel::Debug) {
+                            println!("checkpoint taken");
+                        }
+                    }
+                    Err(e) => {
+                        if level.enabled(LogLevel::Info) {
+                            println!("checkpoint failed: {}", e);
+                        }
+                    }
+                }
+                next_checkpoint = Some(now + interval);
+            }
+        }
+
+        st = match stopped.lock() {
+            Ok(st) => st,
+            Err(_) => return,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use tempfile::TempDir;
+
+    use crate::{
+        file::{block_id::BlockId, page::Page},
+        server::simple_db::SimpleDB,
+    };
+
+    #[test]
+    fn test_background_writer_flushes_dirty_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        // no checkpointer, which would flush the page too
+        let db = SimpleDB::builder(temp_dir.path())
+            .writer_interval_ms(20)
+            .build()
+            .unwrap();
+        let fm = db.file_manager();
+
+        let tx = db.new_tx();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        {
+            let tx = tx.lock().unwrap();
+            tx.append("testfile".to_string()).unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 80, 345, true).unwrap();
+            tx.unpin(&blk).unwrap();
+        }
+        // wait until the dirty page of the running transaction reaches disk
+        let mut p = Page::new_from_blocksize(fm.block_size() as usize);
+        for _ in 0..100 {
+            fm.read(&blk, &mut p).unwrap();
+            if p.get_int(80).unwrap() == 345 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(p.get_int(80).unwrap(), 345);
+        assert!(db.background_writer().unwrap().pages_written() > 0);
+
+        // the database goes away without the transaction finishing, and the
+        // recovery run when it is opened again undoes the change on disk
+        drop(db);
+        let db = SimpleDB::new(temp_dir.path());
+        let tx = db.new_tx();
+        let tx = tx.lock().unwrap();
+        tx.pin(&blk).unwrap();
+        assert_eq!(tx.get_int(&blk, 80).unwrap(), 0);
+        tx.unpin(&blk).unwrap();
+    }
+
+    #[test]
+    fn test_background_writer_checkpoints() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::builder(temp_dir.path())
+            .checkpoint_interval_ms(20)
+            .build()
+            .unwrap();
+
+        let tx = db.new_tx();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        {
+            let tx = tx.lock().unwrap();
+            tx.append("testfile".to_string()).unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 80, 345, true).unwrap();
+            tx.unpin(&blk).unwrap();
+        }
+
+        // checkpoints do not wait for the running transaction
+        for _ in 0..100 {
+            if db.background_writer().unwrap().checkpoints() > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(db.background_writer().unwrap().checkpoints() > 0);
+        let newest = db.log_mgr().lock().unwrap().iterator().unwrap().next().unwrap();
+        // <NQCKPT next_txnum begin_lsn count txnum...>
+        let rec = Page::new_from_bytes(newest.unwrap());
+        assert_eq!(rec.get_int(0).unwrap(), 7);
+        assert!(rec.get_long(4).unwrap() > tx.lock().unwrap().tx_num());
+        assert_eq!(rec.get_int(20).unwrap(), 1);
+        assert_eq!(rec.get_long(24).unwrap(), tx.lock().unwrap().tx_num());
+        tx.lock().unwrap().commit().unwrap();
+
+        // dropping the database stops the thread
+        drop(db);
+    }
+}
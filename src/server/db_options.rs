@@ -0,0 +1,273 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    buffer::{
+        buffer_manager::{DEFAULT_PIN_TIMEOUT_MS, DEFAULT_READ_AHEAD_BLOCKS},
+        replacement_policy::ReplacementPolicyKind,
+    },
+    constants::CONFIG_FILE,
+    file::durability_mode::DurabilityMode,
+    log::log_manager::DEFAULT_LOG_SEGMENT_BLOCKS,
+    tx::concurrency::{
+        deadlock_policy::DeadlockPolicy,
+        lock_table::{DEFAULT_LOCK_ESCALATION_BLOCKS, DEFAULT_LOCK_TIMEOUT_MS},
+    },
+};
+
+use super::log_level::LogLevel;
+
+pub const DEFAULT_BLOCK_SIZE: i32 = 400;
+pub const DEFAULT_BUFFER_SIZE: i32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryPlannerKind {
+    #[default]
+    Basic,
+    Heuristic,
+}
+
+impl FromStr for QueryPlannerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "basic" => Ok(QueryPlannerKind::Basic),
+            "heuristic" => Ok(QueryPlannerKind::Heuristic),
+            _ => Err(format!("unknown query planner {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpdatePlannerKind {
+    #[default]
+    Basic,
+    // Keeps indexes up to date on insert, delete and update.
+    Index,
+}
+
+impl FromStr for UpdatePlannerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "basic" => Ok(UpdatePlannerKind::Basic),
+            "index" => Ok(UpdatePlannerKind::Index),
+            _ => Err(format!("unknown update planner {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DBOptions {
+    pub block_size: i32,
+    pub buffer_size: i32,
+    pub replacement_policy: ReplacementPolicyKind,
+    // blocks read in one go once a scan is detected; 0 disables read-ahead
+    pub read_ahead_blocks: i32,
+    pub query_planner: QueryPlannerKind,
+    pub update_planner: UpdatePlannerKind,
+    pub lock_timeout_ms: i64,
+    pub deadlock_policy: DeadlockPolicy,
+    // block and record locks a transaction takes in one file before locking
+    // the whole file instead; 0 disables escalation
+    pub lock_escalation_blocks: i32,
+    pub pin_timeout_ms: i64,
+    pub durability: DurabilityMode,
+    pub log_level: LogLevel,
+    // 0 disables the background writer or its checkpoints.
+    pub writer_interval_ms: i64,
+    pub checkpoint_interval_ms: i64,
+    // how long a group commit waits for more commits to share its log flush
+    pub group_commit_window_us: i64,
+    pub log_segment_blocks: i32,
+    // where log segments no longer needed are moved, relative to the database
+    // directory; they are deleted when this is None
+    pub log_archive_dir: Option<PathBuf>,
+}
+
+impl Default for DBOptions {
+    fn default() -> Self {
+        DBOptions {
+            block_size: DEFAULT_BLOCK_SIZE,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            replacement_policy: ReplacementPolicyKind::default(),
+            read_ahead_blocks: DEFAULT_READ_AHEAD_BLOCKS,
+            query_planner: QueryPlannerKind::default(),
+            update_planner: UpdatePlannerKind::default(),
+            lock_timeout_ms: DEFAULT_LOCK_TIMEOUT_MS,
+            deadlock_policy: DeadlockPolicy::default(),
+            lock_escalation_blocks: DEFAULT_LOCK_ESCALATION_BLOCKS as i32,
+            pin_timeout_ms: DEFAULT_PIN_TIMEOUT_MS,
+            durability: DurabilityMode::default(),
+            log_level: LogLevel::default(),
+            writer_interval_ms: 0,
+            checkpoint_interval_ms: 0,
+            group_commit_window_us: 0,
+            log_segment_blocks: DEFAULT_LOG_SEGMENT_BLOCKS,
+            log_archive_dir: None,
+        }
+    }
+}
+
+impl DBOptions {
+    // Overrides the options named in `sabidb.conf` in the database directory, if
+    // there is one.
+    pub fn apply_config_file(&mut self, db_directory: &Path) -> Result<(), String> {
+        let path = db_directory.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+        let text = read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        self.apply_config(&text)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Reads `key = value` lines; `#` starts a comment and values may be quoted,
+    // so that they can hold a `#` of their own.
+    pub fn apply_config(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected key = value", i + 1))?;
+            let value = value.trim().trim_matches('"');
+            self.set(key.trim(), value)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = |v: &str| {
+            v.parse::<i64>()
+                .map_err(|_| format!("{} must be a number, got {}", key, v))
+        };
+        match key {
+            "block_size" => self.block_size = number(value)? as i32,
+            "buffer_size" => self.buffer_size = number(value)? as i32,
+            "replacement_policy" => self.replacement_policy = value.parse()?,
+            "read_ahead_blocks" => self.read_ahead_blocks = number(value)? as i32,
+            "query_planner" => self.query_planner = value.parse()?,
+            "update_planner" => self.update_planner = value.parse()?,
+            "lock_timeout_ms" => self.lock_timeout_ms = number(value)?,
+            "deadlock_policy" => self.deadlock_policy = value.parse()?,
+            "lock_escalation_blocks" => self.lock_escalation_blocks = number(value)? as i32,
+            "pin_timeout_ms" => self.pin_timeout_ms = number(value)?,
+            "durability" => self.durability = value.parse()?,
+            "log_level" => self.log_level = value.parse()?,
+            "writer_interval_ms" => self.writer_interval_ms = number(value)?,
+            "checkpoint_interval_ms" => self.checkpoint_interval_ms = number(value)?,
+            "group_commit_window_us" => self.group_commit_window_us = number(value)?,
+            "log_segment_blocks" => self.log_segment_blocks = number(value)? as i32,
+            "log_archive_dir" => {
+                self.log_archive_dir = (!value.is_empty()).then(|| PathBuf::from(value))
+            }
+            _ => return Err(format!("unknown option {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.block_size <= 0 {
+            return Err(format!("block_size must be positive, got {}", self.block_size));
+        }
+        if self.buffer_size <= 0 {
+            return Err(format!("buffer_size must be positive, got {}", self.buffer_size));
+        }
+        if self.read_ahead_blocks < 0 {
+            return Err(format!(
+                "read_ahead_blocks must not be negative, got {}",
+                self.read_ahead_blocks
+            ));
+        }
+        if self.lock_escalation_blocks < 0 {
+            return Err(format!(
+                "lock_escalation_blocks must not be negative, got {}",
+                self.lock_escalation_blocks
+            ));
+        }
+        if self.lock_timeout_ms < 0 || self.pin_timeout_ms < 0 {
+            return Err("timeouts must not be negative".to_string());
+        }
+        if self.writer_interval_ms < 0
+            || self.checkpoint_interval_ms < 0
+            || self.group_commit_window_us < 0
+        {
+            return Err("intervals must not be negative".to_string());
+        }
+        if self.log_segment_blocks <= 0 {
+            return Err(format!(
+                "log_segment_blocks must be positive, got {}",
+                self.log_segment_blocks
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DBOptions, QueryPlannerKind, UpdatePlannerKind};
+    use crate::{
+        buffer::replacement_policy::ReplacementPolicyKind,
+        file::durability_mode::DurabilityMode, server::log_level::LogLevel,
+        tx::concurrency::deadlock_policy::DeadlockPolicy,
+    };
+
+    #[test]
+    fn test_apply_config() {
+        let mut options = DBOptions::default();
+        let conf = r#"
+# tuned for a laptop
+block_size = 800
+buffer_size = 64
+replacement_policy = lru-k
+query_planner = "heuristic"
+update_planner = index
+deadlock_policy = wound-wait
+durability = group # fewer fsyncs
+log_level = quiet
+writer_interval_ms = 200
+log_archive_dir = "archive#2" # a quoted value keeps its #
+"#;
+        options.apply_config(conf).unwrap();
+        assert_eq!(options.block_size, 800);
+        assert_eq!(options.buffer_size, 64);
+        assert_eq!(options.replacement_policy, ReplacementPolicyKind::LruK);
+        assert_eq!(options.query_planner, QueryPlannerKind::Heuristic);
+        assert_eq!(options.update_planner, UpdatePlannerKind::Index);
+        assert_eq!(options.deadlock_policy, DeadlockPolicy::WoundWait);
+        assert_eq!(options.durability, DurabilityMode::Group);
+        assert_eq!(options.log_level, LogLevel::Quiet);
+        assert_eq!(options.writer_interval_ms, 200);
+        assert_eq!(options.checkpoint_interval_ms, 0);
+        assert_eq!(options.log_archive_dir, Some("archive#2".into()));
+        assert_eq!(options.lock_timeout_ms, DBOptions::default().lock_timeout_ms);
+
+        let err = options.apply_config("buffer_size = 8\nbuffers = 8").unwrap_err();
+        assert_eq!(err, "line 2: unknown option buffers");
+        let err = options.apply_config("durability = sometimes").unwrap_err();
+        assert_eq!(err, "line 1: unknown durability mode sometimes");
+    }
+}
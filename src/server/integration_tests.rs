@@ -160,6 +160,24 @@ mod tests {
         }
         assert_eq!(count, 3);
 
+        // 14. Vacuum keeps the remaining rows
+        run_update(&mut planner, "vacuum students", tx.clone());
+
+        let qry = "select sid, sname from students where majorid = 20".to_string();
+        println!("SQL: {}", qry);
+        let plan = planner.create_query_planner(&qry, tx.clone()).unwrap();
+        let scan = plan.lock().unwrap().open().unwrap();
+        let mut count = 0;
+        while scan.lock().unwrap().next().unwrap() {
+            let sid = scan.lock().unwrap().get_int(&"sid".to_string()).unwrap();
+            assert!(sid == 2 || sid == 4);
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        // 15. Checkpoint while the transaction is still running
+        run_update(&mut planner, "checkpoint", tx.clone());
+
         tx.lock().unwrap().commit().unwrap();
         println!("--- Comprehensive SQL Integration Test Passed ---\n");
     }
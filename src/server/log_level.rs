@@ -0,0 +1,33 @@
+use std::str::FromStr;
+
+// How much a database prints to stdout. Each database keeps its own level in
+// its file manager, which every part of the engine can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum LogLevel {
+    Quiet,
+    // Startup banner, recovery and damaged-log messages.
+    #[default]
+    Info,
+    // Also reports every commit and rollback.
+    Debug,
+}
+
+impl LogLevel {
+    // Whether messages of `level` are printed at this level.
+    pub fn enabled(self, level: LogLevel) -> bool {
+        level <= self
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "quiet" => Ok(LogLevel::Quiet),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!("unknown log level {}", s)),
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod background_writer;
+pub mod db_options;
+pub mod log_level;
 pub mod simple_db;
 pub mod integration_tests;
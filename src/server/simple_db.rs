@@ -1,115 +1,152 @@
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::{
-    buffer::buffer_manager::BufferManager,
+    buffer::{buffer_manager::BufferManager, replacement_policy::ReplacementPolicyKind},
     constants::LOG_FILE,
-    file::file_manager::FileManager,
+    file::{
+        durability_mode::DurabilityMode, file_manager::FileManager, superblock::Superblock,
+    },
     index::planner::index_update_planner::IndexUpdatePlanner,
     log::log_manager::LogManager,
     metadata::matadata_manager::MetadataManager,
     opt::heuristic_query_planner::HeuristicQueryPlanner,
     plan::{
         basic_query_planner::BasicQueryPlanner, basic_update_planner::BasicUpdatePlanner,
-        planner::Planner,
+        planner::Planner, query_planner::QueryPlanner, update_planner::UpdatePlanner,
+    },
+    tx::{
+        concurrency::{
+            deadlock_policy::DeadlockPolicy, lock_table::LockTable, version_store::VersionStore,
+        },
+        transaction::Transaction,
     },
-    tx::{concurrency::lock_table::LockTable, transaction::Transaction},
 };
 
-const BLOCK_SISE: i32 = 400;
-const BUFFER_SISE: i32 = 8;
+use super::{
+    background_writer::BackgroundWriter,
+    db_options::{DBOptions, QueryPlannerKind, UpdatePlannerKind},
+    log_level::LogLevel,
+};
 
 pub struct SimpleDB {
     fm: Arc<FileManager>,
     lm: Arc<Mutex<LogManager>>,
-    bm: Arc<Mutex<BufferManager>>,
-    lt: Arc<Mutex<LockTable>>,
+    bm: Arc<BufferManager>,
+    lt: Arc<LockTable>,
+    vs: Arc<VersionStore>,
+    superblock: Superblock,
     mdm: Option<Arc<Mutex<MetadataManager>>>,
     pub planner: Option<Planner>,
+    writer: Option<BackgroundWriter>,
 }
 
 impl SimpleDB {
+    pub fn builder(dirname: &Path) -> SimpleDBBuilder {
+        SimpleDBBuilder::new(dirname)
+    }
+
     pub fn new_with_sizes(dirname: &Path, blocksize: i32, buffsize: i32) -> Self {
-        let fm = Arc::new(FileManager::new_from_blocksize(
-            &dirname, blocksize,
-        ));
+        Self::new_with_durability(dirname, blocksize, buffsize, DurabilityMode::default())
+    }
+
+    pub fn new_with_durability(
+        dirname: &Path,
+        blocksize: i32,
+        buffsize: i32,
+        durability: DurabilityMode,
+    ) -> Self {
+        Self::try_new_with_durability(dirname, blocksize, buffsize, durability)
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", dirname.display(), e))
+    }
+
+    pub fn try_new_with_durability(
+        dirname: &Path,
+        blocksize: i32,
+        buffsize: i32,
+        durability: DurabilityMode,
+    ) -> Result<Self, String> {
+        let options = DBOptions {
+            block_size: blocksize,
+            buffer_size: buffsize,
+            durability,
+            ..DBOptions::default()
+        };
+        Self::open_storage(dirname, &options)
+    }
+
+    // Opens the file, log and buffer managers without reading any metadata.
+    fn open_storage(dirname: &Path, options: &DBOptions) -> Result<Self, String> {
+        let fm = Arc::new(FileManager::new_with_durability(
+            &dirname,
+            options.block_size,
+            options.durability,
+        )
+        .with_log_level(options.log_level));
+        let superblock =
+            Superblock::open_or_create(dirname, fm.is_new(), options.block_size, LOG_FILE)
+                .map_err(|e| e.to_string())?;
+        let archive_dir = options.log_archive_dir.as_ref().map(|dir| dirname.join(dir));
         let lm = Arc::new(Mutex::new(
-            LogManager::new(fm.clone(), LOG_FILE.to_string()).unwrap(),
-        ));
-        let bm = Arc::new(Mutex::new(
-            BufferManager::new(fm.clone(), lm.clone(), buffsize).unwrap(),
+            LogManager::new(fm.clone(), LOG_FILE.to_string())?
+                .with_segment_blocks(options.log_segment_blocks)
+                .with_archive_dir(archive_dir)
+                .with_group_commit_window(Duration::from_micros(
+                    options.group_commit_window_us as u64,
+                )),
         ));
-        let lt = Arc::new(Mutex::new(LockTable::new()));
-        Self {
+        Transaction::restore_tx_numbers(lm.clone())?;
+        let bm = Arc::new(BufferManager::new_with_policy(
+            fm.clone(),
+            lm.clone(),
+            options.buffer_size,
+            options.pin_timeout_ms,
+            options.replacement_policy,
+        )?
+        .with_read_ahead(options.read_ahead_blocks));
+        let lt = Arc::new(
+            LockTable::new_with_timeout(options.lock_timeout_ms)
+                .with_deadlock_policy(options.deadlock_policy)
+                .with_escalation_blocks(options.lock_escalation_blocks as usize),
+        );
+        Ok(Self {
             fm,
             lm,
             bm,
             lt,
+            vs: Arc::new(VersionStore::new()),
+            superblock,
             mdm: None,
             planner: None,
-        }
+            writer: None,
+        })
     }
 
     pub fn new(dirname: &Path) -> Self {
-        let mut db = Self::new_with_sizes(dirname, BLOCK_SISE, BUFFER_SISE);
-        print_logo();
-        let tx = db.new_tx();
-        let is_new = db.fm.is_new();
-        if is_new {
-            println!("creating new database")
-        } else {
-            println!("recovering existing database")
-        }
-
-        let mdm = Arc::new(Mutex::new(
-            MetadataManager::new(is_new, tx.clone()).unwrap(),
-        ));
-        let qp = BasicQueryPlanner::new(mdm.clone());
-        let up = BasicUpdatePlanner::new(mdm.clone());
-
-        let planner = Planner::new(Arc::new(Mutex::new(qp)), Arc::new(Mutex::new(up)));
-        db.mdm = Some(mdm);
-        db.planner = Some(planner);
-
-        tx.lock().unwrap().commit().unwrap();
-
-        db
+        Self::builder(dirname)
+            .build()
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", dirname.display(), e))
     }
 
     pub fn new_with_refined_planners(dirname: &Path) -> Self {
-        let mut db = Self::new_with_sizes(dirname, BLOCK_SISE, BUFFER_SISE);
-        print_logo();
-
-        let tx = db.new_tx();
-        let is_new = db.fm.is_new();
-        if is_new {
-            println!("creating new database")
-        } else {
-            println!("recovering existing database")
-        }
-
-        let mdm = Arc::new(Mutex::new(
-            MetadataManager::new(is_new, tx.clone()).unwrap(),
-        ));
-
-        let qp = HeuristicQueryPlanner::new(mdm.clone());
-        let up = IndexUpdatePlanner::new(mdm.clone());
-
-        let planner = Planner::new(Arc::new(Mutex::new(qp)), Arc::new(Mutex::new(up)));
-        db.mdm = Some(mdm);
-        db.planner = Some(planner);
-
-        tx.lock().unwrap().commit().unwrap();
-
-        db
+        Self::builder(dirname)
+            .query_planner(QueryPlannerKind::Heuristic)
+            .update_planner(UpdatePlannerKind::Index)
+            .build()
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", dirname.display(), e))
     }
 
     pub fn metadata_manager(&self) -> Arc<Mutex<MetadataManager>> {
         self.mdm.clone().unwrap()
     }
 
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
     pub fn file_manager(&self) -> Arc<FileManager> {
         self.fm.clone()
     }
@@ -118,14 +155,27 @@ impl SimpleDB {
         self.lm.clone()
     }
 
-    pub fn buffer_manager(&self) -> Arc<Mutex<BufferManager>> {
+    pub fn buffer_manager(&self) -> Arc<BufferManager> {
         self.bm.clone()
     }
 
-    pub fn lock_table(&self) -> Arc<Mutex<LockTable>> {
+    pub fn lock_table(&self) -> Arc<LockTable> {
         self.lt.clone()
     }
 
+    pub fn version_store(&self) -> Arc<VersionStore> {
+        self.vs.clone()
+    }
+
+    // The bytes taken by the log segments still in the database directory.
+    pub fn log_size(&self) -> Result<u64, String> {
+        self.lm.lock().map_err(|_| "failed to get lock")?.log_size()
+    }
+
+    pub fn background_writer(&self) -> Option<&BackgroundWriter> {
+        self.writer.as_ref()
+    }
+
     pub fn new_tx(&self) -> Arc<Mutex<Transaction>> {
         Arc::new(Mutex::new(
             Transaction::new_from_managers(
@@ -133,12 +183,182 @@ impl SimpleDB {
                 self.lm.clone(),
                 self.bm.clone(),
                 self.lt.clone(),
+                self.vs.clone(),
             )
             .unwrap(),
         ))
     }
 }
 
+pub struct SimpleDBBuilder {
+    dirname: PathBuf,
+    options: DBOptions,
+}
+
+impl SimpleDBBuilder {
+    pub fn new(dirname: &Path) -> Self {
+        SimpleDBBuilder {
+            dirname: dirname.to_path_buf(),
+            options: DBOptions::default(),
+        }
+    }
+
+    // Applies `sabidb.conf` from the database directory; options set after this
+    // call take precedence over the file.
+    pub fn config_file(mut self) -> Result<Self, String> {
+        self.options.apply_config_file(&self.dirname)?;
+        Ok(self)
+    }
+
+    pub fn options(mut self, options: DBOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn block_size(mut self, blocksize: i32) -> Self {
+        self.options.block_size = blocksize;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffsize: i32) -> Self {
+        self.options.buffer_size = buffsize;
+        self
+    }
+
+    pub fn replacement_policy(mut self, kind: ReplacementPolicyKind) -> Self {
+        self.options.replacement_policy = kind;
+        self
+    }
+
+    pub fn read_ahead_blocks(mut self, blocks: i32) -> Self {
+        self.options.read_ahead_blocks = blocks;
+        self
+    }
+
+    pub fn query_planner(mut self, kind: QueryPlannerKind) -> Self {
+        self.options.query_planner = kind;
+        self
+    }
+
+    pub fn update_planner(mut self, kind: UpdatePlannerKind) -> Self {
+        self.options.update_planner = kind;
+        self
+    }
+
+    pub fn lock_timeout_ms(mut self, ms: i64) -> Self {
+        self.options.lock_timeout_ms = ms;
+        self
+    }
+
+    pub fn deadlock_policy(mut self, policy: DeadlockPolicy) -> Self {
+        self.options.deadlock_policy = policy;
+        self
+    }
+
+    pub fn lock_escalation_blocks(mut self, blocks: i32) -> Self {
+        self.options.lock_escalation_blocks = blocks;
+        self
+    }
+
+    pub fn pin_timeout_ms(mut self, ms: i64) -> Self {
+        self.options.pin_timeout_ms = ms;
+        self
+    }
+
+    pub fn durability(mut self, durability: DurabilityMode) -> Self {
+        self.options.durability = durability;
+        self
+    }
+
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.options.log_level = level;
+        self
+    }
+
+    pub fn writer_interval_ms(mut self, ms: i64) -> Self {
+        self.options.writer_interval_ms = ms;
+        self
+    }
+
+    pub fn checkpoint_interval_ms(mut self, ms: i64) -> Self {
+        self.options.checkpoint_interval_ms = ms;
+        self
+    }
+
+    pub fn group_commit_window_us(mut self, us: i64) -> Self {
+        self.options.group_commit_window_us = us;
+        self
+    }
+
+    pub fn log_segment_blocks(mut self, blocks: i32) -> Self {
+        self.options.log_segment_blocks = blocks;
+        self
+    }
+
+    pub fn log_archive_dir(mut self, dir: &Path) -> Self {
+        self.options.log_archive_dir = Some(dir.to_path_buf());
+        self
+    }
+
+    pub fn get_options(&self) -> &DBOptions {
+        &self.options
+    }
+
+    // Opens the database, running recovery and loading the catalog.
+    pub fn build(self) -> Result<SimpleDB, String> {
+        self.options.validate()?;
+
+        let mut db = SimpleDB::open_storage(&self.dirname, &self.options)?;
+        if self.options.log_level.enabled(LogLevel::Info) {
+            print_logo();
+        }
+        let tx = db.new_tx();
+        let is_new = db.fm.is_new();
+        if self.options.log_level.enabled(LogLevel::Info) {
+            if is_new {
+                println!("creating new database")
+            } else {
+                println!("recovering existing database")
+            }
+        }
+        // the background writer can put changes of unfinished transactions on
+        // disk, so they are undone before the database is used again
+        if !is_new {
+            tx.lock().map_err(|_| "failed to get lock")?.recover()?;
+        }
+
+        let mdm = Arc::new(Mutex::new(MetadataManager::new(is_new, tx.clone())?));
+        let qp: Arc<Mutex<dyn QueryPlanner>> = match self.options.query_planner {
+            QueryPlannerKind::Basic => Arc::new(Mutex::new(BasicQueryPlanner::new(mdm.clone()))),
+            QueryPlannerKind::Heuristic => {
+                Arc::new(Mutex::new(HeuristicQueryPlanner::new(mdm.clone())))
+            }
+        };
+        let up: Arc<Mutex<dyn UpdatePlanner>> = match self.options.update_planner {
+            UpdatePlannerKind::Basic => Arc::new(Mutex::new(BasicUpdatePlanner::new(mdm.clone()))),
+            UpdatePlannerKind::Index => Arc::new(Mutex::new(IndexUpdatePlanner::new(mdm.clone()))),
+        };
+        db.mdm = Some(mdm);
+        db.planner = Some(Planner::new(qp, up));
+
+        tx.lock().map_err(|_| "failed to get lock")?.commit()?;
+
+        let interval = |ms: i64| (ms > 0).then(|| Duration::from_millis(ms as u64));
+        let writer_interval = interval(self.options.writer_interval_ms);
+        let checkpoint_interval = interval(self.options.checkpoint_interval_ms);
+        if writer_interval.is_some() || checkpoint_interval.is_some() {
+            db.writer = Some(BackgroundWriter::start(
+                db.bm.clone(),
+                db.lm.clone(),
+                writer_interval,
+                checkpoint_interval,
+            ));
+        }
+
+        Ok(db)
+    }
+}
+
 fn print_logo() {
     println!("\x1b[38;5;208m");
 
@@ -158,7 +378,45 @@ mod integration_tests {
 
     use tempfile::TempDir;
 
-    use crate::{server::simple_db::SimpleDB, testlib::helper::create_student_data};
+    use crate::{
+        constants::CONFIG_FILE,
+        file::durability_mode::DurabilityMode,
+        server::{db_options::UpdatePlannerKind, log_level::LogLevel, simple_db::SimpleDB},
+        testlib::helper::create_student_data,
+    };
+
+    #[test]
+    fn test_reopen_with_other_block_size_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = SimpleDB::new(temp_dir.path());
+        assert_eq!(db.superblock().block_size(), 400);
+        drop(db);
+
+        let res = SimpleDB::try_new_with_durability(temp_dir.path(), 800, 8, DurabilityMode::Full);
+        assert!(res.err().unwrap().contains("block size 400"));
+        assert!(SimpleDB::try_new_with_durability(temp_dir.path(), 400, 8, DurabilityMode::Full).is_ok());
+    }
+
+    #[test]
+    fn test_builder_reads_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join(CONFIG_FILE),
+            "block_size = 800\nbuffer_size = 16\nupdate_planner = index\nlog_level = quiet\n",
+        )
+        .unwrap();
+
+        let builder = SimpleDB::builder(temp_dir.path()).config_file().unwrap();
+        assert_eq!(builder.get_options().update_planner, UpdatePlannerKind::Index);
+        let db = builder.buffer_size(12).build().unwrap();
+        assert_eq!(db.superblock().block_size(), 800);
+        assert_eq!(db.buffer_manager().available(), 12);
+        assert!(db.planner.is_some());
+        assert_eq!(db.file_manager().log_level(), LogLevel::Quiet);
+
+        let err = SimpleDB::builder(temp_dir.path()).buffer_size(0).build().err().unwrap();
+        assert!(err.contains("buffer_size"));
+    }
 
     #[test]
     fn test_planner1() {
@@ -1,4 +1,6 @@
-use crate::server::simple_db::SimpleDB;
+use std::sync::{Arc, Mutex};
+
+use crate::{plan::planner::Planner, server::simple_db::SimpleDB, tx::transaction::Transaction};
 
 pub fn create_student_data(db: &mut SimpleDB) {
     let tx = db.new_tx();
@@ -107,3 +109,18 @@ pub fn create_student_data(db: &mut SimpleDB) {
 
     tx.lock().unwrap().commit().unwrap();
 }
+
+// The number of rows the query returns in the transaction.
+pub fn count_rows(planner: &Planner, qry: &str, tx: &Arc<Mutex<Transaction>>) -> i32 {
+    let p = planner
+        .clone()
+        .create_query_planner(&qry.to_string(), tx.clone())
+        .unwrap();
+    let s = p.lock().unwrap().open().unwrap();
+    let mut n = 0;
+    while s.lock().unwrap().next().unwrap() {
+        n += 1;
+    }
+    s.lock().unwrap().close().unwrap();
+    n
+}
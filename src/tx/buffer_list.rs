@@ -4,7 +4,7 @@ use std::{
 };
 
 use crate::{
-    buffer::{buffer::Buffer, buffer_manager::BufferManager},
+    buffer::{buffer::Buffer, buffer_manager::BufferManager, replacement_policy::AccessHint},
     file::block_id::BlockId,
 };
 
@@ -12,11 +12,11 @@ use crate::{
 pub struct BufferList {
     buffers: HashMap<BlockId, Arc<Mutex<Buffer>>>,
     pins: Vec<BlockId>,
-    bm: Arc<Mutex<BufferManager>>,
+    bm: Arc<BufferManager>,
 }
 
 impl BufferList {
-    pub fn new_from_buffer_manager(bm: Arc<Mutex<BufferManager>>) -> Self {
+    pub fn new_from_buffer_manager(bm: Arc<BufferManager>) -> Self {
         Self {
             buffers: HashMap::new(),
             pins: Vec::new(),
@@ -28,12 +28,10 @@ impl BufferList {
         self.buffers.get_mut(blk)
     }
 
-    pub(crate) fn pin(&mut self, blk: &BlockId) -> Result<(), String> {
+    pub(crate) fn pin_with_hint(&mut self, blk: &BlockId, hint: AccessHint) -> Result<(), String> {
         let buff = self
             .bm
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .pin(blk)
+            .pin_with_hint(blk, hint)
             .map_err(|e| e.to_string())?
             .ok_or("you try to access an invalid buffer")?;
         self.buffers.insert(blk.clone(), buff);
@@ -47,8 +45,6 @@ impl BufferList {
             .get(blk)
             .ok_or("you try to access an invalid buffer")?;
         self.bm
-            .lock()
-            .map_err(|_| "failed to get lock")?
             .unpin(buff.clone())?;
         if let Some(remove_index) = self.pins.iter().position(|x| *x == *blk) {
             self.pins.remove(remove_index);
@@ -66,8 +62,6 @@ impl BufferList {
                 .get(blk)
                 .ok_or("you access to an invalid buffer")?;
             self.bm
-                .lock()
-                .map_err(|_| "failed to get lock")?
                 .unpin(buff.clone())?;
         }
         self.buffers.clear();
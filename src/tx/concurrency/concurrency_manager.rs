@@ -1,134 +1,324 @@
 use std::{
     collections::HashMap,
+    ops::Range,
     sync::{Arc, Mutex},
-    thread, time,
 };
 
-use crate::file::block_id::BlockId;
+use crate::{file::block_id::BlockId, query::constant::Constant};
 
-use super::lock_table::{LockAbortException, LockTable};
-use chrono::Utc;
+use super::{
+    isolation_level::IsolationLevel,
+    lock_mode::{LockMode, LockTarget},
+    lock_table::LockTable,
+};
 
-const MAX_TIME: i64 = 10_000; // 10 seconds
+// The locks a read took, each with the mode the transaction held before, if
+// any, so that they can be given back after the read.
+pub type ReadLocks = Vec<(LockTarget, Option<LockMode>)>;
 
 #[derive(Debug, Clone)]
 pub struct ConcurrencyManager {
-    lt: Arc<Mutex<LockTable>>,
-    locks: Arc<Mutex<HashMap<BlockId, char>>>,
+    txnum: i64,
+    lt: Arc<LockTable>,
+    locks: Arc<Mutex<HashMap<LockTarget, LockMode>>>,
+    // the bytes of each block covered by the transaction's record locks
+    records: Arc<Mutex<HashMap<BlockId, Vec<Range<usize>>>>>,
+    isolation: IsolationLevel,
 }
 
 impl ConcurrencyManager {
-    pub fn new(lt: Arc<Mutex<LockTable>>) -> Self {
+    pub fn new(txnum: i64, lt: Arc<LockTable>) -> Self {
         ConcurrencyManager {
+            txnum,
             lt,
             locks: Arc::new(Mutex::new(HashMap::new())),
+            records: Arc::new(Mutex::new(HashMap::new())),
+            isolation: IsolationLevel::default(),
         }
     }
 
-    pub fn s_lock(&self, blk: &BlockId) -> Result<(), String> {
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    pub fn set_isolation_level(&mut self, level: IsolationLevel) {
+        self.isolation = level;
+    }
+
+    // Takes the locks a read of the block needs at the isolation level. The
+    // locks returned are only for the read, and are released by `end_read`.
+    pub fn read_lock(&self, blk: &BlockId) -> Result<ReadLocks, String> {
+        match self.isolation {
+            IsolationLevel::Serializable => {
+                self.s_lock(blk)?;
+                Ok(Vec::new())
+            }
+            IsolationLevel::ReadCommitted => {
+                let target = LockTarget::Block(blk.clone());
+                let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
+                if Self::covered(&locks, &target, LockMode::Shared) {
+                    return Ok(Vec::new());
+                }
+                self.lock_path(&mut locks, &target, LockMode::Shared)
+            }
+            IsolationLevel::RepeatableRead | IsolationLevel::ReadUncommitted => Ok(Vec::new()),
+        }
+    }
+
+    // Releases the locks taken for a read, and turns the ones the read
+    // strengthened, such as an IX for writing some records of the block, back
+    // into what they were.
+    pub fn end_read(&self, taken: ReadLocks) -> Result<(), String> {
         let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
-        if locks.get(blk).is_none() {
-            self.s_lock_internal(blk).map_err(|e| e.to_string())?;
-            locks.insert(blk.clone(), 'S');
+        for (t, before) in taken.into_iter().rev() {
+            match before {
+                Some(m) => {
+                    self.lt.downgrade(self.txnum, &t, m)?;
+                    locks.insert(t, m);
+                }
+                None => {
+                    self.lt.unlock(self.txnum, &t)?;
+                    locks.remove(&t);
+                }
+            }
         }
         Ok(())
     }
 
+    pub fn s_lock(&self, blk: &BlockId) -> Result<(), String> {
+        self.lock_block(blk, LockMode::Shared)
+    }
+
     pub fn x_lock(&self, blk: &BlockId) -> Result<(), String> {
-        let locks = self.locks.lock().map_err(|_| "failed to get lock")?;
-        if !self.has_x_lock_internal(&locks, blk) {
-            // Need to release locks before calling s_lock or s_lock_internal to avoid deadlock
-            // But wait, x_lock should ideally call s_lock_internal directly if needed
-            drop(locks);
-            self.s_lock(blk)?;
-            let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
-            self.x_lock_internal(blk).map_err(|e| e.to_string())?;
-            locks.insert(blk.clone(), 'X');
+        self.lock_node(&LockTarget::Block(blk.clone()), LockMode::Exclusive)
+    }
+
+    // Like x_lock, but gives up at once instead of waiting for other
+    // transactions. Returns whether the block is locked.
+    pub fn try_x_lock(&self, blk: &BlockId) -> Result<bool, String> {
+        let target = LockTarget::Block(blk.clone());
+        let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
+        if Self::covered(&locks, &target, LockMode::Exclusive) {
+            return Ok(true);
         }
+        self.try_lock_path(&mut locks, &target, LockMode::Exclusive)
+    }
+
+    // Locks the record in `slot` for writing; the record takes up the bytes of
+    // the block between `start` and `end`. Other transactions may still write
+    // other records in the block.
+    pub fn x_lock_record(&self, blk: &BlockId, slot: i32, start: usize, end: usize) -> Result<(), String> {
+        self.lock_node(&LockTarget::Record(blk.clone(), slot), LockMode::Exclusive)?;
+        self.records
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .entry(blk.clone())
+            .or_default()
+            .push(start..end);
         Ok(())
     }
 
-    fn has_x_lock_internal(&self, locks: &HashMap<BlockId, char>, blk: &BlockId) -> bool {
-        if let Some(c) = locks.get(blk) {
-            return *c == 'X';
+    // Gives back the lock on a record the transaction did not write after all.
+    pub fn unlock_record(&self, blk: &BlockId, slot: i32, start: usize, end: usize) -> Result<(), String> {
+        let target = LockTarget::Record(blk.clone(), slot);
+        let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
+        if locks.remove(&target).is_some() {
+            self.lt.unlock(self.txnum, &target)?;
         }
-        false
+        if let Some(ranges) = self
+            .records
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .get_mut(blk)
+        {
+            ranges.retain(|r| *r != (start..end));
+        }
+        Ok(())
     }
 
-    // This function corresponds to s_lock in LockTable in the original implementation
-    fn s_lock_internal(&self, blk: &BlockId) -> Result<(), LockAbortException> {
-        let timestamp = Utc::now().timestamp_millis();
-
-        while !Self::waiting_too_long(timestamp) {
-            if let Ok(lock_table) = self.lt.lock() {
-                if !lock_table.has_x_lock(blk).map_err(|_| LockAbortException)? {
-                    let val = lock_table
-                        .get_lock_val(blk)
-                        .map_err(|_| LockAbortException)?;
-                    lock_table
-                        .locks
-                        .lock()
-                        .map_err(|_| LockAbortException)?
-                        .insert(blk.clone(), val + 1);
-                    return Ok(());
-                }
-            }
+    // Whether the transaction holds a lock on the record itself.
+    pub fn holds_record(&self, blk: &BlockId, slot: i32) -> Result<bool, String> {
+        Ok(self
+            .locks
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .contains_key(&LockTarget::Record(blk.clone(), slot)))
+    }
 
-            thread::sleep(time::Duration::from_millis(100));
+    // Called before writing at `offset`: a write inside a record the
+    // transaction has locked needs no lock on the whole block.
+    pub fn write_lock(&self, blk: &BlockId, offset: usize) -> Result<(), String> {
+        let covered = self
+            .records
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .get(blk)
+            .is_some_and(|ranges| ranges.iter().any(|r| r.contains(&offset)));
+        if covered {
+            return Ok(());
         }
+        self.x_lock(blk)
+    }
 
-        return Err(From::from(LockAbortException));
+    // Locks a whole file, for scans, DDL and maintenance that touch most of it.
+    pub fn lock_file(&self, filename: &str, mode: LockMode) -> Result<(), String> {
+        let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
+        self.lock_path(&mut locks, &LockTarget::File(filename.to_string()), mode)?;
+        Ok(())
     }
 
-    // This function corresponds to x_lock in LockTable in the original implementation
-    fn x_lock_internal(&self, blk: &BlockId) -> Result<(), LockAbortException> {
-        let timestamp = Utc::now().timestamp_millis();
+    // Locks the entries of an index with the key, in the index's leaf file.
+    pub fn lock_key(&self, filename: &str, key: &Constant, mode: LockMode) -> Result<(), String> {
+        self.lock_node(&LockTarget::Key(filename.to_string(), key.clone()), mode)
+    }
 
-        while !Self::waiting_too_long(timestamp) {
-            if let Ok(lock_table) = self.lt.lock() {
-                if !lock_table
-                    .has_other_s_locks(blk)
-                    .map_err(|_| LockAbortException)?
-                {
-                    lock_table
-                        .locks
-                        .lock()
-                        .map_err(|_| LockAbortException)?
-                        .insert(blk.clone(), -1);
-                    return Ok(());
+    fn lock_block(&self, blk: &BlockId, mode: LockMode) -> Result<(), String> {
+        self.lock_node(&LockTarget::Block(blk.clone()), mode)
+    }
+
+    // Locks a block or a record, unless a lock the transaction holds above it
+    // already covers it.
+    fn lock_node(&self, target: &LockTarget, mode: LockMode) -> Result<(), String> {
+        let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
+        if Self::covered(&locks, target, mode) {
+            return Ok(());
+        }
+
+        // Too many block and record locks in one file: lock the file instead,
+        // if that needs no waiting. Other transactions working in the file keep
+        // the locks fine-grained rather than making this one wait, or abort, for
+        // more than it asked for.
+        let file = target.file();
+        let threshold = self.lt.escalation_blocks();
+        let nodes: Vec<(LockTarget, LockMode)> = locks
+            .iter()
+            .filter(|(t, _)| t.file() == file)
+            .map(|(t, m)| (t.clone(), *m))
+            .collect();
+        if let Some(file) = file.filter(|_| threshold > 0 && nodes.len() >= threshold) {
+            // what the record and key locks allowed, the file lock has to: a
+            // block is only held in IX for its records, and a key for writing it
+            let escalated = nodes
+                .iter()
+                .map(|(_, m)| *m)
+                .fold(mode, |a, b| a.join(b));
+            let escalated = match escalated {
+                LockMode::IntentionExclusive | LockMode::SharedIntentionExclusive => LockMode::Exclusive,
+                m => m,
+            };
+            if self.try_lock_path(&mut locks, &file, escalated)? {
+                let mut records = self.records.lock().map_err(|_| "failed to get lock")?;
+                for (t, _) in nodes {
+                    self.lt.unlock(self.txnum, &t)?;
+                    locks.remove(&t);
+                    if let LockTarget::Record(blk, _) | LockTarget::Block(blk) = t {
+                        records.remove(&blk);
+                    }
                 }
+                return Ok(());
             }
+        }
+        self.lock_path(&mut locks, target, mode)?;
+        Ok(())
+    }
+
+    // Whether the transaction holds a lock on the target, or on a file or
+    // block above it, that grants `mode`.
+    fn covered(locks: &HashMap<LockTarget, LockMode>, target: &LockTarget, mode: LockMode) -> bool {
+        if locks.get(target).is_some_and(|m| m.covers(mode)) {
+            return true;
+        }
+        let mut node = target.parent();
+        while let Some(t) = node {
+            if t != LockTarget::Database
+                && locks
+                    .get(&t)
+                    .and_then(|m| m.implicit())
+                    .is_some_and(|m| m.covers(mode))
+            {
+                return true;
+            }
+            node = t.parent();
+        }
+        false
+    }
+
+    // Takes the intention locks from the root down, then the target's lock.
+    // Returns the targets whose locks changed, with the modes held before.
+    fn lock_path(
+        &self,
+        locks: &mut HashMap<LockTarget, LockMode>,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> Result<ReadLocks, String> {
+        let mut taken = Vec::new();
+        for (t, m) in Self::path(target, mode) {
+            if locks.get(&t).is_some_and(|held| held.covers(m)) {
+                continue;
+            }
+            self.lt
+                .lock(self.txnum, &t, m)
+                .map_err(|e| e.to_string())?;
+            let before = locks.get(&t).copied();
+            taken.push((t.clone(), before));
+            locks.insert(t, before.map_or(m, |held| held.join(m)));
+        }
+        Ok(taken)
+    }
 
-            thread::sleep(time::Duration::from_millis(100));
+    // The target and the intention locks above it, from the root down.
+    fn path(target: &LockTarget, mode: LockMode) -> Vec<(LockTarget, LockMode)> {
+        let mut path = vec![(target.clone(), mode)];
+        while let Some(parent) = path.last().and_then(|(t, _)| t.parent()) {
+            path.push((parent, mode.intention()));
         }
+        path.reverse();
+        path
+    }
 
-        return Err(From::from(LockAbortException));
+    // Like lock_path, but stops at the first lock that is not free. The locks
+    // taken up to there are kept.
+    fn try_lock_path(
+        &self,
+        locks: &mut HashMap<LockTarget, LockMode>,
+        target: &LockTarget,
+        mode: LockMode,
+    ) -> Result<bool, String> {
+        for (t, m) in Self::path(target, mode) {
+            if locks.get(&t).is_some_and(|held| held.covers(m)) {
+                continue;
+            }
+            if !self.lt.try_lock(self.txnum, &t, m)? {
+                return Ok(false);
+            }
+            let held = locks.get(&t).map_or(m, |held| held.join(m));
+            locks.insert(t, held);
+        }
+        Ok(true)
     }
 
     pub fn release(&mut self) -> Result<(), String> {
         let mut locks = self.locks.lock().map_err(|_| "failed to get lock")?;
 
         // `unlock`操作を実行し、エラーがあれば返す
-        for (blk, _) in locks.iter() {
+        for (target, _) in locks.iter() {
             self.lt
-                .lock()
-                .map_err(|_| "failed to get lock")?
-                .unlock(blk)
-                .map_err(|e| format!("failed to unlock block {}: {}", blk, e))?;
+                .unlock(self.txnum, target)
+                .map_err(|e| format!("failed to unlock {}: {}", target, e))?;
         }
 
         locks.clear();
-        Ok(())
+        self.records
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clear();
+        self.lt.forget(self.txnum)
     }
 
     #[allow(dead_code)]
     fn has_x_lock(&self, blk: &BlockId) -> Result<bool, String> {
         let locks = self.locks.lock().map_err(|_| "failed to get lock")?;
-        Ok(self.has_x_lock_internal(&locks, blk))
-    }
-
-    fn waiting_too_long(starttime: i64) -> bool {
-        Utc::now().timestamp_millis() - starttime > MAX_TIME
+        Ok(locks.get(&LockTarget::Block(blk.clone())) == Some(&LockMode::Exclusive))
     }
 }
 
@@ -139,9 +329,16 @@ mod tests {
     use tempfile::TempDir;
 
     use crate::{
-        file::block_id::BlockId, server::simple_db::SimpleDB, tx::transaction::Transaction,
+        file::block_id::BlockId,
+        server::simple_db::SimpleDB,
+        tx::{
+            concurrency::{isolation_level::IsolationLevel, lock_mode::LockMode, lock_table::LockTable},
+            transaction::Transaction,
+        },
     };
 
+    use super::ConcurrencyManager;
+
     #[test]
     fn test_concurrency_manager() {
         let temp_dir = TempDir::new().unwrap();
@@ -157,8 +354,11 @@ mod tests {
                 db_a.log_mgr(),
                 db_a.buffer_manager(),
                 db_a.lock_table(),
+                db_a.version_store(),
             )
             .unwrap();
+            // reads take shared locks only when asked to
+            tx_a.set_locking_reads(true);
             let blk1 = BlockId::new("testfile".to_string(), 1);
             let blk2 = BlockId::new("testfile".to_string(), 2);
             tx_a.pin(&blk1).unwrap();
@@ -186,8 +386,11 @@ mod tests {
                 db_b.log_mgr(),
                 db_b.buffer_manager(),
                 db_b.lock_table(),
+                db_b.version_store(),
             )
             .unwrap();
+            // reads take shared locks only when asked to
+            tx_b.set_locking_reads(true);
             let blk1 = BlockId::new("testfile".to_string(), 1);
             let blk2 = BlockId::new("testfile".to_string(), 2);
             tx_b.pin(&blk1).unwrap();
@@ -214,8 +417,11 @@ mod tests {
                 db_c.log_mgr(),
                 db_c.buffer_manager(),
                 db_c.lock_table(),
+                db_c.version_store(),
             )
             .unwrap();
+            // reads take shared locks only when asked to
+            tx_c.set_locking_reads(true);
             let blk1 = BlockId::new("testfile".to_string(), 1);
             let blk2 = BlockId::new("testfile".to_string(), 2);
             tx_c.pin(&blk1).unwrap();
@@ -243,4 +449,57 @@ mod tests {
         handle_b.join().unwrap();
         handle_c.join().unwrap();
     }
+
+    #[test]
+    fn test_file_locks_and_escalation() {
+        let lt = Arc::new(LockTable::new_with_timeout(100).with_escalation_blocks(3));
+        let blk = |n| BlockId::new("t.tbl".to_string(), n);
+        let mut cm1 = ConcurrencyManager::new(1, lt.clone());
+        let mut cm2 = ConcurrencyManager::new(2, lt.clone());
+
+        // a writer's intention lock keeps DDL from locking the whole file
+        cm1.x_lock(&blk(0)).unwrap();
+        assert_eq!(cm2.lock_file("t.tbl", LockMode::Shared), Err("lock abort".to_string()));
+        cm2.x_lock(&blk(1)).unwrap();
+        cm2.release().unwrap();
+
+        // the fourth block lock turns into a lock on the file
+        cm1.s_lock(&blk(2)).unwrap();
+        cm1.s_lock(&blk(3)).unwrap();
+        cm1.s_lock(&blk(4)).unwrap();
+        assert!(cm1.x_lock(&blk(5)).is_ok());
+        assert!(cm2.s_lock(&blk(1)).is_err());
+        assert!(cm2.s_lock(&BlockId::new("u.tbl".to_string(), 0)).is_ok());
+        cm1.release().unwrap();
+        cm2.lock_file("t.tbl", LockMode::Shared).unwrap();
+        cm2.release().unwrap();
+
+        // with another transaction in the file, the locks stay fine-grained
+        // rather than waiting for the file
+        cm2.s_lock(&blk(9)).unwrap();
+        for n in 0..6 {
+            cm1.x_lock(&blk(n)).unwrap();
+        }
+        assert!(cm2.s_lock(&blk(8)).is_ok());
+        assert!(cm2.s_lock(&blk(0)).is_err());
+    }
+
+    #[test]
+    fn test_read_committed_gives_back_read_locks() {
+        let lt = Arc::new(LockTable::new_with_timeout(100));
+        let blk = BlockId::new("t.tbl".to_string(), 0);
+        let mut cm1 = ConcurrencyManager::new(1, lt.clone());
+        cm1.set_isolation_level(IsolationLevel::ReadCommitted);
+        let cm2 = ConcurrencyManager::new(2, lt.clone());
+
+        // reading a block the transaction writes records of makes its IX a SIX
+        // for the read only
+        cm1.x_lock_record(&blk, 0, 4, 8).unwrap();
+        let taken = cm1.read_lock(&blk).unwrap();
+        assert!(cm2.x_lock_record(&blk, 1, 8, 12).is_err());
+        cm1.end_read(taken).unwrap();
+        assert!(cm2.x_lock_record(&blk, 1, 8, 12).is_ok());
+        assert!(cm2.x_lock_record(&blk, 0, 4, 8).is_err());
+        cm1.release().unwrap();
+    }
 }
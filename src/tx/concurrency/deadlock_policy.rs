@@ -0,0 +1,30 @@
+use std::str::FromStr;
+
+// How the lock table keeps transactions from waiting for each other forever. An
+// older transaction is one with a smaller number. The lock timeout still applies
+// under every policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeadlockPolicy {
+    // Waits freely, looking for a cycle in the waits-for graph whenever a
+    // request has to wait; the youngest transaction in a cycle is aborted.
+    #[default]
+    Detect,
+    // A transaction may wait only for younger ones; otherwise it is aborted.
+    WaitDie,
+    // A transaction aborts the younger ones it would wait for, and waits only
+    // for older ones.
+    WoundWait,
+}
+
+impl FromStr for DeadlockPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "detect" => Ok(DeadlockPolicy::Detect),
+            "wait-die" | "wait_die" => Ok(DeadlockPolicy::WaitDie),
+            "wound-wait" | "wound_wait" => Ok(DeadlockPolicy::WoundWait),
+            _ => Err(format!("unknown deadlock policy {}", s)),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// What a transaction's reads may see of other transactions' changes. Writes
+// always hold exclusive locks until the transaction ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationLevel {
+    // Reads take no locks and see uncommitted changes.
+    ReadUncommitted,
+    // Reads see committed changes, holding a shared lock only while reading.
+    ReadCommitted,
+    // Reads see the snapshot taken when the transaction started and take no
+    // locks; of two transactions updating the same record, the first wins.
+    #[default]
+    RepeatableRead,
+    // Reads hold shared locks until the transaction ends, and a scan locks its
+    // whole table.
+    Serializable,
+}
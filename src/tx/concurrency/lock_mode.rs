@@ -0,0 +1,151 @@
+use crate::{file::block_id::BlockId, query::constant::Constant};
+
+// What a lock is taken on. Locks form a hierarchy: the database contains files,
+// which contain blocks, which contain records; the keys of an index are in its
+// leaf file. Before locking a node a transaction holds an intention
+// lock on each of its ancestors, so a lock on a whole file conflicts with locks
+// on its blocks without visiting them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    Database,
+    File(String),
+    Block(BlockId),
+    // a slot in a record page
+    Record(BlockId, i32),
+    // the entries of an index with one search key, including those not
+    // inserted yet, in the index's leaf file
+    Key(String, Constant),
+}
+
+impl LockTarget {
+    pub fn parent(&self) -> Option<LockTarget> {
+        match self {
+            LockTarget::Database => None,
+            LockTarget::File(_) => Some(LockTarget::Database),
+            LockTarget::Block(blk) => Some(LockTarget::File(blk.file_name())),
+            LockTarget::Record(blk, _) => Some(LockTarget::Block(blk.clone())),
+            LockTarget::Key(filename, _) => Some(LockTarget::File(filename.clone())),
+        }
+    }
+
+    // The file the target is in, if it is a block, a record or a key.
+    pub fn file(&self) -> Option<LockTarget> {
+        match self {
+            LockTarget::Database | LockTarget::File(_) => None,
+            LockTarget::Block(blk) | LockTarget::Record(blk, _) => {
+                Some(LockTarget::File(blk.file_name()))
+            }
+            LockTarget::Key(filename, _) => Some(LockTarget::File(filename.clone())),
+        }
+    }
+}
+
+impl std::fmt::Display for LockTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockTarget::Database => write!(f, "database"),
+            LockTarget::File(filename) => write!(f, "file {}", filename),
+            LockTarget::Block(blk) => write!(f, "block {}", blk),
+            LockTarget::Record(blk, slot) => write!(f, "slot {} of block {}", slot, blk),
+            LockTarget::Key(filename, key) => write!(f, "key {} of {}", key, filename),
+        }
+    }
+}
+
+// Ordered so that a later mode is never weaker than an earlier one, except that
+// IntentionExclusive and Shared are incomparable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockMode {
+    // IS: the transaction reads some descendants.
+    IntentionShared,
+    // IX: the transaction writes some descendants.
+    IntentionExclusive,
+    // S: the transaction reads the node and everything below it.
+    Shared,
+    // SIX: S and IX together, for reading a whole file while changing a few blocks.
+    SharedIntentionExclusive,
+    // X: the transaction reads and writes the node and everything below it.
+    Exclusive,
+}
+
+impl LockMode {
+    pub fn compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+        matches!(
+            (self.min(other), self.max(other)),
+            (IntentionShared, IntentionShared)
+                | (IntentionShared, IntentionExclusive)
+                | (IntentionShared, Shared)
+                | (IntentionShared, SharedIntentionExclusive)
+                | (IntentionExclusive, IntentionExclusive)
+                | (Shared, Shared)
+        )
+    }
+
+    // The weakest mode that grants what both do.
+    pub fn join(self, other: LockMode) -> LockMode {
+        use LockMode::*;
+        match (self, other) {
+            (IntentionExclusive, Shared) | (Shared, IntentionExclusive) => SharedIntentionExclusive,
+            _ => self.max(other),
+        }
+    }
+
+    pub fn covers(self, other: LockMode) -> bool {
+        self.join(other) == self
+    }
+
+    // The mode this lock grants on every node below it, if any.
+    pub fn implicit(self) -> Option<LockMode> {
+        match self {
+            LockMode::IntentionShared | LockMode::IntentionExclusive => None,
+            LockMode::Shared | LockMode::SharedIntentionExclusive => Some(LockMode::Shared),
+            LockMode::Exclusive => Some(LockMode::Exclusive),
+        }
+    }
+
+    // The mode the ancestors of a node locked in this mode must be held in.
+    pub fn intention(self) -> LockMode {
+        match self {
+            LockMode::IntentionShared | LockMode::Shared => LockMode::IntentionShared,
+            _ => LockMode::IntentionExclusive,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockMode::{self, *};
+
+    #[test]
+    fn test_lock_mode_compatibility() {
+        let modes = [
+            IntentionShared,
+            IntentionExclusive,
+            Shared,
+            SharedIntentionExclusive,
+            Exclusive,
+        ];
+        // rows and columns in the order above
+        let matrix = [
+            [true, true, true, true, false],
+            [true, true, false, false, false],
+            [true, false, true, false, false],
+            [true, false, false, false, false],
+            [false, false, false, false, false],
+        ];
+        for (i, a) in modes.iter().enumerate() {
+            for (j, b) in modes.iter().enumerate() {
+                assert_eq!(a.compatible(*b), matrix[i][j], "{:?} {:?}", a, b);
+            }
+        }
+
+        assert_eq!(IntentionExclusive.join(Shared), SharedIntentionExclusive);
+        assert_eq!(IntentionShared.join(Shared), Shared);
+        assert!(SharedIntentionExclusive.covers(Shared));
+        assert!(!Shared.covers(IntentionExclusive));
+        assert_eq!(LockMode::SharedIntentionExclusive.intention(), IntentionExclusive);
+        assert_eq!(SharedIntentionExclusive.implicit(), Some(Shared));
+        assert_eq!(IntentionExclusive.implicit(), None);
+    }
+}
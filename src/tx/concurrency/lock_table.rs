@@ -1,61 +1,430 @@
 use core::fmt;
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
 };
 
-use crate::file::block_id::BlockId;
+use super::{
+    deadlock_policy::DeadlockPolicy,
+    lock_mode::{LockMode, LockTarget},
+};
+
+pub const DEFAULT_LOCK_TIMEOUT_MS: i64 = 10_000;
+pub const DEFAULT_LOCK_ESCALATION_BLOCKS: usize = 64;
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    txnum: i64,
+    mode: LockMode,
+}
 
+#[derive(Debug, Default)]
+struct LockState {
+    // the transactions holding each target and their modes
+    holders: HashMap<LockTarget, Vec<(i64, LockMode)>>,
+    // the requests waiting for each target, served front to back
+    waiters: HashMap<LockTarget, VecDeque<Waiter>>,
+    // transactions chosen to abort; each gives up when it next waits, asks
+    // for a lock or starts a statement
+    victims: HashSet<i64>,
+    next_ticket: u64,
+}
+
+// Waiting requests are parked on a condition variable and woken whenever a lock
+// is released. They are granted in arrival order, except that an upgrade of a
+// lock already held goes first, since its holder blocks everyone queued behind
+// it anyway. The table knows which transaction holds or waits for
+// each lock, so it can break deadlocks as the policy says instead of waiting
+// for the timeout.
 #[derive(Debug)]
 pub struct LockTable {
-    pub(crate) locks: Arc<Mutex<HashMap<BlockId, i32>>>,
+    state: Mutex<LockState>,
+    released: Condvar,
+    max_wait: i64,
+    policy: DeadlockPolicy,
+    escalation_blocks: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct LockAbortException;
+pub enum LockAbortException {
+    Timeout,
+    // the transaction was aborted to break a deadlock, or to prevent one
+    Deadlock(i64),
+}
 impl fmt::Display for LockAbortException {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "lock abort")
+        match self {
+            LockAbortException::Timeout => write!(f, "lock abort"),
+            LockAbortException::Deadlock(txnum) => {
+                write!(f, "deadlock: transaction {} aborted", txnum)
+            }
+        }
     }
 }
 
 impl LockTable {
     pub fn new() -> Self {
+        Self::new_with_timeout(DEFAULT_LOCK_TIMEOUT_MS)
+    }
+
+    pub fn new_with_timeout(max_wait: i64) -> Self {
         Self {
-            locks: Arc::new(Mutex::new(HashMap::new())),
+            state: Mutex::new(LockState::default()),
+            released: Condvar::new(),
+            max_wait,
+            policy: DeadlockPolicy::default(),
+            escalation_blocks: DEFAULT_LOCK_ESCALATION_BLOCKS,
         }
     }
 
-    pub(crate) fn unlock(&self, blk: &BlockId) -> Result<(), String> {
-        let val = self.get_lock_val(blk)?;
-        if val > 1 {
-            self.locks
-                .lock()
-                .map_err(|_| "failed to get lock")?
-                .insert(blk.clone(), val - 1);
-        } else {
-            self.locks
-                .lock()
-                .map_err(|_| "failed to get lock")?
-                .remove(blk);
+    pub fn with_deadlock_policy(mut self, policy: DeadlockPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    // A transaction that would hold more block and record locks than this in
+    // one file locks the whole file instead; 0 disables escalation.
+    pub fn with_escalation_blocks(mut self, blocks: usize) -> Self {
+        self.escalation_blocks = blocks;
+        self
+    }
+
+    // How long a transaction waits for a lock before giving up, in milliseconds.
+    pub fn max_wait(&self) -> i64 {
+        self.max_wait
+    }
+
+    pub fn deadlock_policy(&self) -> DeadlockPolicy {
+        self.policy
+    }
+
+    pub fn escalation_blocks(&self) -> usize {
+        self.escalation_blocks
+    }
+
+    // Locks the target in `mode`, or upgrades the transaction's lock on it to
+    // cover `mode` too. The caller takes care of the intention locks above it.
+    pub(crate) fn lock(&self, txnum: i64, target: &LockTarget, mode: LockMode) -> Result<(), LockAbortException> {
+        self.acquire(txnum, target, mode)
+    }
+
+    // Like lock, but gives up at once if the lock cannot be granted. Returns
+    // whether it was.
+    pub(crate) fn try_lock(&self, txnum: i64, target: &LockTarget, mode: LockMode) -> Result<bool, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let held = Self::held(&st, txnum, target);
+        if held.is_some_and(|m| m.covers(mode)) {
+            return Ok(true);
+        }
+        let mode = held.map_or(mode, |m| m.join(mode));
+        let conflicts = Self::conflicts(&st, txnum, target, mode);
+        if !Self::grantable(&st, target, &conflicts) {
+            return Ok(false);
+        }
+        Self::grant(&mut st, txnum, target, mode);
+        Ok(true)
+    }
+
+    // Fails if the transaction was chosen to abort since its last request. A
+    // wounded transaction that is not waiting learns it here, at its next
+    // statement, rather than only at its next lock request.
+    pub(crate) fn check_wounded(&self, txnum: i64) -> Result<(), LockAbortException> {
+        let mut st = self.state.lock().map_err(|_| LockAbortException::Timeout)?;
+        if st.victims.remove(&txnum) {
+            return Err(LockAbortException::Deadlock(txnum));
         }
         Ok(())
     }
 
-    pub(crate) fn has_x_lock(&self, blk: &BlockId) -> Result<bool, String> {
-        Ok(self.get_lock_val(blk)? < 0)
+    pub(crate) fn unlock(&self, txnum: i64, target: &LockTarget) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        if let Some(holders) = st.holders.get_mut(target) {
+            holders.retain(|(t, _)| *t != txnum);
+            if holders.is_empty() {
+                st.holders.remove(target);
+            }
+        }
+        self.released.notify_all();
+        Ok(())
     }
 
-    pub(crate) fn has_other_s_locks(&self, blk: &BlockId) -> Result<bool, String> {
-        Ok(self.get_lock_val(blk)? > 1)
+    // Gives back part of a lock the transaction holds, leaving it in `mode`.
+    pub(crate) fn downgrade(&self, txnum: i64, target: &LockTarget, mode: LockMode) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        if let Some(holder) = st
+            .holders
+            .get_mut(target)
+            .and_then(|h| h.iter_mut().find(|(t, _)| *t == txnum))
+        {
+            holder.1 = mode;
+        }
+        self.released.notify_all();
+        Ok(())
     }
 
-    pub(crate) fn get_lock_val(&self, blk: &BlockId) -> Result<i32, String> {
-        Ok(*self
-            .locks
+    // Called when a transaction ends, in case it was wounded after its last
+    // request.
+    pub(crate) fn forget(&self, txnum: i64) -> Result<(), String> {
+        self.state
             .lock()
             .map_err(|_| "failed to get lock")?
-            .get(blk)
-            .unwrap_or(&0))
+            .victims
+            .remove(&txnum);
+        Ok(())
+    }
+
+    fn acquire(&self, txnum: i64, target: &LockTarget, mode: LockMode) -> Result<(), LockAbortException> {
+        let deadline = Instant::now() + Duration::from_millis(self.max_wait.max(0) as u64);
+        let mut st = self.state.lock().map_err(|_| LockAbortException::Timeout)?;
+        if st.victims.remove(&txnum) {
+            return Err(LockAbortException::Deadlock(txnum));
+        }
+
+        let held = Self::held(&st, txnum, target);
+        if held.is_some_and(|m| m.covers(mode)) {
+            return Ok(());
+        }
+        let mode = held.map_or(mode, |m| m.join(mode));
+        let conflicts = Self::conflicts(&st, txnum, target, mode);
+        if Self::grantable(&st, target, &conflicts) {
+            Self::grant(&mut st, txnum, target, mode);
+            return Ok(());
+        }
+
+        match self.policy {
+            DeadlockPolicy::Detect => {}
+            DeadlockPolicy::WaitDie => {
+                if conflicts.iter().any(|t| *t < txnum) {
+                    return Err(LockAbortException::Deadlock(txnum));
+                }
+            }
+            DeadlockPolicy::WoundWait => {
+                st.victims.extend(conflicts.iter().filter(|t| **t > txnum));
+                self.released.notify_all();
+            }
+        }
+
+        let ticket = st.next_ticket;
+        st.next_ticket += 1;
+        let queue = st.waiters.entry(target.clone()).or_default();
+        if held.is_some() {
+            queue.push_front(Waiter { ticket, txnum, mode });
+        } else {
+            queue.push_back(Waiter { ticket, txnum, mode });
+        }
+
+        loop {
+            if st.victims.remove(&txnum) {
+                Self::dequeue(&mut st, target, ticket);
+                self.released.notify_all();
+                return Err(LockAbortException::Deadlock(txnum));
+            }
+            let first = st
+                .waiters
+                .get(target)
+                .and_then(|q| q.front())
+                .is_some_and(|w| w.ticket == ticket);
+            if first && Self::conflicts(&st, txnum, target, mode).is_empty() {
+                Self::dequeue(&mut st, target, ticket);
+                Self::grant(&mut st, txnum, target, mode);
+                // the next waiter may be compatible too
+                self.released.notify_all();
+                return Ok(());
+            }
+            if self.policy == DeadlockPolicy::Detect {
+                if let Some(victim) = Self::find_cycle(&st, txnum).and_then(|c| c.into_iter().max()) {
+                    if victim == txnum {
+                        Self::dequeue(&mut st, target, ticket);
+                        self.released.notify_all();
+                        return Err(LockAbortException::Deadlock(txnum));
+                    }
+                    st.victims.insert(victim);
+                    self.released.notify_all();
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                Self::dequeue(&mut st, target, ticket);
+                self.released.notify_all();
+                return Err(LockAbortException::Timeout);
+            }
+            st = self
+                .released
+                .wait_timeout(st, deadline - now)
+                .map_err(|_| LockAbortException::Timeout)?
+                .0;
+        }
+    }
+
+    fn held(st: &LockState, txnum: i64, target: &LockTarget) -> Option<LockMode> {
+        st.holders
+            .get(target)
+            .and_then(|h| h.iter().find(|(t, _)| *t == txnum))
+            .map(|(_, m)| *m)
+    }
+
+    // The other transactions whose locks keep the request from being granted.
+    fn conflicts(st: &LockState, txnum: i64, target: &LockTarget, mode: LockMode) -> Vec<i64> {
+        st.holders
+            .get(target)
+            .into_iter()
+            .flatten()
+            .filter(|(t, m)| *t != txnum && !m.compatible(mode))
+            .map(|(t, _)| *t)
+            .collect()
+    }
+
+    // Whether a new request can be granted without waiting: joining compatible
+    // holders does not delay anyone queued for the target.
+    fn grantable(st: &LockState, target: &LockTarget, conflicts: &[i64]) -> bool {
+        let queued = st.waiters.get(target).is_some_and(|q| !q.is_empty());
+        let locked = st.holders.contains_key(target);
+        conflicts.is_empty() && (!queued || locked)
+    }
+
+    fn grant(st: &mut LockState, txnum: i64, target: &LockTarget, mode: LockMode) {
+        let holders = st.holders.entry(target.clone()).or_default();
+        holders.retain(|(t, _)| *t != txnum);
+        holders.push((txnum, mode));
+    }
+
+    fn dequeue(st: &mut LockState, target: &LockTarget, ticket: u64) {
+        if let Some(queue) = st.waiters.get_mut(target) {
+            queue.retain(|w| w.ticket != ticket);
+            if queue.is_empty() {
+                st.waiters.remove(target);
+            }
+        }
+    }
+
+    // A waiting transaction waits for the holders its request conflicts with
+    // and for the requests queued ahead of its own.
+    fn waits_for(st: &LockState) -> HashMap<i64, Vec<i64>> {
+        let mut edges: HashMap<i64, Vec<i64>> = HashMap::new();
+        for (target, queue) in &st.waiters {
+            for (i, w) in queue.iter().enumerate() {
+                let holders = Self::conflicts(st, w.txnum, target, w.mode);
+                let ahead = queue.iter().take(i).map(|a| a.txnum);
+                edges
+                    .entry(w.txnum)
+                    .or_default()
+                    .extend(holders.into_iter().chain(ahead).filter(|t| *t != w.txnum));
+            }
+        }
+        edges
+    }
+
+    // The transactions on a cycle of the waits-for graph through `txnum`, if any.
+    fn find_cycle(st: &LockState, txnum: i64) -> Option<Vec<i64>> {
+        let edges = Self::waits_for(st);
+        let mut path = vec![txnum];
+        let mut visited = HashSet::new();
+        Self::search(&edges, txnum, &mut path, &mut visited).then_some(path)
+    }
+
+    fn search(edges: &HashMap<i64, Vec<i64>>, start: i64, path: &mut Vec<i64>, visited: &mut HashSet<i64>) -> bool {
+        let node = *path.last().unwrap();
+        for next in edges.get(&node).into_iter().flatten() {
+            if *next == start {
+                return true;
+            }
+            if visited.insert(*next) {
+                path.push(*next);
+                if Self::search(edges, start, path, visited) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{LockAbortException, LockTable};
+    use crate::{
+        file::block_id::BlockId,
+        tx::concurrency::{
+            deadlock_policy::DeadlockPolicy,
+            lock_mode::{LockMode::*, LockTarget},
+        },
+    };
+
+    #[test]
+    fn test_waiters_are_woken_in_arrival_order() {
+        let lt = Arc::new(LockTable::new_with_timeout(5_000));
+        let blk = LockTarget::Block(BlockId::new("testfile".to_string(), 1));
+        lt.lock(1, &blk, Shared).unwrap();
+        lt.lock(1, &blk, Exclusive).unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let (lt, blk, order) = (lt.clone(), blk.clone(), order.clone());
+                let h = thread::spawn(move || {
+                    lt.lock(i + 2, &blk, Shared).unwrap();
+                    lt.lock(i + 2, &blk, Exclusive).unwrap();
+                    order.lock().unwrap().push(i);
+                    lt.unlock(i + 2, &blk).unwrap();
+                });
+                // let each request queue up before the next one arrives
+                thread::sleep(Duration::from_millis(50));
+                h
+            })
+            .collect();
+
+        let start = Instant::now();
+        lt.unlock(1, &blk).unwrap();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let lt = LockTable::new_with_timeout(50);
+        lt.lock(1, &blk, Shared).unwrap();
+        lt.lock(1, &blk, Exclusive).unwrap();
+        assert_eq!(lt.lock(2, &blk, Shared), Err(LockAbortException::Timeout));
+    }
+
+    #[test]
+    fn test_deadlocks_abort_the_youngest() {
+        let blk_a = LockTarget::Block(BlockId::new("testfile".to_string(), 1));
+        let blk_b = LockTarget::Block(BlockId::new("testfile".to_string(), 2));
+        for policy in [
+            DeadlockPolicy::Detect,
+            DeadlockPolicy::WaitDie,
+            DeadlockPolicy::WoundWait,
+        ] {
+            let lt = Arc::new(LockTable::new_with_timeout(5_000).with_deadlock_policy(policy));
+            lt.lock(1, &blk_a, Shared).unwrap();
+            lt.lock(1, &blk_a, Exclusive).unwrap();
+            lt.lock(2, &blk_b, Shared).unwrap();
+            lt.lock(2, &blk_b, Exclusive).unwrap();
+
+            let start = Instant::now();
+            let h = {
+                let (lt, blk_a, blk_b) = (lt.clone(), blk_a.clone(), blk_b.clone());
+                thread::spawn(move || {
+                    let res = lt.lock(2, &blk_a, Shared);
+                    lt.unlock(2, &blk_b).unwrap();
+                    res
+                })
+            };
+            thread::sleep(Duration::from_millis(50));
+            // 1 closes the cycle; 2 is younger, so it gives way
+            lt.lock(1, &blk_b, Shared).unwrap();
+            assert_eq!(h.join().unwrap(), Err(LockAbortException::Deadlock(2)), "{:?}", policy);
+            assert!(start.elapsed() < Duration::from_secs(1), "{:?}", policy);
+        }
     }
 }
@@ -1,2 +1,6 @@
 pub mod concurrency_manager;
+pub mod deadlock_policy;
+pub mod isolation_level;
+pub mod lock_mode;
 pub mod lock_table;
+pub mod version_store;
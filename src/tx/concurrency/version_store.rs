@@ -0,0 +1,277 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::{
+    file::block_id::BlockId,
+    query::constant::Constant,
+    record::rid::RID,
+    tx::index_entry::IndexEntry,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OldValue {
+    Int(i32),
+    Str(String),
+}
+
+// A truncation whose transaction committed while older snapshots could still
+// read the blocks it removes. It waits until none can, and is dropped if the
+// blocks were written to in the meantime.
+#[derive(Debug, Clone)]
+pub struct DeferredTruncation {
+    pub txnum: i64,
+    pub filename: String,
+    pub numblocks: i32,
+    // the length of the file and the newest LSN when the transaction committed
+    pub size: i32,
+    pub lsn: i64,
+}
+
+// an index entry of a record deleted by a commit, with the commit's sequence
+type Retired = (u64, Box<dyn IndexEntry>);
+
+#[derive(Debug)]
+struct Change {
+    writer: i64,
+    offset: usize,
+    before: OldValue,
+}
+
+#[derive(Debug, Default)]
+struct VersionState {
+    // the logged changes to each block that some snapshot may not see, oldest first
+    chains: HashMap<BlockId, Vec<Change>>,
+    // when each writer with changes still in the chains committed
+    committed: HashMap<i64, u64>,
+    running: HashSet<i64>,
+    // the commit sequence each running transaction's snapshot sees up to
+    snapshots: HashMap<i64, u64>,
+    commit_seq: u64,
+    // the index entries of the records each commit deleted
+    retired: Vec<Retired>,
+    truncations: Vec<(u64, DeferredTruncation)>,
+}
+
+// Keeps the before-images of recent changes so that a transaction can read
+// blocks as they were when it started, without locks. A change is visible to a
+// snapshot if its writer committed before the snapshot was taken, or is the
+// snapshot's own transaction. An offset is written under an exclusive lock on
+// its record, or on its whole block for slot allocation and formatting, held
+// until the writer ends. No two running writers change the same offset, so the
+// changes to it that a snapshot cannot see are always the newest ones; reading
+// undoes them from the newest back. Latched writes, to B-tree pages, keep no
+// before-images, since their readers latch the pages instead.
+#[derive(Debug, Default)]
+pub struct VersionStore {
+    state: Mutex<VersionState>,
+}
+
+impl VersionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Takes the snapshot of a starting transaction.
+    pub fn begin(&self, txnum: i64) -> Result<u64, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let seq = st.commit_seq;
+        st.running.insert(txnum);
+        st.snapshots.insert(txnum, seq);
+        Ok(seq)
+    }
+
+    // For a transaction that stops reading its snapshot, so the before-images
+    // it would need can go.
+    pub fn end_snapshot(&self, txnum: i64) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.snapshots.remove(&txnum);
+        st.prune();
+        Ok(())
+    }
+
+    // Called with the block's latch held, after the change is made.
+    pub fn record(&self, writer: i64, blk: &BlockId, offset: usize, before: OldValue) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.chains.entry(blk.clone()).or_default().push(Change {
+            writer,
+            offset,
+            before,
+        });
+        Ok(())
+    }
+
+    // The value at `offset` as the snapshot sees it, or None if it sees the
+    // current one. Called with the block's latch held.
+    pub fn read(&self, reader: i64, snapshot: u64, blk: &BlockId, offset: usize) -> Result<Option<OldValue>, String> {
+        let st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let mut ret = None;
+        if let Some(chain) = st.chains.get(blk) {
+            for change in chain.iter().rev().filter(|c| c.offset == offset) {
+                if st.visible(change.writer, reader, snapshot) {
+                    break;
+                }
+                ret = Some(change.before.clone());
+            }
+        }
+        Ok(ret)
+    }
+
+    // Whether a transaction the snapshot cannot see changed the block between
+    // `start` and `end`. The caller holds an exclusive lock on the block, so any
+    // such transaction has committed.
+    pub fn conflicts(&self, writer: i64, snapshot: u64, blk: &BlockId, start: usize, end: usize) -> Result<bool, String> {
+        let st = self.state.lock().map_err(|_| "failed to get lock")?;
+        Ok(st.chains.get(blk).is_some_and(|chain| {
+            chain
+                .iter()
+                .any(|c| (start..end).contains(&c.offset) && !st.visible(c.writer, writer, snapshot))
+        }))
+    }
+
+    // Makes the transaction's changes visible to later snapshots and returns
+    // the sequence number of its commit.
+    pub fn commit(&self, txnum: i64) -> Result<u64, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.commit_seq += 1;
+        let seq = st.commit_seq;
+        st.committed.insert(txnum, seq);
+        st.running.remove(&txnum);
+        st.snapshots.remove(&txnum);
+        st.prune();
+        Ok(seq)
+    }
+
+    // Called once the transaction's changes are undone in the blocks.
+    pub fn rollback(&self, txnum: i64) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        for chain in st.chains.values_mut() {
+            chain.retain(|c| c.writer != txnum);
+        }
+        st.running.remove(&txnum);
+        st.snapshots.remove(&txnum);
+        st.prune();
+        Ok(())
+    }
+
+    // Keeps the index entries of the records deleted by commit `seq` while
+    // snapshots older than the commit may still look them up.
+    pub fn retire(&self, seq: u64, entries: Vec<Box<dyn IndexEntry>>) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.retired.extend(entries.into_iter().map(|e| (seq, e)));
+        Ok(())
+    }
+
+    // Hands out the retired index entries no snapshot needs any longer, with
+    // the commits they came from.
+    pub fn take_expired(&self) -> Result<Vec<Retired>, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let oldest = st.oldest_snapshot();
+        let (expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut st.retired)
+            .into_iter()
+            .partition(|(seq, _)| *seq <= oldest);
+        st.retired = kept;
+        Ok(expired)
+    }
+
+    // Whether the entry waits for older snapshots to finish before it goes.
+    pub fn is_retired(&self, idxname: &String, dataval: &Constant, datarid: &RID) -> Result<bool, String> {
+        let st = self.state.lock().map_err(|_| "failed to get lock")?;
+        Ok(st.retired.iter().any(|(_, e)| e.is(idxname, dataval, datarid)))
+    }
+
+    // Keeps the truncations of commit `seq` while older snapshots may still
+    // read the blocks.
+    pub fn defer_truncations(&self, seq: u64, truncations: Vec<DeferredTruncation>) -> Result<(), String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        st.truncations.extend(truncations.into_iter().map(|t| (seq, t)));
+        Ok(())
+    }
+
+    // Hands out the deferred truncations no snapshot needs any longer, with the
+    // commits they came from.
+    pub fn take_expired_truncations(&self) -> Result<Vec<(u64, DeferredTruncation)>, String> {
+        let mut st = self.state.lock().map_err(|_| "failed to get lock")?;
+        let oldest = st.oldest_snapshot();
+        let (expired, kept) = std::mem::take(&mut st.truncations)
+            .into_iter()
+            .partition(|(seq, _)| *seq <= oldest);
+        st.truncations = kept;
+        Ok(expired)
+    }
+
+    // Whether a running transaction took its snapshot before commit `seq`.
+    pub fn older_snapshots(&self, seq: u64) -> Result<bool, String> {
+        let st = self.state.lock().map_err(|_| "failed to get lock")?;
+        Ok(st.snapshots.values().any(|s| *s < seq))
+    }
+
+    // The number of before-images kept.
+    pub fn size(&self) -> usize {
+        self.state
+            .lock()
+            .map(|st| st.chains.values().map(|c| c.len()).sum())
+            .unwrap_or(0)
+    }
+}
+
+impl VersionState {
+    fn visible(&self, writer: i64, reader: i64, snapshot: u64) -> bool {
+        writer == reader || self.committed.get(&writer).is_some_and(|seq| *seq <= snapshot)
+    }
+
+    fn oldest_snapshot(&self) -> u64 {
+        self.snapshots.values().min().copied().unwrap_or(u64::MAX)
+    }
+
+    // Drops the changes every running snapshot sees.
+    fn prune(&mut self) {
+        let oldest = self.snapshots.values().copied().min().unwrap_or(self.commit_seq);
+        self.committed.retain(|_, seq| *seq > oldest);
+        let VersionState {
+            chains, committed, running, ..
+        } = self;
+        chains.retain(|_, chain| {
+            // a writer that is neither running nor committed was rolled back
+            chain.retain(|c| committed.contains_key(&c.writer) || running.contains(&c.writer));
+            !chain.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OldValue, VersionStore};
+    use crate::file::block_id::BlockId;
+
+    #[test]
+    fn test_version_store_reads_snapshots() {
+        let vs = VersionStore::new();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        let s1 = vs.begin(1).unwrap();
+        let s2 = vs.begin(2).unwrap();
+        // 1 changes offset 0 from 10 to 11
+        vs.record(1, &blk, 0, OldValue::Int(10)).unwrap();
+        assert_eq!(vs.read(1, s1, &blk, 0).unwrap(), None);
+        assert_eq!(vs.read(2, s2, &blk, 0).unwrap(), Some(OldValue::Int(10)));
+        vs.commit(1).unwrap();
+        let s3 = vs.begin(3).unwrap();
+        // 2 started before the commit, 3 after
+        assert_eq!(vs.read(2, s2, &blk, 0).unwrap(), Some(OldValue::Int(10)));
+        assert_eq!(vs.read(3, s3, &blk, 0).unwrap(), None);
+        assert!(vs.conflicts(2, s2, &blk, 0, 4).unwrap());
+        assert!(!vs.conflicts(3, s3, &blk, 0, 4).unwrap());
+        assert!(!vs.conflicts(2, s2, &blk, 4, 8).unwrap());
+
+        // 3 changes it again from 11 to 12; 2 still sees 10
+        vs.record(3, &blk, 0, OldValue::Int(11)).unwrap();
+        assert_eq!(vs.read(2, s2, &blk, 0).unwrap(), Some(OldValue::Int(10)));
+        vs.rollback(3).unwrap();
+        assert_eq!(vs.read(2, s2, &blk, 0).unwrap(), Some(OldValue::Int(10)));
+
+        // once no snapshot needs it, the before-image goes
+        vs.commit(2).unwrap();
+        assert_eq!(vs.size(), 0);
+    }
+}
@@ -0,0 +1,25 @@
+use std::fmt::Debug;
+
+use crate::{query::constant::Constant, record::rid::RID};
+
+use super::transaction::Transaction;
+
+// An entry of an index that a transaction changes apart from the scan that
+// asked for it: logged before it is added, so that a rollback removes it, and
+// removed after the commit that deleted its record. Each kind of index knows
+// how to open itself again from the entry.
+pub trait IndexEntry: Debug + Send + Sync {
+    fn idx_name(&self) -> String;
+    fn field_type(&self) -> i32;
+    fn field_length(&self) -> i32;
+    fn data_val(&self) -> Constant;
+    fn data_rid(&self) -> RID;
+
+    // Removes the entry from its index, if it is still there, in a system
+    // transaction of its own.
+    fn remove(&self, tx: &Transaction) -> Result<(), String>;
+
+    fn is(&self, idxname: &String, dataval: &Constant, datarid: &RID) -> bool {
+        self.idx_name().eq(idxname) && self.data_val().eq(dataval) && self.data_rid().eq(datarid)
+    }
+}
@@ -1,4 +1,5 @@
 mod buffer_list;
 pub mod concurrency;
+pub mod index_entry;
 mod recovery;
 pub mod transaction;
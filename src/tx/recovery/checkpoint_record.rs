@@ -1,47 +1,61 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    constants::INTEGER_BYTES, file::page::Page, log::log_manager::LogManager,
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::page::Page,
+    log::log_manager::LogManager,
     tx::transaction::Transaction,
 };
 
 use super::log_record::{self, LogRecord, CHECKPOINT};
 
-pub struct CheckpointRecord;
+// Carries the next transaction number so that numbering can resume after it
+// even once older log segments are gone.
+pub struct CheckpointRecord {
+    next_txnum: i64,
+}
 
 impl LogRecord for CheckpointRecord {
     fn op(&self) -> i32 {
         log_record::CHECKPOINT
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         -1
     }
 
+    fn next_tx_number(&self) -> Option<i64> {
+        Some(self.next_txnum)
+    }
+
     fn undo(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
         Ok(())
     }
+
+    fn redo(&self, _lsn: i64, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 impl std::fmt::Display for CheckpointRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<CHECKPOINT>",)?;
+        write!(f, "<CHECKPOINT {}>", self.next_txnum)?;
         Ok(())
     }
 }
 
 impl CheckpointRecord {
-    pub fn new() -> Self {
-        CheckpointRecord
+    pub fn new_from_page(p: Page) -> Result<Self, String> {
+        let next_txnum = p.get_long(INTEGER_BYTES as usize)?;
+        Ok(CheckpointRecord { next_txnum })
     }
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>) -> Result<i32, String> {
-        let mut p = Page::new_from_blocksize(INTEGER_BYTES as usize);
+
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>) -> Result<i64, String> {
+        let mut lm = lm.lock().map_err(|_| "failed to get lock")?;
+        let mut p = Page::new_from_blocksize((INTEGER_BYTES + LONG_BYTES) as usize);
         p.set_int(0, CHECKPOINT)?;
-        lm.lock().map_err(|_| "failed to get lock")?.append(
-            p.contents()
-                .lock()
-                .map_err(|_| "failed to get lock")?
-                .to_vec(),
-        )
+        p.set_long(INTEGER_BYTES as usize, lm.next_tx_number())?;
+        let bytes = p.contents().lock().map_err(|_| "failed to get lock")?.to_vec();
+        lm.append(bytes)
     }
 }
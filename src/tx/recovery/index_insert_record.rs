@@ -0,0 +1,129 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::page::Page,
+    index::btree::leaf_entry::LeafEntry,
+    log::log_manager::LogManager,
+    query::constant::Constant,
+    record::{rid::RID, schema::field_type::INTEGER},
+    tx::{index_entry::IndexEntry, transaction::Transaction},
+};
+
+use super::log_record::{self, LogRecord};
+
+// Written before an entry is added to an index for a transaction. The entry
+// itself is added by a system transaction, which logs the page changes and
+// commits on its own, so the pages may have been split and changed by others
+// since; undoing the insert looks the entry up again and removes it.
+pub struct IndexInsertRecord {
+    txnum: i64,
+    entry: LeafEntry,
+}
+
+impl LogRecord for IndexInsertRecord {
+    fn op(&self) -> i32 {
+        log_record::INDEXINSERT
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+
+    fn undo(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        let tx = tx.lock().map_err(|_| "failed to get lock")?;
+        self.entry.remove(&tx)
+    }
+
+    // the removal commits on its own, so redo repeats it whatever becomes of
+    // the transaction
+    fn compensate(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        self.undo(tx)
+    }
+
+    // redo repeats the page changes of the system transaction instead
+    fn redo(&self, _lsn: i64, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for IndexInsertRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<INDEXINSERT {} {} {} {}>",
+            self.txnum,
+            self.entry.idx_name(),
+            self.entry.data_val(),
+            self.entry.data_rid()
+        )?;
+        Ok(())
+    }
+}
+
+impl IndexInsertRecord {
+    pub fn new_from_page(p: Page) -> Result<Self, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let txnum = p.get_long(tpos)?;
+
+        let ipos = tpos + LONG_BYTES as usize;
+        let idxname = p.get_string(ipos)?;
+
+        let fpos = ipos + Page::max_length(idxname.len());
+        let fldtype = p.get_int(fpos)?;
+        let fldlen = p.get_int(fpos + INTEGER_BYTES as usize)?;
+
+        let rpos = fpos + 2 * INTEGER_BYTES as usize;
+        let datarid = RID::new(p.get_int(rpos)?, p.get_int(rpos + INTEGER_BYTES as usize)?);
+
+        let vpos = rpos + 2 * INTEGER_BYTES as usize;
+        let dataval = if fldtype == INTEGER {
+            Constant::new_from_i32(p.get_int(vpos)?)
+        } else {
+            Constant::new_from_string(p.get_string(vpos)?)
+        };
+
+        Ok(IndexInsertRecord {
+            txnum,
+            entry: LeafEntry::new(idxname, fldtype, fldlen, dataval, datarid),
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        txnum: i64,
+        entry: &dyn IndexEntry,
+    ) -> Result<i64, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let ipos = tpos + LONG_BYTES as usize;
+        let fpos = ipos + Page::max_length(entry.idx_name().len());
+        let rpos = fpos + 2 * INTEGER_BYTES as usize;
+        let vpos = rpos + 2 * INTEGER_BYTES as usize;
+        let dataval = entry.data_val();
+        let reclen = if entry.field_type() == INTEGER {
+            vpos + INTEGER_BYTES as usize
+        } else {
+            vpos + Page::max_length(dataval.as_string().unwrap().len())
+        };
+
+        let mut p = Page::new_from_blocksize(reclen);
+        p.set_int(0, log_record::INDEXINSERT)?;
+        p.set_long(tpos, txnum)?;
+        p.set_string(ipos, &entry.idx_name())?;
+        p.set_int(fpos, entry.field_type())?;
+        p.set_int(fpos + INTEGER_BYTES as usize, entry.field_length())?;
+        p.set_int(rpos, entry.data_rid().block_number())?;
+        p.set_int(rpos + INTEGER_BYTES as usize, entry.data_rid().slot())?;
+        if entry.field_type() == INTEGER {
+            p.set_int(vpos, dataval.as_int().unwrap())?;
+        } else {
+            p.set_string(vpos, &dataval.as_string().unwrap())?;
+        }
+        lm.lock().map_err(|_| "failed to get lock")?.append(
+            p.contents()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .to_vec(),
+        )
+    }
+}
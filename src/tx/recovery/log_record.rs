@@ -4,8 +4,10 @@ use crate::{file::page::Page, tx::transaction::Transaction};
 
 use super::{
     checkpoint_record::CheckpointRecord, commit_record::CommitRecord,
-    rollback_record::RollbackRecord, set_int_record::SetIntRecord,
+    index_insert_record::IndexInsertRecord, nq_checkpoint_record::NqCheckpointRecord, rollback_record::RollbackRecord,
+    savepoint_record::SavepointRecord, set_int_record::SetIntRecord,
     set_string_record::SetStringRecord, start_record::StartRecord,
+    truncate_record::TruncateRecord,
 };
 
 pub const CHECKPOINT: i32 = 0;
@@ -14,22 +16,46 @@ pub const COMMIT: i32 = 2;
 pub const ROLLBACK: i32 = 3;
 pub const SETINT: i32 = 4;
 pub const SETSTRING: i32 = 5;
+pub const TRUNCATE: i32 = 6;
+pub const NQCHECKPOINT: i32 = 7;
+pub const SAVEPOINT: i32 = 8;
+pub const INDEXINSERT: i32 = 9;
 
 pub trait LogRecord {
     fn op(&self) -> i32;
-    fn tx_number(&self) -> i32;
+    fn tx_number(&self) -> i64;
     fn undo(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String>;
+    // Reapplies the change if the page does not hold it yet, i.e. if the page
+    // LSN is older than the record's `lsn`.
+    fn redo(&self, lsn: i64, tx: Arc<Mutex<Transaction>>) -> Result<(), String>;
+    // Undoes the change with a logged update, for rolling back to a savepoint
+    // while the transaction goes on; redo then repeats the undo as well.
+    fn compensate(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+    // The number the next transaction was to get, noted by checkpoints.
+    fn next_tx_number(&self) -> Option<i64> {
+        None
+    }
+
+    fn as_nq_checkpoint(&self) -> Option<&NqCheckpointRecord> {
+        None
+    }
 }
 
 pub fn create_log_record(bytes: Vec<u8>) -> Result<Box<dyn LogRecord>, String> {
     let p = Page::new_from_bytes(bytes);
     match p.get_int(0)? {
-        CHECKPOINT => Ok(Box::new(CheckpointRecord::new())),
+        CHECKPOINT => Ok(Box::new(CheckpointRecord::new_from_page(p)?)),
         START => Ok(Box::new(StartRecord::new_from_page(p)?)),
         COMMIT => Ok(Box::new(CommitRecord::new_from_page(p)?)),
         ROLLBACK => Ok(Box::new(RollbackRecord::new_from_page(p)?)),
         SETINT => Ok(Box::new(SetIntRecord::new_from_page(p)?)),
         SETSTRING => Ok(Box::new(SetStringRecord::new_from_page(p)?)),
-        _ => panic!("unreachable!!"),
+        TRUNCATE => Ok(Box::new(TruncateRecord::new_from_page(p)?)),
+        NQCHECKPOINT => Ok(Box::new(NqCheckpointRecord::new_from_page(p)?)),
+        SAVEPOINT => Ok(Box::new(SavepointRecord::new_from_page(p)?)),
+        INDEXINSERT => Ok(Box::new(IndexInsertRecord::new_from_page(p)?)),
+        op => Err(format!("unknown log record type {}", op)),
     }
 }
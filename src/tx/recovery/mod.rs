@@ -1,8 +1,12 @@
 mod checkpoint_record;
 mod commit_record;
+mod index_insert_record;
 mod log_record;
+mod nq_checkpoint_record;
 pub mod recovery_manager;
 mod rollback_record;
+mod savepoint_record;
 mod set_int_record;
 mod set_string_record;
 mod start_record;
+mod truncate_record;
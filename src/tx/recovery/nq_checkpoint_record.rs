@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::page::Page,
+    log::log_manager::LogManager,
+    tx::transaction::Transaction,
+};
+
+use super::log_record::{self, LogRecord, NQCHECKPOINT};
+
+// A checkpoint taken while transactions run. Every change logged up to
+// `begin_lsn` was on disk when the record was written, and `txs` are the
+// transactions that were running at `begin_lsn`. Like CHECKPOINT, it carries
+// the next transaction number.
+pub struct NqCheckpointRecord {
+    next_txnum: i64,
+    begin_lsn: i64,
+    txs: Vec<i64>,
+}
+
+impl LogRecord for NqCheckpointRecord {
+    fn op(&self) -> i32 {
+        log_record::NQCHECKPOINT
+    }
+
+    fn tx_number(&self) -> i64 {
+        -1
+    }
+
+    fn next_tx_number(&self) -> Option<i64> {
+        Some(self.next_txnum)
+    }
+
+    fn undo(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn redo(&self, _lsn: i64, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn as_nq_checkpoint(&self) -> Option<&NqCheckpointRecord> {
+        Some(self)
+    }
+}
+
+impl std::fmt::Display for NqCheckpointRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<NQCKPT {} {}", self.next_txnum, self.begin_lsn)?;
+        for txnum in &self.txs {
+            write!(f, " {}", txnum)?;
+        }
+        write!(f, ">")?;
+        Ok(())
+    }
+}
+
+impl NqCheckpointRecord {
+    pub fn new_from_page(p: Page) -> Result<Self, String> {
+        let xpos = INTEGER_BYTES as usize;
+        let next_txnum = p.get_long(xpos)?;
+        let bpos = xpos + LONG_BYTES as usize;
+        let begin_lsn = p.get_long(bpos)?;
+        let npos = bpos + LONG_BYTES as usize;
+        let n = p.get_int(npos)?;
+        let tpos = npos + INTEGER_BYTES as usize;
+        let txs = (0..n as usize)
+            .map(|i| p.get_long(tpos + i * LONG_BYTES as usize))
+            .collect::<Result<Vec<i64>, String>>()?;
+        Ok(NqCheckpointRecord {
+            next_txnum,
+            begin_lsn,
+            txs,
+        })
+    }
+
+    pub fn begin_lsn(&self) -> i64 {
+        self.begin_lsn
+    }
+
+    pub fn txs(&self) -> &[i64] {
+        &self.txs
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        begin_lsn: i64,
+        txs: &[i64],
+    ) -> Result<i64, String> {
+        let xpos = INTEGER_BYTES as usize;
+        let bpos = xpos + LONG_BYTES as usize;
+        let npos = bpos + LONG_BYTES as usize;
+        let tpos = npos + INTEGER_BYTES as usize;
+        let mut lm = lm.lock().map_err(|_| "failed to get lock")?;
+        let mut p = Page::new_from_blocksize(tpos + txs.len() * LONG_BYTES as usize);
+        p.set_int(0, NQCHECKPOINT)?;
+        p.set_long(xpos, lm.next_tx_number())?;
+        p.set_long(bpos, begin_lsn)?;
+        p.set_int(npos, txs.len() as i32)?;
+        for (i, txnum) in txs.iter().enumerate() {
+            p.set_long(tpos + i * LONG_BYTES as usize, *txnum)?;
+        }
+        let bytes = p.contents().lock().map_err(|_| "failed to get lock")?.to_vec();
+        lm.append(bytes)
+    }
+}
@@ -1,73 +1,201 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    buffer::buffer_manager::BufferManager,
+    buffer::{buffer::Buffer, buffer_manager::BufferManager},
+    file::file_manager::PageReadException,
     log::log_manager::LogManager,
+    server::log_level::LogLevel,
+    tx::index_entry::IndexEntry,
 };
 
 use super::{
     checkpoint_record::CheckpointRecord, commit_record::CommitRecord,
-    log_record::{self, create_log_record},
-    rollback_record::RollbackRecord, set_int_record::SetIntRecord,
+    index_insert_record::IndexInsertRecord,
+    log_record::{self, create_log_record, LogRecord},
+    nq_checkpoint_record::NqCheckpointRecord,
+    rollback_record::RollbackRecord, savepoint_record::SavepointRecord, set_int_record::SetIntRecord,
     set_string_record::SetStringRecord, start_record::StartRecord,
+    truncate_record::TruncateRecord,
 };
 
 #[derive(Debug)]
 pub struct RecoveryManager {
     tx: Arc<Mutex<crate::tx::transaction::Transaction>>,
-    txnum: i32,
+    txnum: i64,
     lm: Arc<Mutex<LogManager>>,
-    bm: Arc<Mutex<BufferManager>>,
+    bm: Arc<BufferManager>,
 }
 
 impl RecoveryManager {
     pub fn new_from_managers(
         tx: Arc<Mutex<crate::tx::transaction::Transaction>>,
-        txnum: i32,
+        txnum: i64,
         lm: Arc<Mutex<LogManager>>,
-        bm: Arc<Mutex<BufferManager>>,
+        bm: Arc<BufferManager>,
     ) -> Result<Self, String> {
+        // registered first so that a checkpoint never lands between the two
+        lm.lock().map_err(|_| "failed to get lock")?.tx_started(txnum);
         StartRecord::write_to_log(lm.clone(), txnum)?;
         Ok(Self { tx, txnum, lm, bm })
     }
 
+    // Writes a non-quiescent checkpoint and returns its LSN. The running
+    // transactions and the end of the log are noted together, then every dirty
+    // page is written; a change is logged while its frame is latched, so each one
+    // logged by then is in the pages written. New transactions start and log
+    // freely in the meantime. Once the record is on disk, log segments older
+    // than both the checkpoint and the START of the listed transactions go.
+    pub fn checkpoint(lm: Arc<Mutex<LogManager>>, bm: Arc<BufferManager>) -> Result<i64, String> {
+        let (begin_lsn, txs, oldest) = {
+            let lm = lm.lock().map_err(|_| "failed to get lock")?;
+            (lm.latest_lsn(), lm.active_txs(), lm.oldest_active_lsn())
+        };
+        bm.flush_all_dirty()?;
+        let lsn = NqCheckpointRecord::write_to_log(lm.clone(), begin_lsn, &txs)?;
+        let mut lm = lm.lock().map_err(|_| "failed to get lock")?;
+        lm.flush(lsn)?;
+        lm.release_before(begin_lsn.min(oldest.unwrap_or(begin_lsn)) + 1)?;
+        Ok(lsn)
+    }
+
+    pub fn take_checkpoint(&self) -> Result<i64, String> {
+        Self::checkpoint(self.lm.clone(), self.bm.clone())
+    }
+
+    // No-force: the transaction's pages stay in the buffer pool and only the log is
+    // flushed, since recovery can redo the changes from their after-images. The
+    // flush, shared with concurrent commits, syncs the log, so the commit is
+    // durable on return unless the database was opened with DurabilityMode::None.
     pub fn commit(&mut self) -> Result<(), String> {
-        self.bm.lock().unwrap().flush_all(self.txnum)?;
         let lsn = CommitRecord::write_to_log(self.lm.clone(), self.txnum)?;
-        self.lm.lock().unwrap().flush(lsn)?;
+        LogManager::commit_flush(&self.lm, lsn)
+    }
+
+    // For system transactions, whose commit reaches disk with whatever is
+    // flushed next.
+    pub fn commit_unforced(&mut self) -> Result<(), String> {
+        CommitRecord::write_to_log(self.lm.clone(), self.txnum)?;
+        Ok(())
+    }
+
+    // Called once a committed transaction has applied its truncations; until then
+    // checkpoints list it as running, since a truncation logged before the
+    // checkpoint may not have reached the file yet.
+    pub fn end(&mut self) -> Result<(), String> {
+        self.lm
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .tx_finished(self.txnum);
         Ok(())
     }
 
     pub fn rollback(&mut self) -> Result<(), String> {
         self.do_rollback()?;
-        self.bm.lock().unwrap().flush_all(self.txnum)?;
+        self.bm.flush_all(self.txnum)?;
         let lsn = RollbackRecord::write_to_log(self.lm.clone(), self.txnum)?;
-        self.lm.lock().unwrap().flush(lsn)?;
+        let mut lm = self.lm.lock().map_err(|_| "failed to get lock")?;
+        lm.flush(lsn)?;
+        lm.tx_finished(self.txnum);
         Ok(())
     }
 
     pub fn recover(&mut self) -> Result<(), String> {
         self.do_recover()?;
-        self.bm.lock().unwrap().flush_all(self.txnum)?;
+        self.bm.flush_all_dirty()?;
         let lsn = CheckpointRecord::write_to_log(self.lm.clone())?;
-        self.lm.lock().unwrap().flush(lsn)?;
+        let mut lm = self.lm.lock().unwrap();
+        lm.flush(lsn)?;
+        lm.release_before(lsn)?;
         Ok(())
     }
 
-    pub fn set_int(&mut self, buff: Arc<Mutex<crate::buffer::buffer::Buffer>>, offset: i32) -> Result<i32, String> {
-        let oldval = buff.lock().unwrap().contents().get_int(offset as usize)?;
-        let blk = buff.lock().unwrap().block().unwrap();
-        let lsn = SetIntRecord::write_to_log(self.lm.clone(), self.txnum, &blk, offset, oldval)?;
+    // The caller holds the buffer's latch until the change is made.
+    pub fn set_int(&mut self, buff: &mut Buffer, offset: i32, newval: i32) -> Result<i64, String> {
+        let oldval = buff.contents().get_int(offset as usize)?;
+        let blk = buff.block().unwrap();
+        let lsn = SetIntRecord::write_to_log(self.lm.clone(), self.txnum, &blk, offset, oldval, newval)?;
         Ok(lsn)
     }
 
-    pub fn set_string(&mut self, buff: Arc<Mutex<crate::buffer::buffer::Buffer>>, offset: i32) -> Result<i32, String> {
-        let oldval = buff.lock().unwrap().contents().get_string(offset as usize)?;
-        let blk = buff.lock().unwrap().block().unwrap();
-        let lsn = SetStringRecord::write_to_log(self.lm.clone(), self.txnum, &blk, offset, oldval)?;
+    pub fn set_string(&mut self, buff: &mut Buffer, offset: i32, newval: String) -> Result<i64, String> {
+        let oldval = buff.contents().get_string(offset as usize)?;
+        let blk = buff.block().unwrap();
+        let lsn = SetStringRecord::write_to_log(self.lm.clone(), self.txnum, &blk, offset, oldval, newval)?;
         Ok(lsn)
     }
 
+    // Must be on disk before the file is truncated.
+    pub fn log_truncation(&mut self, filename: &String, numblocks: i32) -> Result<(), String> {
+        let lsn = TruncateRecord::write_to_log(self.lm.clone(), self.txnum, filename, numblocks)?;
+        self.lm.lock().map_err(|_| "failed to get lock")?.flush(lsn)
+    }
+
+    // For a truncation that the committed transaction `txnum` deferred; recovery
+    // redoes it as that transaction's.
+    pub fn log_deferred_truncation(&mut self, txnum: i64, filename: &String, numblocks: i32) -> Result<(), String> {
+        let lsn = TruncateRecord::write_to_log(self.lm.clone(), txnum, filename, numblocks)?;
+        self.lm.lock().map_err(|_| "failed to get lock")?.flush(lsn)
+    }
+
+    pub fn log_index_insert(&mut self, entry: &dyn IndexEntry) -> Result<(), String> {
+        IndexInsertRecord::write_to_log(self.lm.clone(), self.txnum, entry)?;
+        Ok(())
+    }
+
+    // Returns the LSN of the SAVEPOINT record.
+    pub fn savepoint(&mut self, name: &String) -> Result<i64, String> {
+        SavepointRecord::write_to_log(self.lm.clone(), self.txnum, name)
+    }
+
+    pub fn latest_lsn(&self) -> Result<i64, String> {
+        Ok(self.lm.lock().map_err(|_| "failed to get lock")?.latest_lsn())
+    }
+
+    // The transaction's records logged after `lsn`, newest first.
+    pub fn changes_since(&mut self, lsn: i64) -> Result<Vec<Box<dyn LogRecord>>, String> {
+        let mut lm = self.lm.lock().map_err(|_| "failed to get lock")?;
+        // the iterator only sees what is on disk
+        let latest = lm.latest_lsn();
+        lm.flush(latest)?;
+        let mut iter = lm.iterator()?;
+        let mut changes = Vec::new();
+        while let Some(bytes_res) = iter.next() {
+            if iter.lsn() <= lsn {
+                break;
+            }
+            let rec = create_log_record(bytes_res?)?;
+            if rec.tx_number() == self.txnum {
+                changes.push(rec);
+            }
+        }
+        Ok(changes)
+    }
+
+    // Reads the log back to its newest checkpoint, which notes the next number,
+    // so that no transaction number is handed out twice across restarts.
+    pub fn restore_tx_numbers(lm: Arc<Mutex<LogManager>>) -> Result<(), String> {
+        let mut next = 1;
+        let iter = lm.lock().map_err(|_| "failed to get lock")?.iterator()?;
+        for bytes_res in iter {
+            let bytes = match bytes_res {
+                Ok(bytes) => bytes,
+                Err(PageReadException::Corrupt(_)) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let rec = create_log_record(bytes)?;
+            if let Some(ckpt_next) = rec.next_tx_number() {
+                next = next.max(ckpt_next);
+                break;
+            }
+            next = next.max(rec.tx_number() + 1);
+        }
+        lm.lock().map_err(|_| "failed to get lock")?.seed_tx_numbers(next);
+        Ok(())
+    }
+
     fn do_rollback(&mut self) -> Result<(), String> {
         let mut iter = self.lm.lock().unwrap().iterator()?;
         while let Some(bytes_res) = iter.next() {
@@ -83,21 +211,89 @@ impl RecoveryManager {
         Ok(())
     }
 
+    // Restarts in three passes. Analysis reads the log backward to the last
+    // quiescent checkpoint, or past the newest non-quiescent one until it has seen
+    // the start of every transaction listed there that had not finished. It finds
+    // the transactions that committed or rolled back; redo repeats the committed
+    // changes whose pages missed them, oldest first, and undo rolls back the
+    // transactions that never finished, newest first. Rolled back transactions
+    // need neither since their undone pages were forced. Index entries are
+    // removed last, once the pages of the indexes are as the finished system
+    // transactions left them.
     fn do_recover(&mut self) -> Result<(), String> {
-        let mut finished_txs = Vec::new();
+        let mut records = Vec::new();
+        let mut committed = HashSet::new();
+        let mut finished = HashSet::new();
+        let mut started = HashSet::new();
+        // (begin LSN, transactions running then) of the newest fuzzy checkpoint
+        let mut nq_checkpoint: Option<(i64, HashSet<i64>)> = None;
         let mut iter = self.lm.lock().unwrap().iterator()?;
         while let Some(bytes_res) = iter.next() {
-            let bytes = bytes_res?;
+            // Everything older than a damaged log block is unreachable, so recovery
+            // works with what it could read.
+            let bytes = match bytes_res {
+                Ok(bytes) => bytes,
+                Err(PageReadException::Corrupt(blk)) => {
+                    if self.lm.lock().unwrap().log_level().enabled(LogLevel::Info) {
+                        eprintln!(
+                            "warning: log block {} is damaged; recovery stops at the last valid record",
+                            blk
+                        );
+                    }
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if let Some((begin_lsn, txs)) = &nq_checkpoint {
+                if iter.lsn() <= *begin_lsn
+                    && txs.iter().all(|t| finished.contains(t) || started.contains(t))
+                {
+                    break;
+                }
+            }
             let rec = create_log_record(bytes)?;
-            if rec.op() == log_record::CHECKPOINT {
-                return Ok(());
+            match rec.op() {
+                log_record::CHECKPOINT => break,
+                log_record::NQCHECKPOINT if nq_checkpoint.is_none() => {
+                    let ckpt = rec.as_nq_checkpoint().unwrap();
+                    nq_checkpoint = Some((ckpt.begin_lsn(), ckpt.txs().iter().copied().collect()));
+                }
+                log_record::START => {
+                    started.insert(rec.tx_number());
+                }
+                log_record::COMMIT => {
+                    committed.insert(rec.tx_number());
+                    finished.insert(rec.tx_number());
+                }
+                log_record::ROLLBACK => {
+                    finished.insert(rec.tx_number());
+                }
+                _ => {}
             }
-            if rec.op() == log_record::COMMIT || rec.op() == log_record::ROLLBACK {
-                finished_txs.push(rec.tx_number());
-            } else if !finished_txs.contains(&rec.tx_number()) {
-                rec.undo(self.tx.clone())?;
+            records.push((iter.lsn(), rec));
+        }
+
+        // Changes logged before the checkpoint began are on disk, except for the
+        // truncations of transactions still finishing their commit then.
+        let (redo_from, running) = match nq_checkpoint {
+            Some((begin_lsn, txs)) => (begin_lsn, txs),
+            None => (i64::MIN, HashSet::new()),
+        };
+        for (lsn, rec) in records.iter().rev() {
+            if committed.contains(&rec.tx_number())
+                && (*lsn > redo_from || running.contains(&rec.tx_number()))
+            {
+                rec.redo(*lsn, self.tx.clone())?;
             }
         }
+        let unfinished = records
+            .iter()
+            .filter(|(_, rec)| !finished.contains(&rec.tx_number()));
+        let (logical, physical): (Vec<_>, Vec<_>) =
+            unfinished.partition(|(_, rec)| rec.op() == log_record::INDEXINSERT);
+        for (_, rec) in physical.into_iter().chain(logical) {
+            rec.undo(self.tx.clone())?;
+        }
         Ok(())
     }
 }
@@ -111,6 +307,7 @@ mod tests {
         constants::INTEGER_BYTES,
         file::{block_id::BlockId, file_manager::FileManager, page::Page},
         server::simple_db::SimpleDB,
+        tx::transaction::Transaction,
     };
 
     #[test]
@@ -181,8 +378,8 @@ mod tests {
 
         let tx3_num = tx3.lock().unwrap().tx_num();
         let tx4_num = tx4.lock().unwrap().tx_num();
-        bm.lock().unwrap().flush_all(tx3_num).unwrap();
-        bm.lock().unwrap().flush_all(tx4_num).unwrap();
+        bm.flush_all(tx3_num).unwrap();
+        bm.flush_all(tx4_num).unwrap();
 
         print_values(&fm, &blk0, &blk1, "After modification");
 
@@ -259,6 +456,207 @@ mod tests {
         assert_eq!(p1.get_string(30).unwrap(), "def");
     }
 
+    #[test]
+    fn test_recovery_redoes_committed_and_undoes_unfinished() {
+        let temp_dir = TempDir::new().unwrap();
+        let blk0 = BlockId::new("testfile".to_string(), 0);
+        let blk1 = BlockId::new("testfile".to_string(), 1);
+        {
+            let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+            let tx1 = db.new_tx();
+            let tx2 = db.new_tx();
+            {
+                let tx1 = tx1.lock().unwrap();
+                tx1.pin(&blk0).unwrap();
+                tx1.set_int(&blk0, 0, 123, true).unwrap();
+                tx1.set_string(&blk0, 30, "abc".to_string(), true).unwrap();
+                let tx2 = tx2.lock().unwrap();
+                tx2.pin(&blk1).unwrap();
+                tx2.set_int(&blk1, 0, 456, true).unwrap();
+            }
+            tx1.lock().unwrap().commit().unwrap();
+            // the commit only forced the log
+            let mut p = Page::new_from_blocksize(400);
+            db.file_manager().read(&blk0, &mut p).unwrap();
+            assert_eq!(p.get_int(0).unwrap(), 0);
+
+            // the unfinished change reaches disk, then the process dies
+            let tx2_num = tx2.lock().unwrap().tx_num();
+            db.buffer_manager().flush_all(tx2_num).unwrap();
+        }
+
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        db.new_tx().lock().unwrap().recover().unwrap();
+        let fm = db.file_manager();
+        let mut p0 = Page::new_from_blocksize(400);
+        let mut p1 = Page::new_from_blocksize(400);
+        let lsn = fm.read_with_lsn(&blk0, &mut p0).unwrap();
+        fm.read(&blk1, &mut p1).unwrap();
+        assert_eq!(p0.get_int(0).unwrap(), 123);
+        assert_eq!(p0.get_string(30).unwrap(), "abc");
+        assert_eq!(p1.get_int(0).unwrap(), 0);
+        assert!(lsn > 0);
+    }
+
+    #[test]
+    fn test_recovery_starts_from_nq_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let blk0 = BlockId::new("testfile".to_string(), 0);
+        let blk1 = BlockId::new("testfile".to_string(), 1);
+        {
+            let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+            let tx0 = db.new_tx();
+            {
+                let tx0 = tx0.lock().unwrap();
+                tx0.pin(&blk1).unwrap();
+                tx0.set_int(&blk1, 8, 77, true).unwrap();
+            }
+            tx0.lock().unwrap().commit().unwrap();
+
+            let tx1 = db.new_tx();
+            let tx1 = tx1.lock().unwrap();
+            tx1.pin(&blk0).unwrap();
+            tx1.set_int(&blk0, 0, 11, true).unwrap();
+            Transaction::checkpoint(db.log_mgr(), db.buffer_manager()).unwrap();
+            tx1.set_int(&blk0, 4, 33, true).unwrap();
+
+            let tx2 = db.new_tx();
+            {
+                let tx2 = tx2.lock().unwrap();
+                tx2.pin(&blk1).unwrap();
+                tx2.set_int(&blk1, 0, 44, true).unwrap();
+            }
+            tx2.lock().unwrap().commit().unwrap();
+
+            // tx0's change is older than the checkpoint, so recovery must not
+            // look at it again: wipe it from disk behind the database's back
+            let fm = db.file_manager();
+            let mut p = Page::new_from_blocksize(400);
+            fm.read(&blk1, &mut p).unwrap();
+            assert_eq!(p.get_int(8).unwrap(), 77);
+            p.set_int(8, 0).unwrap();
+            fm.write(&blk1, &p).unwrap();
+        }
+
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        db.new_tx().lock().unwrap().recover().unwrap();
+        let fm = db.file_manager();
+        let mut p0 = Page::new_from_blocksize(400);
+        let mut p1 = Page::new_from_blocksize(400);
+        fm.read(&blk0, &mut p0).unwrap();
+        fm.read(&blk1, &mut p1).unwrap();
+        // tx1 was running at the checkpoint and never finished
+        assert_eq!(p0.get_int(0).unwrap(), 0);
+        assert_eq!(p0.get_int(4).unwrap(), 0);
+        assert_eq!(p1.get_int(0).unwrap(), 44);
+        assert_eq!(p1.get_int(8).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_checkpoint_releases_log_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        let open = || {
+            SimpleDB::builder(temp_dir.path())
+                .log_segment_blocks(1)
+                .build()
+                .unwrap()
+        };
+        {
+            let db = open();
+            for i in 0..50 {
+                let tx = db.new_tx();
+                let mut tx = tx.lock().unwrap();
+                tx.pin(&blk).unwrap();
+                tx.set_int(&blk, 0, i, true).unwrap();
+                tx.commit().unwrap();
+            }
+            let size = db.log_size().unwrap();
+            assert!(db.log_mgr().lock().unwrap().segment_count() > 2);
+
+            Transaction::checkpoint(db.log_mgr(), db.buffer_manager()).unwrap();
+            assert!(db.log_size().unwrap() < size);
+            assert!(db.log_mgr().lock().unwrap().segment_count() <= 2);
+
+            // committed after the checkpoint, so only the remaining segments have it
+            let tx = db.new_tx();
+            let mut tx = tx.lock().unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 0, 100, true).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let db = open();
+        let mut p = Page::new_from_blocksize(db.file_manager().block_size() as usize);
+        db.file_manager().read(&blk, &mut p).unwrap();
+        assert_eq!(p.get_int(0).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint_survives_recovery() {
+        let temp_dir = TempDir::new().unwrap();
+        let blk = BlockId::new("testfile".to_string(), 0);
+        {
+            let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+            let tx = db.new_tx();
+            let mut tx = tx.lock().unwrap();
+            tx.pin(&blk).unwrap();
+            tx.set_int(&blk, 0, 1, true).unwrap();
+            tx.savepoint(&"a".to_string()).unwrap();
+            tx.set_int(&blk, 0, 2, true).unwrap();
+            tx.set_string(&blk, 30, "bad".to_string(), true).unwrap();
+            tx.savepoint(&"b".to_string()).unwrap();
+            tx.rollback_to_savepoint(&"a".to_string()).unwrap();
+            assert_eq!(tx.get_int(&blk, 0).unwrap(), 1);
+            assert_eq!(tx.get_string(&blk, 30).unwrap(), "");
+            // rolling back to "a" forgot "b"
+            assert!(tx.rollback_to_savepoint(&"b".to_string()).is_err());
+            tx.release_savepoint(&"a".to_string()).unwrap();
+            assert!(tx.rollback_to_savepoint(&"a".to_string()).is_err());
+            tx.set_int(&blk, 4, 3, true).unwrap();
+            // only the log reaches disk
+            tx.commit().unwrap();
+        }
+
+        // redo repeats the undone changes and their undo, in order
+        let db = SimpleDB::new_with_sizes(temp_dir.path(), 400, 8);
+        db.new_tx().lock().unwrap().recover().unwrap();
+        let mut p = Page::new_from_blocksize(400);
+        db.file_manager().read(&blk, &mut p).unwrap();
+        assert_eq!(p.get_int(0).unwrap(), 1);
+        assert_eq!(p.get_string(30).unwrap(), "");
+        assert_eq!(p.get_int(4).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_tx_numbers_survive_restarts() {
+        let dir1 = TempDir::new().unwrap();
+        let dir2 = TempDir::new().unwrap();
+        let last = {
+            let db1 = SimpleDB::builder(dir1.path()).build().unwrap();
+            let db2 = SimpleDB::builder(dir2.path()).build().unwrap();
+            // each database numbers its own transactions
+            let first = db1.new_tx().lock().unwrap().tx_num();
+            assert_eq!(db2.new_tx().lock().unwrap().tx_num(), first);
+
+            for _ in 0..3 {
+                db1.new_tx().lock().unwrap().commit().unwrap();
+            }
+            Transaction::checkpoint(db1.log_mgr(), db1.buffer_manager()).unwrap();
+            let tx = db1.new_tx();
+            let mut tx = tx.lock().unwrap();
+            tx.commit().unwrap();
+            tx.tx_num()
+        };
+
+        let db1 = SimpleDB::builder(dir1.path()).build().unwrap();
+        assert!(db1.new_tx().lock().unwrap().tx_num() > last);
+        drop(db1);
+        // opened without recovery, so only the scan back to the checkpoint seeds it
+        let db1 = SimpleDB::new_with_sizes(dir1.path(), 400, 8);
+        assert!(db1.new_tx().lock().unwrap().tx_num() > last + 1);
+    }
+
     fn print_values(
         fm: &Arc<FileManager>,
         blk0: &BlockId,
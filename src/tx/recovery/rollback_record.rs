@@ -1,14 +1,14 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    constants::INTEGER_BYTES, file::page::Page, log::log_manager::LogManager,
+    constants::{INTEGER_BYTES, LONG_BYTES}, file::page::Page, log::log_manager::LogManager,
     tx::transaction::Transaction,
 };
 
 use super::log_record::{self, LogRecord};
 
 pub struct RollbackRecord {
-    txnum: i32,
+    txnum: i64,
 }
 
 impl LogRecord for RollbackRecord {
@@ -16,13 +16,17 @@ impl LogRecord for RollbackRecord {
         log_record::ROLLBACK
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
 
     fn undo(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
         Ok(())
     }
+
+    fn redo(&self, _lsn: i64, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 impl std::fmt::Display for RollbackRecord {
@@ -35,13 +39,13 @@ impl std::fmt::Display for RollbackRecord {
 impl RollbackRecord {
     pub fn new_from_page(p: Page) -> Result<Self, String> {
         let tpos = INTEGER_BYTES as usize;
-        let txnum = p.get_int(tpos)?;
+        let txnum = p.get_long(tpos)?;
         Ok(RollbackRecord { txnum })
     }
-    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, txnum: i32) -> Result<i32, String> {
-        let mut p = Page::new_from_blocksize(2 * INTEGER_BYTES as usize);
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, txnum: i64) -> Result<i64, String> {
+        let mut p = Page::new_from_blocksize((INTEGER_BYTES + LONG_BYTES) as usize);
         p.set_int(0, log_record::ROLLBACK)?;
-        p.set_int(INTEGER_BYTES as usize, txnum)?;
+        p.set_long(INTEGER_BYTES as usize, txnum)?;
         lm.lock().map_err(|_| "failed to get lock")?.append(
             p.contents()
                 .lock()
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::page::Page,
+    log::log_manager::LogManager,
+    tx::transaction::Transaction,
+};
+
+use super::log_record::{self, LogRecord};
+
+// Marks where a savepoint was set. Rolling back to it undoes the transaction's
+// records that follow.
+pub struct SavepointRecord {
+    txnum: i64,
+    name: String,
+}
+
+impl LogRecord for SavepointRecord {
+    fn op(&self) -> i32 {
+        log_record::SAVEPOINT
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+
+    fn undo(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn redo(&self, _lsn: i64, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SavepointRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<SAVEPOINT {} {}>", self.txnum, self.name)?;
+        Ok(())
+    }
+}
+
+impl SavepointRecord {
+    pub fn new_from_page(p: Page) -> Result<Self, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let txnum = p.get_long(tpos)?;
+        let name = p.get_string(tpos + LONG_BYTES as usize)?;
+        Ok(SavepointRecord { txnum, name })
+    }
+
+    pub fn write_to_log(lm: Arc<Mutex<LogManager>>, txnum: i64, name: &String) -> Result<i64, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let npos = tpos + LONG_BYTES as usize;
+
+        let mut p = Page::new_from_blocksize(npos + Page::max_length(name.len()));
+        p.set_int(0, log_record::SAVEPOINT)?;
+        p.set_long(tpos, txnum)?;
+        p.set_string(npos, name)?;
+        lm.lock().map_err(|_| "failed to get lock")?.append(
+            p.contents()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .to_vec(),
+        )
+    }
+}
@@ -1,8 +1,8 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    constants::INTEGER_BYTES,
-    file::{block_id::BlockId, page::Page},
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::{block_id::BlockId, file_manager::is_temp_file, page::Page},
     log::log_manager::LogManager,
     tx::transaction::Transaction,
 };
@@ -10,9 +10,10 @@ use crate::{
 use super::log_record::{self, LogRecord};
 
 pub struct SetIntRecord {
-    txnum: i32,
+    txnum: i64,
     offset: usize,
-    val: i32,
+    oldval: i32,
+    newval: i32,
     blk: BlockId,
 }
 
@@ -21,25 +22,45 @@ impl LogRecord for SetIntRecord {
         log_record::SETINT
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
 
     fn undo(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
         let tx = tx.lock().map_err(|_| "failed to get lock")?;
         tx.pin(&self.blk)?;
-        tx.set_int(&self.blk, self.offset, self.val, false)?;
+        tx.set_int(&self.blk, self.offset, self.oldval, false)?;
         tx.unpin(&self.blk)?;
         Ok(())
     }
+
+    fn compensate(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        let tx = tx.lock().map_err(|_| "failed to get lock")?;
+        tx.pin(&self.blk)?;
+        tx.set_int(&self.blk, self.offset, self.oldval, true)?;
+        tx.unpin(&self.blk)?;
+        Ok(())
+    }
+
+    fn redo(&self, lsn: i64, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        // temporary files are deleted at startup and not worth recreating
+        if is_temp_file(&self.blk.file_name()) {
+            return Ok(());
+        }
+        let tx = tx.lock().map_err(|_| "failed to get lock")?;
+        tx.pin(&self.blk)?;
+        let res = tx.redo_set_int(&self.blk, self.offset, self.newval, lsn);
+        tx.unpin(&self.blk)?;
+        res
+    }
 }
 
 impl std::fmt::Display for SetIntRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "<SETINT {} {} {} {} >",
-            self.txnum, self.blk, self.offset, self.val
+            "<SETINT {} {} {} {} {} >",
+            self.txnum, self.blk, self.offset, self.oldval, self.newval
         )?;
         Ok(())
     }
@@ -48,9 +69,9 @@ impl std::fmt::Display for SetIntRecord {
 impl SetIntRecord {
     pub fn new_from_page(p: Page) -> Result<Self, String> {
         let tpos = INTEGER_BYTES as usize;
-        let txnum = p.get_int(tpos)?;
+        let txnum = p.get_long(tpos)?;
 
-        let fpos = tpos + INTEGER_BYTES as usize;
+        let fpos = tpos + LONG_BYTES as usize;
         let filename = p.get_string(fpos)?;
 
         let bpos = fpos + Page::max_length(filename.len());
@@ -61,36 +82,42 @@ impl SetIntRecord {
         let offset = p.get_int(opos)? as usize;
 
         let vpos = opos + INTEGER_BYTES as usize;
-        let val = p.get_int(vpos)?;
+        let oldval = p.get_int(vpos)?;
+        let newval = p.get_int(vpos + INTEGER_BYTES as usize)?;
 
         Ok(Self {
             txnum,
             offset,
-            val,
+            oldval,
+            newval,
             blk,
         })
     }
 
     pub fn write_to_log(
         lm: Arc<Mutex<LogManager>>,
-        txnum: i32,
+        txnum: i64,
         blk: &BlockId,
         offset: i32,
-        val: i32,
-    ) -> Result<i32, String> {
+        oldval: i32,
+        newval: i32,
+    ) -> Result<i64, String> {
         let tpos = INTEGER_BYTES as usize;
-        let fpos = tpos + INTEGER_BYTES as usize;
+        let fpos = tpos + LONG_BYTES as usize;
         let bpos = fpos + Page::max_length(blk.file_name().len());
         let opos = bpos + INTEGER_BYTES as usize;
         let vpos = opos + INTEGER_BYTES as usize;
 
-        let mut p = Page::new_from_blocksize(vpos + INTEGER_BYTES as usize);
+        let npos = vpos + INTEGER_BYTES as usize;
+
+        let mut p = Page::new_from_blocksize(npos + INTEGER_BYTES as usize);
         p.set_int(0, log_record::SETINT)?;
-        p.set_int(tpos, txnum)?;
+        p.set_long(tpos, txnum)?;
         p.set_string(fpos, &blk.file_name())?;
         p.set_int(bpos, blk.number())?;
         p.set_int(opos, offset)?;
-        p.set_int(vpos, val)?;
+        p.set_int(vpos, oldval)?;
+        p.set_int(npos, newval)?;
         lm.lock().map_err(|_| "failed to get lock")?.append(
             p.contents()
                 .lock()
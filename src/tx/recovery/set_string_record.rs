@@ -1,8 +1,8 @@
 use std::sync::{Arc, Mutex};
 
 use crate::{
-    constants::INTEGER_BYTES,
-    file::{block_id::BlockId, page::Page},
+    constants::{INTEGER_BYTES, LONG_BYTES},
+    file::{block_id::BlockId, file_manager::is_temp_file, page::Page},
     log::log_manager::LogManager,
     tx::transaction::Transaction,
 };
@@ -10,9 +10,10 @@ use crate::{
 use super::log_record::{self, LogRecord};
 
 pub struct SetStringRecord {
-    txnum: i32,
+    txnum: i64,
     offset: usize,
-    val: String,
+    oldval: String,
+    newval: String,
     blk: BlockId,
 }
 
@@ -21,25 +22,45 @@ impl LogRecord for SetStringRecord {
         log_record::SETSTRING
     }
 
-    fn tx_number(&self) -> i32 {
+    fn tx_number(&self) -> i64 {
         self.txnum
     }
 
     fn undo(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
         let tx = tx.lock().map_err(|_| "failed to get lock")?;
         tx.pin(&self.blk)?;
-        tx.set_string(&self.blk, self.offset, self.val.clone(), false)?;
+        tx.set_string(&self.blk, self.offset, self.oldval.clone(), false)?;
         tx.unpin(&self.blk)?;
         Ok(())
     }
+
+    fn compensate(&self, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        let tx = tx.lock().map_err(|_| "failed to get lock")?;
+        tx.pin(&self.blk)?;
+        tx.set_string(&self.blk, self.offset, self.oldval.clone(), true)?;
+        tx.unpin(&self.blk)?;
+        Ok(())
+    }
+
+    fn redo(&self, lsn: i64, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        // temporary files are deleted at startup and not worth recreating
+        if is_temp_file(&self.blk.file_name()) {
+            return Ok(());
+        }
+        let tx = tx.lock().map_err(|_| "failed to get lock")?;
+        tx.pin(&self.blk)?;
+        let res = tx.redo_set_string(&self.blk, self.offset, self.newval.clone(), lsn);
+        tx.unpin(&self.blk)?;
+        res
+    }
 }
 
 impl std::fmt::Display for SetStringRecord {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "<SETSTRING {} {} {} {} >",
-            self.txnum, self.blk, self.offset, self.val
+            "<SETSTRING {} {} {} {} {} >",
+            self.txnum, self.blk, self.offset, self.oldval, self.newval
         )?;
         Ok(())
     }
@@ -48,9 +69,9 @@ impl std::fmt::Display for SetStringRecord {
 impl SetStringRecord {
     pub fn new_from_page(p: Page) -> Result<Self, String> {
         let tpos = INTEGER_BYTES as usize;
-        let txnum = p.get_int(tpos)?;
+        let txnum = p.get_long(tpos)?;
 
-        let fpos = tpos + INTEGER_BYTES as usize;
+        let fpos = tpos + LONG_BYTES as usize;
         let filename = p.get_string(fpos)?;
 
         let bpos = fpos + Page::max_length(filename.len());
@@ -61,37 +82,42 @@ impl SetStringRecord {
         let offset = p.get_int(opos)? as usize;
 
         let vpos = opos + INTEGER_BYTES as usize;
-        let val = p.get_string(vpos)?;
+        let oldval = p.get_string(vpos)?;
+        let newval = p.get_string(vpos + Page::max_length(oldval.len()))?;
 
         Ok(Self {
             txnum,
             offset,
-            val,
+            oldval,
+            newval,
             blk,
         })
     }
 
     pub fn write_to_log(
         lm: Arc<Mutex<LogManager>>,
-        txnum: i32,
+        txnum: i64,
         blk: &BlockId,
         offset: i32,
-        val: String,
-    ) -> Result<i32, String> {
+        oldval: String,
+        newval: String,
+    ) -> Result<i64, String> {
         let tpos = INTEGER_BYTES as usize;
-        let fpos = tpos + INTEGER_BYTES as usize;
+        let fpos = tpos + LONG_BYTES as usize;
         let bpos = fpos + Page::max_length(blk.file_name().len());
         let opos = bpos + INTEGER_BYTES as usize;
         let vpos = opos + INTEGER_BYTES as usize;
-        let reclen = vpos + Page::max_length(val.len());
+        let npos = vpos + Page::max_length(oldval.len());
+        let reclen = npos + Page::max_length(newval.len());
 
         let mut p = Page::new_from_blocksize(reclen);
         p.set_int(0, log_record::SETSTRING)?;
-        p.set_int(tpos, txnum)?;
+        p.set_long(tpos, txnum)?;
         p.set_string(fpos, &blk.file_name())?;
         p.set_int(bpos, blk.number())?;
         p.set_int(opos, offset)?;
-        p.set_string(vpos, &val)?;
+        p.set_string(vpos, &oldval)?;
+        p.set_string(npos, &newval)?;
         lm.lock().map_err(|_| "failed to get lock")?.append(
             p.contents()
                 .lock()
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    constants::{INTEGER_BYTES, LONG_BYTES}, file::page::Page, log::log_manager::LogManager,
+    tx::transaction::Transaction,
+};
+
+use super::log_record::{self, LogRecord};
+
+// Written after the COMMIT of a transaction that shortened a file, before the
+// file is truncated, so that redo does not bring the removed blocks back.
+pub struct TruncateRecord {
+    txnum: i64,
+    filename: String,
+    numblocks: i32,
+}
+
+impl LogRecord for TruncateRecord {
+    fn op(&self) -> i32 {
+        log_record::TRUNCATE
+    }
+
+    fn tx_number(&self) -> i64 {
+        self.txnum
+    }
+
+    // the truncation only happens once the transaction has committed
+    fn undo(&self, _tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn redo(&self, _lsn: i64, tx: Arc<Mutex<Transaction>>) -> Result<(), String> {
+        tx.lock()
+            .map_err(|_| "failed to get lock")?
+            .redo_truncate(&self.filename, self.numblocks)
+    }
+}
+
+impl std::fmt::Display for TruncateRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<TRUNCATE {} {} {}>", self.txnum, self.filename, self.numblocks)?;
+        Ok(())
+    }
+}
+
+impl TruncateRecord {
+    pub fn new_from_page(p: Page) -> Result<Self, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let txnum = p.get_long(tpos)?;
+
+        let fpos = tpos + LONG_BYTES as usize;
+        let filename = p.get_string(fpos)?;
+
+        let npos = fpos + Page::max_length(filename.len());
+        let numblocks = p.get_int(npos)?;
+
+        Ok(TruncateRecord {
+            txnum,
+            filename,
+            numblocks,
+        })
+    }
+
+    pub fn write_to_log(
+        lm: Arc<Mutex<LogManager>>,
+        txnum: i64,
+        filename: &String,
+        numblocks: i32,
+    ) -> Result<i64, String> {
+        let tpos = INTEGER_BYTES as usize;
+        let fpos = tpos + LONG_BYTES as usize;
+        let npos = fpos + Page::max_length(filename.len());
+
+        let mut p = Page::new_from_blocksize(npos + INTEGER_BYTES as usize);
+        p.set_int(0, log_record::TRUNCATE)?;
+        p.set_long(tpos, txnum)?;
+        p.set_string(fpos, filename)?;
+        p.set_int(npos, numblocks)?;
+        lm.lock().map_err(|_| "failed to get lock")?.append(
+            p.contents()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .to_vec(),
+        )
+    }
+}
@@ -1,45 +1,108 @@
-use std::sync::{Arc, Mutex, atomic::{AtomicI32, Ordering}};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
 
 use crate::{
-    buffer::buffer_manager::BufferManager,
-    file::{block_id::BlockId, file_manager::FileManager},
+    buffer::{buffer_manager::BufferManager, latch_table::PageLatch, replacement_policy::AccessHint},
+    file::{block_id::BlockId, file_manager::FileManager, page::Page},
     log::log_manager::LogManager,
+    query::constant::Constant,
+    record::rid::RID,
+    server::log_level::LogLevel,
 };
 
 use super::{
     buffer_list::BufferList,
-    concurrency::{concurrency_manager::ConcurrencyManager, lock_table::LockTable},
+    concurrency::{
+        concurrency_manager::{ConcurrencyManager, ReadLocks},
+        isolation_level::IsolationLevel,
+        lock_mode::LockMode,
+        lock_table::LockTable,
+        version_store::{DeferredTruncation, OldValue, VersionStore},
+    },
+    index_entry::IndexEntry,
     recovery::recovery_manager::RecoveryManager,
 };
 
 const END_OF_FILE: i32 = -1;
-static NEXT_TX_NUM: AtomicI32 = AtomicI32::new(0);
+
+// (name, LSN of its record, pending truncations and retired entries then)
+type Savepoint = (String, i64, usize, usize);
 
 #[derive(Debug, Clone)]
 pub struct Transaction {
     recovery_manager: Option<Arc<Mutex<RecoveryManager>>>,
     concurrent_manager: Arc<Mutex<ConcurrencyManager>>,
-    buffer_manager: Arc<Mutex<BufferManager>>,
+    buffer_manager: Arc<BufferManager>,
     file_manager: Arc<FileManager>,
-    txnum: i32,
+    log_manager: Arc<Mutex<LogManager>>,
+    lock_table: Arc<LockTable>,
+    txnum: i64,
     mybuffers: Arc<Mutex<BufferList>>,
+    pending_truncations: Arc<Mutex<Vec<(String, i32)>>>,
+    // index entries of the records the transaction deleted
+    retired_entries: Arc<Mutex<Vec<Box<dyn IndexEntry>>>>,
+    // oldest first
+    savepoints: Arc<Mutex<Vec<Savepoint>>>,
+    version_store: Arc<VersionStore>,
+    // the commit sequence the transaction's reads see up to
+    snapshot: Arc<AtomicU64>,
+    // set once the transaction pins a block; its isolation level is fixed
+    // from then on
+    started: Arc<AtomicBool>,
+    locking_reads: Arc<AtomicBool>,
+    latched: Arc<AtomicBool>,
+    // a system transaction changes index pages for a user transaction and
+    // commits as soon as the change is made
+    system: bool,
+    // the latches on the pages a system transaction changed
+    held_latches: Arc<Mutex<Vec<PageLatch>>>,
 }
 
 impl Transaction {
     pub fn new_from_managers(
         fm: Arc<FileManager>,
         lm: Arc<Mutex<LogManager>>,
-        bm: Arc<Mutex<BufferManager>>,
-        lt: Arc<Mutex<LockTable>>,
+        bm: Arc<BufferManager>,
+        lt: Arc<LockTable>,
+        vs: Arc<VersionStore>,
+    ) -> Result<Self, String> {
+        Self::new_with_managers(fm, lm, bm, lt, vs, false)
+    }
+
+    fn new_with_managers(
+        fm: Arc<FileManager>,
+        lm: Arc<Mutex<LogManager>>,
+        bm: Arc<BufferManager>,
+        lt: Arc<LockTable>,
+        vs: Arc<VersionStore>,
+        system: bool,
     ) -> Result<Self, String> {
-        let txnum = Self::next_tx_number();
+        let txnum = lm.lock().map_err(|_| "failed to get lock")?.allocate_tx_number();
+        let snapshot = match system {
+            true => 0,
+            false => vs.begin(txnum)?,
+        };
         let mut tran = Transaction {
             recovery_manager: None,
-            concurrent_manager: Arc::new(Mutex::new(ConcurrencyManager::new(lt))),
+            concurrent_manager: Arc::new(Mutex::new(ConcurrencyManager::new(txnum, lt.clone()))),
             buffer_manager: bm.clone(),
             file_manager: fm,
+            log_manager: lm.clone(),
+            lock_table: lt,
             txnum: txnum,
             mybuffers: Arc::new(Mutex::new(BufferList::new_from_buffer_manager(bm.clone()))),
+            pending_truncations: Arc::new(Mutex::new(Vec::new())),
+            retired_entries: Arc::new(Mutex::new(Vec::new())),
+            savepoints: Arc::new(Mutex::new(Vec::new())),
+            version_store: vs,
+            snapshot: Arc::new(AtomicU64::new(snapshot)),
+            started: Arc::new(AtomicBool::new(false)),
+            locking_reads: Arc::new(AtomicBool::new(false)),
+            latched: Arc::new(AtomicBool::new(system)),
+            system,
+            held_latches: Arc::new(Mutex::new(Vec::new())),
         };
         let recovery_manager = Arc::new(Mutex::new(RecoveryManager::new_from_managers(
             Arc::new(Mutex::new(tran.clone())),
@@ -51,27 +114,133 @@ impl Transaction {
         Ok(tran)
     }
 
+    // Runs `op` in a system transaction of its own, which commits if it
+    // succeeds and rolls back if not. Changes to the structure of an index are
+    // made this way, so they stay whatever becomes of the transaction that
+    // asked for them, and other transactions can build on them at once.
+    pub fn system_op<T>(
+        &self,
+        op: impl FnOnce(Arc<Mutex<Transaction>>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let sys = Arc::new(Mutex::new(Self::new_with_managers(
+            self.file_manager.clone(),
+            self.log_manager.clone(),
+            self.buffer_manager.clone(),
+            self.lock_table.clone(),
+            self.version_store.clone(),
+            true,
+        )?));
+        let ret = op(sys.clone());
+        let mut sys = sys.lock().map_err(|_| "failed to get lock")?;
+        match ret {
+            Ok(_) => sys.commit()?,
+            Err(_) => sys.rollback()?,
+        }
+        ret
+    }
+
     pub fn commit(&mut self) -> Result<(), String> {
+        if self.system {
+            return self.commit_system();
+        }
         self.recovery_manager
             .as_ref()
             .unwrap()
             .lock()
             .map_err(|_| "failed to get lock")?
             .commit()?;
+        let seq = self.version_store.commit(self.txnum)?;
+        let retired = std::mem::take(
+            &mut *self
+                .retired_entries
+                .lock()
+                .map_err(|_| "failed to get lock")?,
+        );
+        self.version_store.retire(seq, retired)?;
 
-        println!("transaction {} commited", self.txnum);
+        if self.file_manager.log_level().enabled(LogLevel::Debug) {
+            println!("transaction {} commited", self.txnum);
+        }
+
+        // buffers must be unpinned before truncation so that none keeps a discarded block
+        self.mybuffers
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .unpin_all()?;
+
+        // Snapshots taken before the commit may still read the blocks, so the
+        // truncations wait for them. Until they are applied the transaction
+        // stays running for checkpoints.
+        let deferred = self.defer_truncations(seq)?;
+        self.apply_truncations()?;
+        if !deferred {
+            self.recovery_manager
+                .as_ref()
+                .unwrap()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .end()?;
+        }
 
         self.concurrent_manager
             .lock()
             .map_err(|_| "failed to get lock")?
             .release()?;
 
+        // the transaction is durable by now, so a failure to clean up after
+        // older ones does not fail its commit
+        if let Err(e) = self.clean_up_expired() {
+            if self.file_manager.log_level().enabled(LogLevel::Info) {
+                eprintln!("warning: transaction {} left cleanup for later: {}", self.txnum, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // The index entries of deleted records and the deferred truncations go once
+    // no snapshot needs them, which may be only now that this transaction
+    // ended. Those that fail are kept for the next commit to try again.
+    fn clean_up_expired(&self) -> Result<(), String> {
+        let mut failed = None;
+        for (seq, entry) in self.version_store.take_expired()? {
+            if let Err(e) = entry.remove(self) {
+                self.version_store.retire(seq, vec![entry])?;
+                failed.get_or_insert(e);
+            }
+        }
+        for (seq, truncation) in self.version_store.take_expired_truncations()? {
+            if let Err(e) = self.apply_deferred_truncation(seq, truncation.clone()) {
+                self.version_store.defer_truncations(seq, vec![truncation])?;
+                failed.get_or_insert(e);
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    }
+
+    // The COMMIT of a system transaction is not forced; it reaches disk ahead
+    // of the commit of the user transaction it worked for, or of any later
+    // one. The pages it changed stay latched until the COMMIT is logged, so
+    // that undoing its changes after a crash never undoes someone else's.
+    fn commit_system(&mut self) -> Result<(), String> {
+        {
+            let mut rm = self
+                .recovery_manager
+                .as_ref()
+                .unwrap()
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            rm.commit_unforced()?;
+            rm.end()?;
+        }
+        self.held_latches
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clear();
         self.mybuffers
             .lock()
             .map_err(|_| "failed to get lock")?
-            .unpin_all()?;
-
-        Ok(())
+            .unpin_all()
     }
 
     pub fn rollback(&mut self) -> Result<(), String> {
@@ -81,8 +250,26 @@ impl Transaction {
             .lock()
             .map_err(|_| "failed to get lock")?
             .rollback()?;
+        self.held_latches
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clear();
+        if !self.system {
+            self.version_store.rollback(self.txnum)?;
+        }
+
+        if self.file_manager.log_level().enabled(LogLevel::Debug) {
+            println!("transaction {} rolled back", self.txnum);
+        }
 
-        println!("transaction {} rolled back", self.txnum);
+        self.pending_truncations
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clear();
+        self.retired_entries
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .clear();
 
         self.concurrent_manager
             .lock()
@@ -97,76 +284,446 @@ impl Transaction {
         Ok(())
     }
 
-    pub fn recover(&self) -> Result<(), String> {
-        self.buffer_manager
+    // Reads normally lock and see what the isolation level says. Locking reads
+    // see the latest committed data under shared locks held until the end of
+    // the transaction, whatever the level, for code that changes what it
+    // reads, such as table compaction. Returns the previous setting.
+    pub fn set_locking_reads(&self, on: bool) -> bool {
+        self.locking_reads.swap(on, Ordering::SeqCst)
+    }
+
+    // Latched reads and writes take no locks and see pages as they are now,
+    // for structures that latch the pages they use themselves, such as B-tree
+    // indexes. Returns the previous setting.
+    pub fn set_latched(&self, on: bool) -> bool {
+        self.latched.swap(on, Ordering::SeqCst)
+    }
+
+    fn snapshot(&self) -> u64 {
+        self.snapshot.load(Ordering::SeqCst)
+    }
+
+    fn is_latched(&self) -> bool {
+        self.latched.load(Ordering::SeqCst)
+    }
+
+    // Latches a page until the latch is dropped; exclusively to change it.
+    pub fn latch(&self, blk: &BlockId, exclusive: bool) -> Result<PageLatch, String> {
+        self.buffer_manager.latch(blk, exclusive)
+    }
+
+    // Where the last search of a free space map stopped, shared by all
+    // transactions.
+    pub fn free_space_hint(&self, filename: &str) -> i32 {
+        self.buffer_manager.free_space_hint(filename)
+    }
+
+    pub fn set_free_space_hint(&self, filename: &str, blknum: i32) -> Result<(), String> {
+        self.buffer_manager.set_free_space_hint(filename, blknum)
+    }
+
+    // Keeps the latch on a page the transaction changed until it ends.
+    pub fn hold_latch(&self, latch: PageLatch) -> Result<(), String> {
+        self.held_latches
             .lock()
             .map_err(|_| "failed to get lock")?
-            .flush_all(self.txnum)?;
+            .push(latch);
+        Ok(())
+    }
+
+    // Logs an entry about to be added to an index for this transaction, so
+    // that a rollback removes it again whatever else the index went through.
+    pub fn log_index_insert(&self, entry: &dyn IndexEntry) -> Result<(), String> {
         self.recovery_manager
             .as_ref()
             .unwrap()
             .lock()
             .map_err(|_| "failed to get lock")?
-            .recover()?;
-        Ok(())
+            .log_index_insert(entry)
     }
 
-    pub fn pin(&self, blk: &BlockId) -> Result<(), String> {
-        self.mybuffers
+    // Queues the removal of the entry of a record deleted from an index. It is
+    // removed after the transaction commits, once no snapshot still sees the
+    // record; in the meantime scans through the index skip it.
+    pub fn retire_index_entry(&self, entry: Box<dyn IndexEntry>) -> Result<(), String> {
+        self.retired_entries
             .lock()
             .map_err(|_| "failed to get lock")?
-            .pin(blk)?;
+            .push(entry);
         Ok(())
     }
 
-    pub fn unpin(&self, blk: &BlockId) -> Result<(), String> {
-        self.mybuffers
+    // Whether the entry is already queued for removal, by this transaction or
+    // by a commit that older snapshots hold back.
+    pub fn is_retired(&self, idxname: &String, dataval: &Constant, datarid: &RID) -> Result<bool, String> {
+        let queued = self
+            .retired_entries
             .lock()
             .map_err(|_| "failed to get lock")?
-            .unpin(blk)?;
+            .iter()
+            .any(|e| e.is(idxname, dataval, datarid));
+        Ok(queued || self.version_store.is_retired(idxname, dataval, datarid)?)
+    }
+
+    pub fn isolation_level(&self) -> Result<IsolationLevel, String> {
+        Ok(self
+            .concurrent_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .isolation_level())
+    }
+
+    // Only repeatable read keeps a snapshot for the transaction; at the other
+    // levels the before-images it would need are not kept for it. The level
+    // can only change before the transaction's first statement.
+    pub fn set_isolation_level(&mut self, level: IsolationLevel) -> Result<(), String> {
+        let mut cm = self
+            .concurrent_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?;
+        if level == cm.isolation_level() {
+            return Ok(());
+        }
+        if self.started.load(Ordering::SeqCst) {
+            return Err(format!(
+                "transaction {} cannot change its isolation level after its first statement",
+                self.txnum
+            ));
+        }
+        if level == IsolationLevel::RepeatableRead {
+            self.snapshot
+                .store(self.version_store.begin(self.txnum)?, Ordering::SeqCst);
+        } else if cm.isolation_level() == IsolationLevel::RepeatableRead {
+            self.version_store.end_snapshot(self.txnum)?;
+        }
+        cm.set_isolation_level(level);
         Ok(())
     }
 
-    pub fn tx_num(&self) -> i32 {
-        self.txnum
+    // Takes the locks a read of the block needs, and tells whether the read
+    // sees the snapshot. The locks returned are released after the read.
+    fn begin_read(&self, blk: &BlockId) -> Result<(ReadLocks, bool), String> {
+        if self.is_latched() {
+            return Ok((Vec::new(), false));
+        }
+        let cm = self
+            .concurrent_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?;
+        if self.locking_reads.load(Ordering::SeqCst) {
+            cm.s_lock(blk)?;
+            return Ok((Vec::new(), false));
+        }
+        let snapshot = cm.isolation_level() == IsolationLevel::RepeatableRead;
+        Ok((cm.read_lock(blk)?, snapshot))
     }
 
-    pub fn get_int(&self, blk: &BlockId, offset: usize) -> Result<i32, String> {
+    fn end_read(&self, taken: ReadLocks) -> Result<(), String> {
+        if taken.is_empty() {
+            return Ok(());
+        }
         self.concurrent_manager
             .lock()
             .map_err(|_| "failed to get lock")?
-            .s_lock(blk)?;
+            .end_read(taken)
+    }
 
-        let ret = self
-            .mybuffers
+    // Locks a whole file until the transaction ends, covering the locks on its
+    // blocks.
+    pub fn lock_file(&self, filename: &str, mode: LockMode) -> Result<(), String> {
+        self.concurrent_manager
             .lock()
             .map_err(|_| "failed to get lock")?
-            .get_buffer(blk)
-            .ok_or("you access to a buffer that does not exist")?
+            .lock_file(filename, mode)
+    }
+
+    // A scan through a whole file under locks held to the end locks the file
+    // once rather than each block.
+    pub fn lock_for_scan(&self, filename: &str) -> Result<(), String> {
+        if self.locking_reads.load(Ordering::SeqCst)
+            || self.isolation_level()? == IsolationLevel::Serializable
+        {
+            self.lock_file(filename, LockMode::Shared)?;
+        }
+        Ok(())
+    }
+
+    // A lookup in an index under serializable locks the key it looks up until
+    // the transaction ends, so a second lookup cannot find entries inserted with
+    // the key in between, wherever in the index they were put.
+    pub fn lock_for_search(&self, filename: &str, key: &Constant) -> Result<(), String> {
+        if !self.locking_reads.load(Ordering::SeqCst)
+            && self.isolation_level()? == IsolationLevel::Serializable
+        {
+            self.lock_key(filename, key, LockMode::Shared)?;
+        }
+        Ok(())
+    }
+
+    pub fn lock_key(&self, filename: &str, key: &Constant, mode: LockMode) -> Result<(), String> {
+        self.concurrent_manager
             .lock()
             .map_err(|_| "failed to get lock")?
-            .contents()
-            .get_int(offset)?;
+            .lock_key(filename, key, mode)
+    }
 
-        Ok(ret)
+    // Locks an empty slot, which takes up the bytes of the block between
+    // `start` and `end`, for a record to be inserted in it. Returns false, and
+    // gives the lock back, if the slot is not free for this transaction after
+    // all: under repeatable read, the snapshot still sees a record deleted from
+    // it since.
+    pub fn lock_free_slot(&self, blk: &BlockId, slot: i32, start: usize, end: usize) -> Result<bool, String> {
+        let (level, held) = {
+            let cm = self
+                .concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            let held = cm.holds_record(blk, slot)?;
+            cm.x_lock_record(blk, slot, start, end)?;
+            (cm.isolation_level(), held)
+        };
+        let free = level != IsolationLevel::RepeatableRead
+            || !self
+                .version_store
+                .conflicts(self.txnum, self.snapshot(), blk, start, end)?;
+        if !free && !held {
+            self.unlock_free_slot(blk, slot, start, end)?;
+        }
+        Ok(free)
     }
 
-    pub fn get_string(&self, blk: &BlockId, offset: usize) -> Result<String, String> {
+    // Gives back the lock on a slot taken by lock_free_slot, for a slot that
+    // turned out not to be empty.
+    pub fn unlock_free_slot(&self, blk: &BlockId, slot: i32, start: usize, end: usize) -> Result<(), String> {
         self.concurrent_manager
             .lock()
             .map_err(|_| "failed to get lock")?
-            .s_lock(blk)?;
+            .unlock_record(blk, slot, start, end)
+    }
 
-        let ret = self
-            .mybuffers
+    // Locks the record in `slot`, which takes up the bytes of the block between
+    // `start` and `end`, for writing. Under repeatable read, fails if a
+    // transaction this one cannot see changed the record: the first updater
+    // wins.
+    pub fn x_lock_record(&self, blk: &BlockId, slot: i32, start: usize, end: usize) -> Result<(), String> {
+        let level = {
+            let cm = self
+                .concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            cm.x_lock_record(blk, slot, start, end)?;
+            cm.isolation_level()
+        };
+        if level == IsolationLevel::RepeatableRead
+            && self
+                .version_store
+                .conflicts(self.txnum, self.snapshot(), blk, start, end)?
+        {
+            return Err(format!(
+                "could not serialize access due to a concurrent update of {}",
+                blk
+            ));
+        }
+        Ok(())
+    }
+
+    // A later savepoint may reuse a name; the newest one with it is meant.
+    pub fn savepoint(&self, name: &String) -> Result<(), String> {
+        let lsn = self
+            .recovery_manager
+            .as_ref()
+            .unwrap()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .savepoint(name)?;
+        let (pending, retired) = self.pending_lengths()?;
+        self.savepoints
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .push((name.clone(), lsn, pending, retired));
+        Ok(())
+    }
+
+    // Undoes the changes made since the savepoint, newest first, and forgets the
+    // savepoints set after it. The savepoint itself stays, and so do the locks.
+    pub fn rollback_to_savepoint(&self, name: &String) -> Result<(), String> {
+        let mut savepoints = self.savepoints.lock().map_err(|_| "failed to get lock")?;
+        let pos = savepoints
+            .iter()
+            .rposition(|(n, _, _, _)| n == name)
+            .ok_or(format!("no savepoint named {}", name))?;
+        let (_, lsn, pending, retired) = savepoints[pos].clone();
+        savepoints.truncate(pos + 1);
+        drop(savepoints);
+        self.undo_since(lsn, pending, retired)?;
+
+        if self.file_manager.log_level().enabled(LogLevel::Debug) {
+            println!("transaction {} rolled back to savepoint {}", self.txnum, name);
+        }
+        Ok(())
+    }
+
+    // Fails if the transaction was wounded to prevent a deadlock, so that it
+    // rolls back before the older transaction waiting for it times out.
+    pub fn check_wounded(&self) -> Result<(), String> {
+        self.lock_table
+            .check_wounded(self.txnum)
+            .map_err(|e| e.to_string())
+    }
+
+    // An implicit savepoint taken before each statement, so that a statement that
+    // fails halfway can be undone on its own. Nothing is logged for it.
+    pub fn begin_statement(&self) -> Result<(i64, usize, usize), String> {
+        self.check_wounded()?;
+        let lsn = self
+            .recovery_manager
+            .as_ref()
+            .unwrap()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .latest_lsn()?;
+        let (pending, retired) = self.pending_lengths()?;
+        Ok((lsn, pending, retired))
+    }
+
+    pub fn rollback_statement(&self, mark: (i64, usize, usize)) -> Result<(), String> {
+        self.undo_since(mark.0, mark.1, mark.2)?;
+
+        if self.file_manager.log_level().enabled(LogLevel::Debug) {
+            println!("transaction {} rolled back a failed statement", self.txnum);
+        }
+        Ok(())
+    }
+
+    // Forgets the savepoint and those set after it; the changes stay.
+    pub fn release_savepoint(&self, name: &String) -> Result<(), String> {
+        let mut savepoints = self.savepoints.lock().map_err(|_| "failed to get lock")?;
+        let pos = savepoints
+            .iter()
+            .rposition(|(n, _, _, _)| n == name)
+            .ok_or(format!("no savepoint named {}", name))?;
+        savepoints.truncate(pos);
+        Ok(())
+    }
+
+    // Writes a checkpoint without waiting for running transactions to finish;
+    // returns the LSN of its record.
+    pub fn checkpoint(lm: Arc<Mutex<LogManager>>, bm: Arc<BufferManager>) -> Result<i64, String> {
+        RecoveryManager::checkpoint(lm, bm)
+    }
+
+    // Resumes transaction numbering after the highest number in the log.
+    pub fn restore_tx_numbers(lm: Arc<Mutex<LogManager>>) -> Result<(), String> {
+        RecoveryManager::restore_tx_numbers(lm)
+    }
+
+    // The CHECKPOINT statement; this transaction is listed as running.
+    pub fn take_checkpoint(&self) -> Result<i64, String> {
+        self.recovery_manager
+            .as_ref()
+            .unwrap()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .take_checkpoint()
+    }
+
+    pub fn recover(&self) -> Result<(), String> {
+        self.buffer_manager
+            .flush_all(self.txnum)?;
+        self.recovery_manager
+            .as_ref()
+            .unwrap()
             .lock()
             .map_err(|_| "failed to get lock")?
+            .recover()?;
+        Ok(())
+    }
+
+    pub fn pin(&self, blk: &BlockId) -> Result<(), String> {
+        self.pin_with_hint(blk, AccessHint::Normal)
+    }
+
+    // Lets the buffer manager know the block is read as part of a sequential scan.
+    pub fn pin_with_hint(&self, blk: &BlockId, hint: AccessHint) -> Result<(), String> {
+        self.started.store(true, Ordering::SeqCst);
+        self.mybuffers
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .pin_with_hint(blk, hint)?;
+        Ok(())
+    }
+
+    // Reads up to `count` blocks from `first` into the pool ahead of their pins.
+    pub fn read_ahead(&self, first: &BlockId, count: i32) -> Result<i32, String> {
+        self.buffer_manager.read_ahead(first, count)
+    }
+
+    pub fn unpin(&self, blk: &BlockId) -> Result<(), String> {
+        self.mybuffers
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .unpin(blk)?;
+        Ok(())
+    }
+
+    pub fn tx_num(&self) -> i64 {
+        self.txnum
+    }
+
+    pub fn get_int(&self, blk: &BlockId, offset: usize) -> Result<i32, String> {
+        let (taken, snapshot) = self.begin_read(blk)?;
+        let ret = {
+            let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
+            let mut buff = binding
+                .get_buffer(blk)
+                .ok_or("you access to a buffer that does not exist")?
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            let current = buff.contents().get_int(offset)?;
+            match snapshot {
+                true => match self.version_store.read(self.txnum, self.snapshot(), blk, offset)? {
+                    Some(OldValue::Int(old)) => old,
+                    _ => current,
+                },
+                false => current,
+            }
+        };
+        self.end_read(taken)?;
+
+        Ok(ret)
+    }
+
+    // Reads what is in the block now, without a lock, for a caller that locks
+    // what it finds before relying on it.
+    pub fn peek_int(&self, blk: &BlockId, offset: usize) -> Result<i32, String> {
+        let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
+        let mut buff = binding
             .get_buffer(blk)
             .ok_or("you access to a buffer that does not exist")?
             .lock()
-            .map_err(|_| "failed to get lock")?
-            .contents()
-            .get_string(offset)?;
+            .map_err(|_| "failed to get lock")?;
+        buff.contents().get_int(offset)
+    }
+
+    pub fn get_string(&self, blk: &BlockId, offset: usize) -> Result<String, String> {
+        let (taken, snapshot) = self.begin_read(blk)?;
+        let ret = {
+            let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
+            let mut buff = binding
+                .get_buffer(blk)
+                .ok_or("you access to a buffer that does not exist")?
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            let current = buff.contents().get_string(offset)?;
+            match snapshot {
+                true => match self.version_store.read(self.txnum, self.snapshot(), blk, offset)? {
+                    Some(OldValue::Str(old)) => old,
+                    _ => current,
+                },
+                false => current,
+            }
+        };
+        self.end_read(taken)?;
 
         Ok(ret)
     }
@@ -178,31 +735,41 @@ impl Transaction {
         val: i32,
         ok_to_log: bool,
     ) -> Result<(), String> {
-        self.concurrent_manager
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .x_lock(blk)?;
+        let latched = self.is_latched();
+        if !latched {
+            self.concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .write_lock(blk, offset)?;
+        }
 
         let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
         let buff_arc = binding
             .get_buffer(blk)
             .ok_or("you access to a buffer that does not exist")?;
 
+        // the latch is held from logging the change until it is made, so a
+        // checkpoint that flushes the page sees every change logged before it
+        let mut buff = buff_arc.lock().map_err(|_| "failed to get lock")?;
         let lsn = if ok_to_log {
             self.recovery_manager
                 .as_ref()
                 .unwrap()
                 .lock()
                 .map_err(|_| "failed to get lock")?
-                .set_int(buff_arc.clone(), offset as i32)?
+                .set_int(&mut buff, offset as i32, val)?
         } else {
             -1
         };
+        let before = buff.contents().get_int(offset)?;
 
-        let mut buff = buff_arc.lock().map_err(|_| "failed to get lock")?;
         let p = buff.contents();
         p.set_int(offset, val)?;
         buff.set_modified(self.txnum, lsn);
+        if ok_to_log && !latched {
+            self.version_store
+                .record(self.txnum, blk, offset, OldValue::Int(before))?;
+        }
         Ok(())
     }
 
@@ -213,58 +780,134 @@ impl Transaction {
         val: String,
         ok_to_log: bool,
     ) -> Result<(), String> {
-        self.concurrent_manager
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .x_lock(blk)?;
+        let latched = self.is_latched();
+        if !latched {
+            self.concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .write_lock(blk, offset)?;
+        }
 
         let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
         let buff_arc = binding
             .get_buffer(blk)
             .ok_or("you access to a buffer that does not exist")?;
 
+        let mut buff = buff_arc.lock().map_err(|_| "failed to get lock")?;
         let lsn = if ok_to_log {
             self.recovery_manager
                 .as_ref()
                 .unwrap()
                 .lock()
                 .map_err(|_| "failed to get lock")?
-                .set_string(buff_arc.clone(), offset as i32)?
+                .set_string(&mut buff, offset as i32, val.clone())?
         } else {
             -1
         };
+        let before = buff.contents().get_string(offset)?;
 
-        let mut buff = buff_arc.lock().map_err(|_| "failed to get lock")?;
         let p = buff.contents();
         p.set_string(offset, &val)?;
         buff.set_modified(self.txnum, lsn);
+        if ok_to_log && !latched {
+            self.version_store
+                .record(self.txnum, blk, offset, OldValue::Str(before))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn redo_set_int(
+        &self,
+        blk: &BlockId,
+        offset: usize,
+        val: i32,
+        lsn: i64,
+    ) -> Result<(), String> {
+        self.redo(blk, lsn, |p| p.set_int(offset, val))
+    }
+
+    pub(crate) fn redo_set_string(
+        &self,
+        blk: &BlockId,
+        offset: usize,
+        val: String,
+        lsn: i64,
+    ) -> Result<(), String> {
+        self.redo(blk, lsn, |p| p.set_string(offset, &val))
+    }
+
+    pub(crate) fn redo_truncate(&self, filename: &String, numblocks: i32) -> Result<(), String> {
+        self.buffer_manager.discard_blocks(filename, numblocks)?;
+        if self.file_manager.len(filename)? > numblocks {
+            self.file_manager.truncate(filename, numblocks)?;
+        }
+        Ok(())
+    }
+
+    // Applies a logged change to a pinned block unless the page already reflects
+    // it. The page takes the record's LSN, so the change is not applied twice.
+    fn redo(
+        &self,
+        blk: &BlockId,
+        lsn: i64,
+        change: impl FnOnce(&mut Page) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut binding = self.mybuffers.lock().map_err(|_| "failed to get lock")?;
+        let buff_arc = binding
+            .get_buffer(blk)
+            .ok_or("you access to a buffer that does not exist")?;
+        let mut buff = buff_arc.lock().map_err(|_| "failed to get lock")?;
+        if buff.lsn() >= lsn {
+            return Ok(());
+        }
+        change(buff.contents())?;
+        buff.set_modified(self.txnum, lsn);
         Ok(())
     }
 
     pub fn size(&self, filename: String) -> Result<i32, String> {
-        let dummyblk = BlockId::new(filename.clone(), END_OF_FILE);
-        self.concurrent_manager
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .s_lock(&dummyblk)?;
+        let (taken, _) = self.begin_read(&BlockId::new(filename.clone(), END_OF_FILE))?;
         let ret = self
             .file_manager
             .len(&filename)?;
+        self.end_read(taken)?;
         Ok(ret)
     }
 
     pub fn append(&self, filename: String) -> Result<BlockId, String> {
         let dummyblk = BlockId::new(filename.clone(), END_OF_FILE);
-        self.concurrent_manager
-            .lock()
-            .map_err(|_| "failed to get lock")?
-            .x_lock(&dummyblk)?;
+        if !self.is_latched() {
+            self.concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .x_lock(&dummyblk)?;
+        }
         let ret = self
             .file_manager
             .append(&filename)?;
         Ok(ret)
     }
 
+    // The file is only shortened once the transaction commits, so a rollback can
+    // still restore records that were moved out of the truncated blocks.
+    pub fn truncate(&self, filename: String, numblocks: i32) -> Result<(), String> {
+        let dummyblk = BlockId::new(filename.clone(), END_OF_FILE);
+        let cm = self
+            .concurrent_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?;
+        cm.x_lock(&dummyblk)?;
+        let size = self.file_manager.len(&filename)?;
+        for blknum in numblocks..size {
+            cm.x_lock(&BlockId::new(filename.clone(), blknum))?;
+        }
+        self.pending_truncations
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .push((filename, numblocks));
+        Ok(())
+    }
+
     pub fn block_size(&self) -> Result<i32, String> {
         Ok(self
             .file_manager
@@ -274,12 +917,154 @@ impl Transaction {
     pub fn available_buffers(&self) -> Result<i32, String> {
         Ok(self
             .buffer_manager
+            .available())
+    }
+
+    fn pending_lengths(&self) -> Result<(usize, usize), String> {
+        Ok((
+            self.pending_truncations
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .len(),
+            self.retired_entries
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .len(),
+        ))
+    }
+
+    // Undoes, with logged updates, the changes made after `lsn` and drops the
+    // truncations and index entry removals queued since.
+    fn undo_since(&self, lsn: i64, pending: usize, retired: usize) -> Result<(), String> {
+        let changes = self
+            .recovery_manager
+            .as_ref()
+            .unwrap()
             .lock()
             .map_err(|_| "failed to get lock")?
-            .available())
+            .changes_since(lsn)?;
+        let tx = Arc::new(Mutex::new(self.clone()));
+        for rec in changes {
+            rec.compensate(tx.clone())?;
+        }
+        self.pending_truncations
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .truncate(pending);
+        self.retired_entries
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .truncate(retired);
+        Ok(())
+    }
+
+    // Hands the pending truncations to the version store if snapshots older
+    // than commit `seq` are running. Returns whether it did.
+    fn defer_truncations(&self, seq: u64) -> Result<bool, String> {
+        let mut pending = self
+            .pending_truncations
+            .lock()
+            .map_err(|_| "failed to get lock")?;
+        if pending.is_empty() || !self.version_store.older_snapshots(seq)? {
+            return Ok(false);
+        }
+        let lsn = self
+            .recovery_manager
+            .as_ref()
+            .unwrap()
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .latest_lsn()?;
+        let mut truncations = Vec::new();
+        for (filename, numblocks) in pending.drain(..) {
+            truncations.push(DeferredTruncation {
+                txnum: self.txnum,
+                size: self.file_manager.len(&filename)?,
+                filename,
+                numblocks,
+                lsn,
+            });
+        }
+        self.version_store.defer_truncations(seq, truncations)?;
+        Ok(true)
+    }
+
+    // Applies a truncation deferred by another transaction's commit, under
+    // locks of this one taken and released again for it. If the locks are not
+    // free at once, it is kept for a later commit; if the blocks were written
+    // to since, it is dropped.
+    fn apply_deferred_truncation(&self, seq: u64, t: DeferredTruncation) -> Result<(), String> {
+        let locked = {
+            let cm = self
+                .concurrent_manager
+                .lock()
+                .map_err(|_| "failed to get lock")?;
+            let mut locked = cm.try_x_lock(&BlockId::new(t.filename.clone(), END_OF_FILE))?;
+            for blknum in t.numblocks..t.size {
+                locked = locked && cm.try_x_lock(&BlockId::new(t.filename.clone(), blknum))?;
+            }
+            locked
+        };
+        let ret = match locked {
+            true => self.truncate_unchanged(&t),
+            false => self.version_store.defer_truncations(seq, vec![t]),
+        };
+        self.concurrent_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .release()?;
+        ret
+    }
+
+    fn truncate_unchanged(&self, t: &DeferredTruncation) -> Result<(), String> {
+        let mut unchanged = self.file_manager.len(&t.filename)? == t.size;
+        let mut blknum = t.numblocks;
+        while unchanged && blknum < t.size {
+            let blk = BlockId::new(t.filename.clone(), blknum);
+            self.pin(&blk)?;
+            let lsn = self
+                .mybuffers
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .get_buffer(&blk)
+                .ok_or("you access to a buffer that does not exist")?
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .lsn();
+            self.unpin(&blk)?;
+            unchanged = lsn <= t.lsn;
+            blknum += 1;
+        }
+        if unchanged {
+            self.recovery_manager
+                .as_ref()
+                .unwrap()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .log_deferred_truncation(t.txnum, &t.filename, t.numblocks)?;
+            self.redo_truncate(&t.filename, t.numblocks)?;
+        }
+        self.log_manager
+            .lock()
+            .map_err(|_| "failed to get lock")?
+            .tx_finished(t.txnum);
+        Ok(())
     }
 
-    fn next_tx_number() -> i32 {
-        NEXT_TX_NUM.fetch_add(1, Ordering::SeqCst) + 1
+    fn apply_truncations(&self) -> Result<(), String> {
+        let mut pending = self
+            .pending_truncations
+            .lock()
+            .map_err(|_| "failed to get lock")?;
+        for (filename, numblocks) in pending.drain(..) {
+            self.recovery_manager
+                .as_ref()
+                .unwrap()
+                .lock()
+                .map_err(|_| "failed to get lock")?
+                .log_truncation(&filename, numblocks)?;
+            self.redo_truncate(&filename, numblocks)?;
+        }
+        Ok(())
     }
 }